@@ -0,0 +1,503 @@
+use std::ops::Range;
+
+use crate::build_sheet::{format_column, BuildSheet, BuildSheetFormat, Wire, HOLE_PITCH_MM};
+use crate::layout::Layout;
+use crate::pdf::{PdfDocument, PdfFont, PdfPage, Rgb, MM_TO_PT};
+use crate::via::Via;
+
+// Printable assembly sheets
+//
+// The layout as a PDF with the board drawn at 1:1 scale, so that a sheet can be laid
+// on the board and the holes lined up. The pages are in the order the board is built
+// in:
+//
+// - Copper side: the strips and the strip cuts. The board is mirrored, as it's seen
+// when turned over for cutting, so column A is on the right.
+// - Wire side: the wires, in the colours from the build sheet, on the component side.
+// - Components: the footprints, with pin 1 marked with a square, and the names.
+// - Build list: the build sheet as plain text. See BuildSheet.
+//
+// Each board page has column letters and row numbers along the edges, the corner holes
+// marked, and a 100 mm ruler for checking that the sheet was printed at actual size.
+// The PDF asks the reader not to scale the pages, but not all readers honour that,
+// and the printer dialog may still be set to fit the page.
+//
+// A board that doesn't fit on a page is split into tiles, with TILE_OVERLAP columns and
+// rows in common between neighbouring tiles, so that the printed tiles can be lined up
+// on each other. The tiles are in rows, from the top left.
+
+// Points per hole.
+const HOLE_PITCH_PT: f32 = HOLE_PITCH_MM * MM_TO_PT;
+const PAGE_MARGIN_PT: f32 = 36.0;
+const HEADER_H_PT: f32 = 28.0;
+// Space for the column letters and row numbers around the board.
+const LABEL_W_PT: f32 = 12.0;
+const RULER_H_PT: f32 = 36.0;
+const RULER_MM: usize = 100;
+const TILE_OVERLAP: usize = 2;
+const LABEL_FONT_SIZE: f32 = 4.5;
+const BUILD_LIST_FONT_SIZE: f32 = 8.0;
+const BUILD_LIST_LINE_H_PT: f32 = 10.0;
+
+const OUTLINE_COLOR: Rgb = [0.6, 0.6, 0.6];
+const HOLE_COLOR: Rgb = [0.55, 0.55, 0.55];
+const STRIP_COLOR: Rgb = [0.93, 0.8, 0.68];
+const TEXT_COLOR: Rgb = [0.0, 0.0, 0.0];
+const WHITE: Rgb = [1.0, 1.0, 1.0];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PageSize {
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    // Width and height in points, in portrait.
+    pub fn size_pt(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0 * MM_TO_PT, 297.0 * MM_TO_PT),
+            PageSize::Letter => (612.0, 792.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum BoardSide {
+    Copper,
+    Wires,
+    Components,
+}
+
+impl BoardSide {
+    fn name(&self) -> &'static str {
+        match self {
+            BoardSide::Copper => "Copper side, mirrored: strip cuts",
+            BoardSide::Wires => "Component side: wires",
+            BoardSide::Components => "Component side: components",
+        }
+    }
+}
+
+// The columns and rows of the board that are printed on one page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Tile {
+    x_range: Range<usize>,
+    y_range: Range<usize>,
+}
+
+impl Tile {
+    fn contains(&self, via: Via) -> bool {
+        self.x_range.contains(&via.x) && self.y_range.contains(&via.y)
+    }
+}
+
+pub struct AssemblyPdf<'a> {
+    layout: &'a Layout,
+    build_sheet: BuildSheet,
+    title: String,
+    page_w: f32,
+    page_h: f32,
+    tile_vec: Vec<Tile>,
+}
+
+impl<'a> AssemblyPdf<'a> {
+    pub fn new(layout: &'a Layout, title: &str, page_size: PageSize) -> Self {
+        let (page_w, page_h) = page_size.size_pt();
+        let n_tile_columns = ((page_w - 2.0 * (PAGE_MARGIN_PT + LABEL_W_PT)) / HOLE_PITCH_PT) as usize;
+        let n_tile_rows =
+            ((page_h - 2.0 * (PAGE_MARGIN_PT + LABEL_W_PT) - HEADER_H_PT - RULER_H_PT) / HOLE_PITCH_PT) as usize;
+        let mut tile_vec = Vec::new();
+        for y_range in gen_tile_range_vec(layout.board.h, n_tile_rows) {
+            for x_range in gen_tile_range_vec(layout.board.w, n_tile_columns) {
+                tile_vec.push(Tile {
+                    x_range: x_range.clone(),
+                    y_range: y_range.clone(),
+                });
+            }
+        }
+        Self {
+            layout,
+            build_sheet: BuildSheet::new(layout),
+            title: title.to_string(),
+            page_w,
+            page_h,
+            tile_vec,
+        }
+    }
+
+    pub fn gen_document(&self) -> PdfDocument {
+        let build_list_line_vec: Vec<String> = self
+            .build_sheet
+            .format(BuildSheetFormat::Text)
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        let n_build_list_lines_per_page =
+            ((self.page_h - 2.0 * PAGE_MARGIN_PT - HEADER_H_PT) / BUILD_LIST_LINE_H_PT) as usize;
+        let build_list_chunk_vec: Vec<&[String]> = build_list_line_vec.chunks(n_build_list_lines_per_page).collect();
+        let n_pages = self.tile_vec.len() * 3 + build_list_chunk_vec.len();
+
+        let mut document = PdfDocument::new(&self.title);
+        for side in [BoardSide::Copper, BoardSide::Wires, BoardSide::Components] {
+            for (tile_idx, tile) in self.tile_vec.iter().enumerate() {
+                let mut page = PdfPage::new(self.page_w, self.page_h);
+                let tile_str = if self.tile_vec.len() > 1 {
+                    format!("Tile {} of {}. ", tile_idx + 1, self.tile_vec.len())
+                } else {
+                    String::new()
+                };
+                let subtitle = format!(
+                    "Columns {}-{}, rows {}-{}. {}Print at actual size (100%). Page {} of {}",
+                    format_column(tile.x_range.start),
+                    format_column(tile.x_range.end - 1),
+                    tile.y_range.start + 1,
+                    tile.y_range.end,
+                    tile_str,
+                    document.n_pages() + 1,
+                    n_pages
+                );
+                self.draw_header(&mut page, side.name(), &subtitle);
+                self.draw_board_page(&mut page, side, tile);
+                document.add_page(page);
+            }
+        }
+        for chunk in build_list_chunk_vec {
+            let mut page = PdfPage::new(self.page_w, self.page_h);
+            let subtitle = format!("Page {} of {}", document.n_pages() + 1, n_pages);
+            self.draw_header(&mut page, "Build list", &subtitle);
+            page.set_fill_color(TEXT_COLOR);
+            for (i, line) in chunk.iter().enumerate() {
+                let y = PAGE_MARGIN_PT + HEADER_H_PT + (i + 1) as f32 * BUILD_LIST_LINE_H_PT;
+                page.text(PAGE_MARGIN_PT, y, BUILD_LIST_FONT_SIZE, PdfFont::Courier, line);
+            }
+            document.add_page(page);
+        }
+        document
+    }
+
+    fn draw_header(&self, page: &mut PdfPage, heading: &str, subtitle: &str) {
+        page.set_fill_color(TEXT_COLOR);
+        page.text(
+            PAGE_MARGIN_PT,
+            PAGE_MARGIN_PT + 10.0,
+            12.0,
+            PdfFont::Helvetica,
+            &format!("{} - {}", self.title, heading),
+        );
+        page.text(PAGE_MARGIN_PT, PAGE_MARGIN_PT + 22.0, 8.0, PdfFont::Helvetica, subtitle);
+    }
+
+    fn draw_board_page(&self, page: &mut PdfPage, side: BoardSide, tile: &Tile) {
+        let is_mirrored = side == BoardSide::Copper;
+        let (board_x, board_y) = self.board_origin();
+        let tile_w = tile.x_range.len() as f32 * HOLE_PITCH_PT;
+        let tile_h = tile.y_range.len() as f32 * HOLE_PITCH_PT;
+
+        if side == BoardSide::Copper {
+            page.set_fill_color(STRIP_COLOR);
+            for x in tile.x_range.clone() {
+                let (cx, _) = self.hole_pos(tile, Via::new(x, tile.y_range.start), is_mirrored);
+                page.rect(cx - HOLE_PITCH_PT * 0.4, board_y, HOLE_PITCH_PT * 0.8, tile_h, true);
+            }
+        }
+        page.set_stroke_color(OUTLINE_COLOR);
+        page.set_line_width(0.5);
+        page.rect(board_x, board_y, tile_w, tile_h, false);
+        let hole_color = if is_mirrored { WHITE } else { HOLE_COLOR };
+        for y in tile.y_range.clone() {
+            for x in tile.x_range.clone() {
+                let (cx, cy) = self.hole_pos(tile, Via::new(x, y), is_mirrored);
+                page.dot(cx, cy, 1.6, hole_color);
+            }
+        }
+
+        match side {
+            BoardSide::Copper => self.draw_cuts(page, tile),
+            BoardSide::Wires => self.draw_wires(page, tile),
+            BoardSide::Components => self.draw_components(page, tile),
+        }
+        self.draw_labels(page, tile, is_mirrored);
+        self.draw_corner_markers(page, tile, is_mirrored);
+        self.draw_ruler(page);
+    }
+
+    // A cut separates a hole from the one above it, so it's drawn across the strip
+    // halfway between them.
+    fn draw_cuts(&self, page: &mut PdfPage, tile: &Tile) {
+        page.set_stroke_color(TEXT_COLOR);
+        page.set_line_width(1.5);
+        for cut in &self.build_sheet.cut_vec {
+            if cut.y == 0 || !tile.contains(*cut) || !tile.y_range.contains(&(cut.y - 1)) {
+                continue;
+            }
+            let (cx, cy) = self.hole_pos(tile, *cut, true);
+            let cy = cy - HOLE_PITCH_PT * 0.5;
+            page.line(cx - HOLE_PITCH_PT * 0.45, cy, cx + HOLE_PITCH_PT * 0.45, cy);
+        }
+    }
+
+    // The wires run along rows. A wire that continues on a neighbouring tile is drawn
+    // to the edge of this one. The wires are outlined so that white wires show up.
+    fn draw_wires(&self, page: &mut PdfPage, tile: &Tile) {
+        for wire in &self.build_sheet.wire_vec {
+            let Some((start, end)) = clip_wire(wire, tile) else {
+                continue;
+            };
+            let (x1, y1) = self.hole_pos(tile, start, false);
+            let (x2, y2) = self.hole_pos(tile, end, false);
+            for (color, line_w) in [(TEXT_COLOR, 2.6), (wire_colour_rgb(wire.colour), 1.8)] {
+                page.set_stroke_color(color);
+                page.set_line_width(line_w);
+                page.line(x1, y1, x2, y2);
+            }
+            page.set_fill_color(TEXT_COLOR);
+            for via in [wire.start, wire.end] {
+                if tile.contains(via) {
+                    let (cx, cy) = self.hole_pos(tile, via, false);
+                    page.circle(cx, cy, 1.6, true);
+                }
+            }
+        }
+    }
+
+    // The footprint is the rectangle around the pins. Names are only printed on the
+    // tile that has the middle of the footprint.
+    fn draw_components(&self, page: &mut PdfPage, tile: &Tile) {
+        let circuit = &self.layout.circuit;
+        for component in &self.build_sheet.component_vec {
+            let pin_vec = circuit.calc_component_pins(&component.name);
+            let x_min = pin_vec.iter().map(|pin| pin.x).min().unwrap();
+            let x_max = pin_vec.iter().map(|pin| pin.x).max().unwrap();
+            let y_min = pin_vec.iter().map(|pin| pin.y).min().unwrap();
+            let y_max = pin_vec.iter().map(|pin| pin.y).max().unwrap();
+            let x_range = x_min.max(tile.x_range.start)..(x_max + 1).min(tile.x_range.end);
+            let y_range = y_min.max(tile.y_range.start)..(y_max + 1).min(tile.y_range.end);
+            if x_range.is_empty() || y_range.is_empty() {
+                continue;
+            }
+            let (x1, y1) = self.hole_pos(tile, Via::new(x_range.start, y_range.start), false);
+            let (x2, y2) = self.hole_pos(tile, Via::new(x_range.end - 1, y_range.end - 1), false);
+            let inset = HOLE_PITCH_PT * 0.4;
+            page.set_stroke_color(TEXT_COLOR);
+            page.set_line_width(0.6);
+            page.rect(
+                x1 - inset,
+                y1 - inset,
+                x2 - x1 + 2.0 * inset,
+                y2 - y1 + 2.0 * inset,
+                false,
+            );
+            page.set_line_width(0.5);
+            for (pin_idx, pin) in pin_vec.iter().enumerate() {
+                if !tile.contains(*pin) {
+                    continue;
+                }
+                let (cx, cy) = self.hole_pos(tile, *pin, false);
+                if pin_idx == 0 {
+                    page.rect(cx - 1.8, cy - 1.8, 3.6, 3.6, false);
+                } else {
+                    page.circle(cx, cy, 1.8, false);
+                }
+            }
+            let center = Via::new((x_min + x_max) / 2, (y_min + y_max) / 2);
+            if tile.contains(center) {
+                let (cx, _) = self.hole_pos(tile, center, false);
+                let cy = (y1 + y2) / 2.0;
+                let font_size = 5.0;
+                page.set_fill_color(TEXT_COLOR);
+                page.text(
+                    cx - PdfPage::text_width(font_size, &component.name) / 2.0,
+                    cy + font_size * 0.35,
+                    font_size,
+                    PdfFont::Courier,
+                    &component.name,
+                );
+            }
+        }
+    }
+
+    // Column letters above and below the board, and row numbers on both sides.
+    fn draw_labels(&self, page: &mut PdfPage, tile: &Tile, is_mirrored: bool) {
+        let (board_x, board_y) = self.board_origin();
+        let tile_w = tile.x_range.len() as f32 * HOLE_PITCH_PT;
+        let tile_h = tile.y_range.len() as f32 * HOLE_PITCH_PT;
+        page.set_fill_color(TEXT_COLOR);
+        for x in tile.x_range.clone() {
+            let column = format_column(x);
+            let (cx, _) = self.hole_pos(tile, Via::new(x, tile.y_range.start), is_mirrored);
+            let label_x = cx - PdfPage::text_width(LABEL_FONT_SIZE, &column) / 2.0;
+            page.text(label_x, board_y - 3.0, LABEL_FONT_SIZE, PdfFont::Courier, &column);
+            page.text(
+                label_x,
+                board_y + tile_h + 3.0 + LABEL_FONT_SIZE,
+                LABEL_FONT_SIZE,
+                PdfFont::Courier,
+                &column,
+            );
+        }
+        for y in tile.y_range.clone() {
+            let row = (y + 1).to_string();
+            let (_, cy) = self.hole_pos(tile, Via::new(tile.x_range.start, y), is_mirrored);
+            let label_y = cy + LABEL_FONT_SIZE * 0.35;
+            page.text(
+                board_x - 3.0 - PdfPage::text_width(LABEL_FONT_SIZE, &row),
+                label_y,
+                LABEL_FONT_SIZE,
+                PdfFont::Courier,
+                &row,
+            );
+            page.text(board_x + tile_w + 3.0, label_y, LABEL_FONT_SIZE, PdfFont::Courier, &row);
+        }
+    }
+
+    // Circles with crosshairs on the corner holes of the tile, for lining up the sheet
+    // with the board, or with the neighbouring tiles.
+    fn draw_corner_markers(&self, page: &mut PdfPage, tile: &Tile, is_mirrored: bool) {
+        page.set_stroke_color(TEXT_COLOR);
+        page.set_line_width(0.4);
+        for x in [tile.x_range.start, tile.x_range.end - 1] {
+            for y in [tile.y_range.start, tile.y_range.end - 1] {
+                let (cx, cy) = self.hole_pos(tile, Via::new(x, y), is_mirrored);
+                let r = HOLE_PITCH_PT * 0.45;
+                page.circle(cx, cy, r, false);
+                page.line(cx - r, cy, cx + r, cy);
+                page.line(cx, cy - r, cx, cy + r);
+            }
+        }
+    }
+
+    // Ticks every mm, longer every 5 and 10 mm, and labels every 10 mm.
+    fn draw_ruler(&self, page: &mut PdfPage) {
+        let x0 = PAGE_MARGIN_PT;
+        let y0 = self.page_h - PAGE_MARGIN_PT - 16.0;
+        page.set_stroke_color(TEXT_COLOR);
+        page.set_line_width(0.3);
+        page.line(x0, y0, x0 + RULER_MM as f32 * MM_TO_PT, y0);
+        page.set_fill_color(TEXT_COLOR);
+        for mm in 0..=RULER_MM {
+            let x = x0 + mm as f32 * MM_TO_PT;
+            let tick_h = match mm {
+                _ if mm % 10 == 0 => 8.0,
+                _ if mm % 5 == 0 => 5.0,
+                _ => 3.0,
+            };
+            page.line(x, y0, x, y0 - tick_h);
+            if mm % 10 == 0 {
+                let label = (mm / 10).to_string();
+                page.text(
+                    x - PdfPage::text_width(LABEL_FONT_SIZE, &label) / 2.0,
+                    y0 + 6.0,
+                    LABEL_FONT_SIZE,
+                    PdfFont::Courier,
+                    &label,
+                );
+            }
+        }
+        page.text(
+            x0,
+            y0 + 15.0,
+            7.0,
+            PdfFont::Helvetica,
+            "This ruler is 10 cm long, and 10 holes are 25.4 mm. If not, print again at actual size (100%).",
+        );
+    }
+
+    // The top left of the board area, outside of the labels.
+    fn board_origin(&self) -> (f32, f32) {
+        (PAGE_MARGIN_PT + LABEL_W_PT, PAGE_MARGIN_PT + HEADER_H_PT + LABEL_W_PT)
+    }
+
+    // The middle of the hole on the page.
+    fn hole_pos(&self, tile: &Tile, via: Via, is_mirrored: bool) -> (f32, f32) {
+        let (board_x, board_y) = self.board_origin();
+        let column_idx = if is_mirrored {
+            tile.x_range.end - 1 - via.x
+        } else {
+            via.x - tile.x_range.start
+        };
+        (
+            board_x + (column_idx as f32 + 0.5) * HOLE_PITCH_PT,
+            board_y + ((via.y - tile.y_range.start) as f32 + 0.5) * HOLE_PITCH_PT,
+        )
+    }
+}
+
+// Split n columns or rows into ranges of at most n_per_tile, where neighbouring ranges
+// share TILE_OVERLAP.
+fn gen_tile_range_vec(n: usize, n_per_tile: usize) -> Vec<Range<usize>> {
+    let step = n_per_tile.saturating_sub(TILE_OVERLAP).max(1);
+    let mut range_vec = Vec::new();
+    let mut start = 0;
+    loop {
+        let end = (start + n_per_tile).min(n);
+        range_vec.push(start..end);
+        if end == n {
+            break;
+        }
+        start += step;
+    }
+    range_vec
+}
+
+// The part of the wire that is on the tile.
+fn clip_wire(wire: &Wire, tile: &Tile) -> Option<(Via, Via)> {
+    if !tile.y_range.contains(&wire.start.y) {
+        return None;
+    }
+    let x_min = wire.start.x.min(wire.end.x).max(tile.x_range.start);
+    let x_max = wire.start.x.max(wire.end.x).min(tile.x_range.end - 1);
+    (x_min <= x_max).then(|| (Via::new(x_min, wire.start.y), Via::new(x_max, wire.start.y)))
+}
+
+// The build sheet wire colours. White wires are drawn in light grey, with the outline.
+fn wire_colour_rgb(colour: &str) -> Rgb {
+    match colour {
+        "black" => [0.1, 0.1, 0.1],
+        "red" => [0.85, 0.1, 0.1],
+        "yellow" => [0.95, 0.85, 0.1],
+        "green" => [0.1, 0.65, 0.2],
+        "blue" => [0.15, 0.35, 0.9],
+        "orange" => [1.0, 0.55, 0.0],
+        "white" => [0.95, 0.95, 0.95],
+        "violet" => [0.55, 0.25, 0.8],
+        "grey" => [0.55, 0.55, 0.55],
+        "brown" => [0.55, 0.3, 0.1],
+        _ => [0.5, 0.5, 0.5],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    fn tiles_cover_the_board_with_overlap() {
+        assert_eq!(gen_tile_range_vec(40, 69), vec![0..40]);
+        assert_eq!(gen_tile_range_vec(69, 69), vec![0..69]);
+        assert_eq!(gen_tile_range_vec(100, 40), vec![0..40, 38..78, 76..100]);
+    }
+
+    #[test]
+    fn large_boards_are_printed_on_several_pages_at_actual_size() {
+        let mut layout = Layout::new();
+        layout.board = Board::new(60, 40);
+        let assembly_pdf = AssemblyPdf::new(&layout, "Test", PageSize::A4);
+        assert_eq!(assembly_pdf.tile_vec.len(), 1);
+        // One page per side, and the build list.
+        assert_eq!(assembly_pdf.gen_document().n_pages(), 4);
+        let (x1, _) = assembly_pdf.hole_pos(&assembly_pdf.tile_vec[0], Via::new(0, 0), false);
+        let (x2, _) = assembly_pdf.hole_pos(&assembly_pdf.tile_vec[0], Via::new(10, 0), false);
+        assert!(((x2 - x1) / MM_TO_PT - 25.4).abs() < 1e-3);
+
+        layout.board = Board::new(100, 120);
+        let assembly_pdf = AssemblyPdf::new(&layout, "Test", PageSize::A4);
+        assert_eq!(assembly_pdf.tile_vec.len(), 4);
+        assert_eq!(assembly_pdf.gen_document().n_pages(), 4 * 3 + 1);
+        // Mirrored, column A is on the right.
+        let tile = &assembly_pdf.tile_vec[0];
+        let (x1, _) = assembly_pdf.hole_pos(tile, Via::new(0, 0), true);
+        let (x2, _) = assembly_pdf.hole_pos(tile, Via::new(1, 0), true);
+        assert!(x1 > x2);
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::layout::Layout;
+use crate::via::Via;
+
+// Build sheet
+//
+// A list of everything that has to be done on the board to build a layout, in the
+// order it's best done in:
+//
+// - Strip cuts. They are easiest to do before there is any solder on the strips. See
+// the notes on cutting in the README.
+// - Wires, shortest first, so that the longer wires can be laid over the shorter ones.
+// - Components.
+//
+// Positions are given as a column letter and a row number, like on a spreadsheet, as
+// seen from the component side, with the strips running vertically. Column A is the
+// leftmost column and row 1 is the top row. On the copper side, column A is the
+// rightmost column. A strip cut separates a hole from the one above it, so it's given
+// as the two holes it goes between.
+//
+// Each net gets a wire colour. Ground nets are black, power nets are red, and the
+// other nets get colours from WIRE_COLOUR_VEC, in the order their first wire appears
+// on the sheet. A net is a ground or power net if one of its pins is on a component
+// with a name in GROUND_COMPONENT_NAME_VEC or SUPPLY_COMPONENT_NAME_VEC, and a net is
+// also a power net if it has the power attribute. See NetAttributes.
+
+// The distance between neighbouring holes on a stripboard.
+pub const HOLE_PITCH_MM: f32 = 2.54;
+
+const GROUND_WIRE_COLOUR: &str = "black";
+const POWER_WIRE_COLOUR: &str = "red";
+const WIRE_COLOUR_VEC: [&str; 8] = ["yellow", "green", "blue", "orange", "white", "violet", "grey", "brown"];
+const GROUND_COMPONENT_NAME_VEC: [&str; 4] = ["gnd", "ground", "vss", "0v"];
+const SUPPLY_COMPONENT_NAME_VEC: [&str; 4] = ["vcc", "vdd", "vin", "vbat"];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BuildSheetFormat {
+    Markdown,
+    Csv,
+    Text,
+}
+
+impl BuildSheetFormat {
+    // By the extension of the file name: .md for Markdown, .csv for CSV and plain text
+    // for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("md") => BuildSheetFormat::Markdown,
+            Some("csv") => BuildSheetFormat::Csv,
+            _ => BuildSheetFormat::Text,
+        }
+    }
+}
+
+// A wire on the component side, along a row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Wire {
+    pub start: Via,
+    pub end: Via,
+    pub colour: &'static str,
+}
+
+impl Wire {
+    // The number of hole pitches between the ends.
+    pub fn len(&self) -> usize {
+        self.start.x.abs_diff(self.end.x) + self.start.y.abs_diff(self.end.y)
+    }
+
+    pub fn len_mm(&self) -> f32 {
+        self.len() as f32 * HOLE_PITCH_MM
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ComponentPlacement {
+    pub name: String,
+    pub package_name: String,
+    // The position of pin 1.
+    pub pin0_pos: Via,
+}
+
+pub struct BuildSheet {
+    pub board_w: usize,
+    pub board_h: usize,
+    // In assembly order.
+    pub cut_vec: Vec<Via>,
+    pub wire_vec: Vec<Wire>,
+    pub component_vec: Vec<ComponentPlacement>,
+}
+
+impl BuildSheet {
+    pub fn new(layout: &Layout) -> Self {
+        Self {
+            board_w: layout.board.w,
+            board_h: layout.board.h,
+            cut_vec: gen_cut_vec(layout),
+            wire_vec: gen_wire_vec(layout),
+            component_vec: gen_component_vec(layout),
+        }
+    }
+
+    pub fn format(&self, format: BuildSheetFormat) -> String {
+        match format {
+            BuildSheetFormat::Markdown => self.format_markdown(),
+            BuildSheetFormat::Csv => self.format_csv(),
+            BuildSheetFormat::Text => self.format_text(),
+        }
+    }
+
+    fn format_markdown(&self) -> String {
+        let mut s = String::new();
+        s += &format!(
+            "# Build sheet\n\nBoard: {} columns, {} rows\n\n",
+            self.board_w, self.board_h
+        );
+        s += &format!("## Strip cuts ({})\n\n", self.cut_vec.len());
+        s += "| Step | Between | And |\n|---:|---|---|\n";
+        let mut step = 1;
+        for cut in &self.cut_vec {
+            s += &format!("| {} | {} | {} |\n", step, format_pos(above(*cut)), format_pos(*cut));
+            step += 1;
+        }
+        s += &format!("\n## Wires ({})\n\n", self.wire_vec.len());
+        s += "| Step | From | To | Length (holes) | Length (mm) | Colour |\n|---:|---|---|---:|---:|---|\n";
+        for wire in &self.wire_vec {
+            s += &format!(
+                "| {} | {} | {} | {} | {:.1} | {} |\n",
+                step,
+                format_pos(wire.start),
+                format_pos(wire.end),
+                wire.len(),
+                wire.len_mm(),
+                wire.colour
+            );
+            step += 1;
+        }
+        s += &format!("\n## Components ({})\n\n", self.component_vec.len());
+        s += "| Step | Component | Package | Pin 1 |\n|---:|---|---|---|\n";
+        for component in &self.component_vec {
+            s += &format!(
+                "| {} | {} | {} | {} |\n",
+                step,
+                component.name,
+                component.package_name,
+                format_pos(component.pin0_pos)
+            );
+            step += 1;
+        }
+        s
+    }
+
+    // A single table, with the columns that don't apply to an item left empty.
+    fn format_csv(&self) -> String {
+        let mut s = String::from("step,item,name,package,from,to,length_holes,length_mm,colour\n");
+        let mut step = 1;
+        for cut in &self.cut_vec {
+            s += &format!("{},cut,,,{},{},,,\n", step, format_pos(above(*cut)), format_pos(*cut));
+            step += 1;
+        }
+        for wire in &self.wire_vec {
+            s += &format!(
+                "{},wire,,,{},{},{},{:.1},{}\n",
+                step,
+                format_pos(wire.start),
+                format_pos(wire.end),
+                wire.len(),
+                wire.len_mm(),
+                wire.colour
+            );
+            step += 1;
+        }
+        for component in &self.component_vec {
+            s += &format!(
+                "{},component,{},{},{},,,,\n",
+                step,
+                component.name,
+                component.package_name,
+                format_pos(component.pin0_pos)
+            );
+            step += 1;
+        }
+        s
+    }
+
+    fn format_text(&self) -> String {
+        let mut s = format!("Build sheet\nBoard: {} columns, {} rows\n", self.board_w, self.board_h);
+        s += &format!("\nStrip cuts ({})\n", self.cut_vec.len());
+        let mut step = 1;
+        for cut in &self.cut_vec {
+            s += &format!(
+                "{:4}. Cut between {} and {}\n",
+                step,
+                format_pos(above(*cut)),
+                format_pos(*cut)
+            );
+            step += 1;
+        }
+        s += &format!("\nWires ({})\n", self.wire_vec.len());
+        for wire in &self.wire_vec {
+            s += &format!(
+                "{:4}. {} - {}, {} holes ({:.1} mm), {}\n",
+                step,
+                format_pos(wire.start),
+                format_pos(wire.end),
+                wire.len(),
+                wire.len_mm(),
+                wire.colour
+            );
+            step += 1;
+        }
+        s += &format!("\nComponents ({})\n", self.component_vec.len());
+        for component in &self.component_vec {
+            s += &format!(
+                "{:4}. {} ({}), pin 1 at {}\n",
+                step,
+                component.name,
+                component.package_name,
+                format_pos(component.pin0_pos)
+            );
+            step += 1;
+        }
+        s
+    }
+}
+
+// Column letter and row number, e.g. C12.
+pub fn format_pos(via: Via) -> String {
+    format!("{}{}", format_column(via.x), via.y + 1)
+}
+
+// After Z, the columns are AA, AB, etc.
+pub fn format_column(x: usize) -> String {
+    let mut column = String::new();
+    let mut x = x + 1;
+    while x > 0 {
+        x -= 1;
+        column.insert(0, (b'A' + (x % 26) as u8) as char);
+        x /= 26;
+    }
+    column
+}
+
+// The hole above the strip cut. There are no cuts in the top row.
+fn above(cut: Via) -> Via {
+    Via::new(cut.x, cut.y.saturating_sub(1))
+}
+
+// Strip by strip, from the top.
+fn gen_cut_vec(layout: &Layout) -> Vec<Via> {
+    let mut cut_vec = layout.strip_cut_vec.clone();
+    cut_vec.sort_by_key(|via| (via.x, via.y));
+    cut_vec
+}
+
+// The wire layer sections of the routes. A route that makes a wire jump follows a
+// wire that is already on the board, so the sections that are within another wire
+// in the same row are left out.
+fn gen_wire_vec(layout: &Layout) -> Vec<Wire> {
+    let connection_net_idx_vec = layout.circuit.gen_connection_net_idx_vec();
+    let mut net_wire_vec: Vec<(usize, Via, Via)> = Vec::new();
+    for (route_idx, route_section_vec) in layout.route_vec.iter().enumerate() {
+        let net_idx = layout
+            .route_connection_idx_vec
+            .get(route_idx)
+            .and_then(|&connection_idx| connection_net_idx_vec.get(connection_idx))
+            .copied()
+            .unwrap_or(usize::MAX);
+        for section in route_section_vec {
+            if section.start.is_wire_layer && section.end.is_wire_layer && section.start.via != section.end.via {
+                let (start, end) = if section.start.via.x <= section.end.via.x {
+                    (section.start.via, section.end.via)
+                } else {
+                    (section.end.via, section.start.via)
+                };
+                net_wire_vec.push((net_idx, start, end));
+            }
+        }
+    }
+    let is_within = |(_, start, end): &(usize, Via, Via), (_, other_start, other_end): &(usize, Via, Via)| {
+        start.y == other_start.y && start.x >= other_start.x && end.x <= other_end.x
+    };
+    let mut kept_vec: Vec<(usize, Via, Via)> = Vec::new();
+    for (i, wire) in net_wire_vec.iter().enumerate() {
+        // Of identical wires, only the first is kept.
+        let is_covered = net_wire_vec
+            .iter()
+            .enumerate()
+            .any(|(j, other)| j != i && is_within(wire, other) && (!is_within(other, wire) || j < i));
+        if !is_covered {
+            kept_vec.push(*wire);
+        }
+    }
+    kept_vec.sort_by_key(|(_, start, end)| (start.x.abs_diff(end.x), start.y, start.x));
+
+    let net_colour_map = gen_net_colour_map(layout);
+    let mut other_colour_map: HashMap<usize, &'static str> = HashMap::new();
+    kept_vec
+        .into_iter()
+        .map(|(net_idx, start, end)| {
+            let colour = match net_colour_map.get(&net_idx) {
+                Some(colour) => colour,
+                None => {
+                    let n_colours = other_colour_map.len();
+                    *other_colour_map
+                        .entry(net_idx)
+                        .or_insert(WIRE_COLOUR_VEC[n_colours % WIRE_COLOUR_VEC.len()])
+                }
+            };
+            Wire { start, end, colour }
+        })
+        .collect()
+}
+
+// The colours of the ground and power nets, by net index.
+fn gen_net_colour_map(layout: &Layout) -> HashMap<usize, &'static str> {
+    let circuit = &layout.circuit;
+    let connection_net_idx_vec = circuit.gen_connection_net_idx_vec();
+    let connection_net_attributes_vec = circuit.gen_connection_net_attributes_vec();
+    let is_named = |name: &str, name_vec: &[&str]| name_vec.contains(&name.to_lowercase().as_str());
+    let mut net_colour_map = HashMap::new();
+    for (connection_idx, connection) in circuit.connection_vec.iter().enumerate() {
+        let name_vec = [&connection.start.component_name, &connection.end.component_name];
+        let colour = if name_vec.iter().any(|name| is_named(name, &GROUND_COMPONENT_NAME_VEC)) {
+            GROUND_WIRE_COLOUR
+        } else if connection_net_attributes_vec[connection_idx].is_power
+            || name_vec.iter().any(|name| is_named(name, &SUPPLY_COMPONENT_NAME_VEC))
+        {
+            POWER_WIRE_COLOUR
+        } else {
+            continue;
+        };
+        // Ground wins if a net is both.
+        let net_colour = net_colour_map
+            .entry(connection_net_idx_vec[connection_idx])
+            .or_insert(colour);
+        if colour == GROUND_WIRE_COLOUR {
+            *net_colour = colour;
+        }
+    }
+    net_colour_map
+}
+
+// Sorted by name.
+fn gen_component_vec(layout: &Layout) -> Vec<ComponentPlacement> {
+    let mut component_vec: Vec<ComponentPlacement> = layout
+        .circuit
+        .component_name_to_component_map
+        .iter()
+        .map(|(name, component)| ComponentPlacement {
+            name: name.clone(),
+            package_name: component.package_name.clone(),
+            pin0_pos: layout.circuit.calc_component_pins(name)[0],
+        })
+        .collect();
+    component_vec.sort_by(|a, b| a.name.cmp(&b.name));
+    component_vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::circuit::{Component, Connection, ConnectionPoint};
+    use crate::via::{LayerStartEndVia, LayerVia, OffsetVia};
+
+    #[test]
+    fn positions_are_column_letters_and_row_numbers() {
+        assert_eq!(format_pos(Via::new(0, 0)), "A1");
+        assert_eq!(format_pos(Via::new(2, 11)), "C12");
+        assert_eq!(format_pos(Via::new(25, 0)), "Z1");
+        assert_eq!(format_pos(Via::new(26, 0)), "AA1");
+        assert_eq!(format_pos(Via::new(27, 4)), "AB5");
+    }
+
+    // Two nets, each with a wire along row 2, and a route in the second net that
+    // follows part of the second wire.
+    fn wire_layout() -> Layout {
+        let mut layout = Layout::new();
+        layout.board = Board::new(10, 10);
+        let circuit = &mut layout.circuit;
+        circuit
+            .package_to_pos_map
+            .insert("pin".to_string(), vec![OffsetVia::new(0, 0)]);
+        for (name, x, y) in [("gnd", 0, 3), ("a", 2, 3), ("b", 3, 3), ("c", 8, 3), ("d", 6, 5)] {
+            circuit
+                .component_name_to_component_map
+                .insert(name.to_string(), Component::new("pin".to_string(), Via::new(x, y)));
+        }
+        for (start, end) in [("gnd", "a"), ("b", "c"), ("c", "d")] {
+            circuit.connection_vec.push(Connection::new(
+                ConnectionPoint::new(start.to_string(), 0),
+                ConnectionPoint::new(end.to_string(), 0),
+            ));
+        }
+        let wire = |x1, x2| {
+            vec![LayerStartEndVia::from_layer_vias(
+                LayerVia::from_via(Via::new(x1, 2), true),
+                LayerVia::from_via(Via::new(x2, 2), true),
+            )]
+        };
+        layout.route_vec = vec![wire(0, 2), wire(8, 3), wire(4, 6)];
+        layout.route_connection_idx_vec = vec![0, 1, 2];
+        layout.strip_cut_vec = vec![Via::new(3, 5), Via::new(1, 2), Via::new(1, 1)];
+        layout
+    }
+
+    #[test]
+    fn wires_are_listed_once_shortest_first_with_a_colour_per_net() {
+        let build_sheet = BuildSheet::new(&wire_layout());
+        let wire = |x1, x2, colour| Wire {
+            start: Via::new(x1, 2),
+            end: Via::new(x2, 2),
+            colour,
+        };
+        assert_eq!(build_sheet.wire_vec, vec![wire(0, 2, "black"), wire(3, 8, "yellow")]);
+        assert_eq!(
+            build_sheet.cut_vec,
+            vec![Via::new(1, 1), Via::new(1, 2), Via::new(3, 5)]
+        );
+    }
+
+    #[test]
+    fn cuts_come_first_then_wires_then_components() {
+        let text = BuildSheet::new(&wire_layout()).format(BuildSheetFormat::Text);
+        assert!(text.contains("   3. Cut between D5 and D6\n"));
+        assert!(text.contains("   4. A3 - C3, 2 holes (5.1 mm), black\n"));
+        assert!(text.contains("   6. a (pin), pin 1 at C4\n"));
+        let csv = BuildSheet::new(&wire_layout()).format(BuildSheetFormat::Csv);
+        assert_eq!(csv.lines().nth(5), Some("5,wire,,,D3,I3,5,12.7,yellow"));
+    }
+}
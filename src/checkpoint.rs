@@ -0,0 +1,617 @@
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::str::FromStr;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::layout::{CostBreakdown, Layout};
+use crate::nets::Nets;
+use crate::ordering_search::OrderingSearch;
+use crate::util::Fnv1aHasher;
+use crate::via::{LayerStartEndVia, LayerVia, Via};
+
+// Checkpoints, so that long searches can be resumed after the app has exited.
+//
+// A checkpoint holds the state of the ordering search and the routes of the best layout
+// found so far. For the GA, the state is the population with the fitness of each
+// organism, the generation count, the state of all the random generators and the
+// fitness cache. A checkpoint can only be resumed with the same ordering search that
+// it was saved with.
+//
+// The file is line based text, like the .circuit files. Each line is a key followed
+// by values separated by spaces, and the lines are read back in the order they were
+// written. Empty lines and lines starting with "#" are ignored.
+//
+// The circuit hash covers everything that affects the fitness of an ordering: the
+// board, the circuit and the costs. A checkpoint can only be resumed for a circuit
+// with the same hash. The search algorithm is not included, since UCS and A* find
+// routes with the same cost. If the hash differs, the circuit has been edited since
+// the checkpoint was saved, and the search is warm-started instead (for the GA, from
+// the saved population).
+// The best layout is then dropped, and all the organisms are evaluated again.
+//
+// Orderings that had been handed out to the router threads but were not released
+// when the checkpoint was saved are routed again after resuming.
+
+const CHECKPOINT_VERSION: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoadedCheckpoint {
+    Resumed,
+    WarmStarted,
+}
+
+// A hash that doesn't match just causes a warm start instead of a resume. See
+// Fnv1aHasher.
+pub fn calc_circuit_hash(layout: &Layout) -> u64 {
+    let mut hasher = Fnv1aHasher::new();
+    layout.board.w.hash(&mut hasher);
+    layout.board.h.hash(&mut hasher);
+    layout.settings.wire_cost.hash(&mut hasher);
+    layout.settings.strip_cost.hash(&mut hasher);
+    layout.settings.via_cost.hash(&mut hasher);
+    layout.settings.cut_cost.hash(&mut hasher);
+    layout.settings.area_cost.hash(&mut hasher);
+    layout.settings.length_mismatch_cost.hash(&mut hasher);
+    layout.settings.rip_up_passes.hash(&mut hasher);
+    layout.circuit.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn save_checkpoint(
+    path: &Path,
+    input_layout: &Layout,
+    ordering_search: &dyn OrderingSearch,
+    best_layout: &Layout,
+) -> Result<(), String> {
+    let mut writer = CheckpointWriter::new();
+    writer.write("version", vec![CHECKPOINT_VERSION.to_string()]);
+    writer.write("circuit_hash", vec![format!("{:016x}", calc_circuit_hash(input_layout))]);
+    write_layout(&mut writer, best_layout);
+    writer.write("ordering_search", vec![ordering_search.get_strategy().name().to_string()]);
+    ordering_search.write_checkpoint(&mut writer);
+    writer.save(path)
+}
+
+// Save a single layout, e.g. one picked from the Pareto front. The file has the
+// same format as a checkpoint without the ordering search.
+pub fn save_layout(path: &Path, layout: &Layout) -> Result<(), String> {
+    let mut writer = CheckpointWriter::new();
+    writer.write("version", vec![CHECKPOINT_VERSION.to_string()]);
+    writer.write("circuit_hash", vec![format!("{:016x}", calc_circuit_hash(layout))]);
+    write_layout(&mut writer, layout);
+    writer.save(path)
+}
+
+// Restore the ordering search and the best layout. On error, neither is changed. The best layout
+// is rebuilt on top of a copy of the input layout, so that the router threads see it
+// as based on the current input layout.
+pub fn load_checkpoint(
+    path: &Path,
+    input_layout: &Layout,
+    ordering_search: &mut dyn OrderingSearch,
+    best_layout: &mut Layout,
+) -> Result<LoadedCheckpoint, String> {
+    let mut reader = CheckpointReader::load(path)?;
+    let version: usize = reader.read("version")?.get(0)?;
+    if version != CHECKPOINT_VERSION {
+        return Err(format!("Unsupported checkpoint version: {}", version));
+    }
+    let circuit_hash = reader.read("circuit_hash")?.get::<String>(0)?;
+    if circuit_hash != format!("{:016x}", calc_circuit_hash(input_layout)) {
+        for key in ["layout", "route_status", "route", "strip_cuts"] {
+            reader.skip(key);
+        }
+        read_ordering_search(&mut reader, ordering_search)?;
+        ordering_search.warm_start(&input_layout.circuit);
+        return Ok(LoadedCheckpoint::WarmStarted);
+    }
+    let mut new_best_layout = input_layout.clone();
+    read_layout(&mut reader, &mut new_best_layout)?;
+    read_ordering_search(&mut reader, ordering_search)?;
+    *best_layout = new_best_layout;
+    Ok(LoadedCheckpoint::Resumed)
+}
+
+fn read_ordering_search(reader: &mut CheckpointReader, ordering_search: &mut dyn OrderingSearch) -> Result<(), String> {
+    let line = reader.read("ordering_search")?;
+    let name: String = line.get(0)?;
+    let strategy = ordering_search.get_strategy();
+    if name != strategy.name() {
+        return Err(format!(
+            "The checkpoint was saved with the {} ordering search, not {}",
+            name,
+            strategy.name()
+        ));
+    }
+    ordering_search.read_checkpoint(reader)
+}
+
+//
+// Layout
+//
+
+// The route counts and the routes with their cost breakdowns and connections. The
+// totals and the nets are recalculated when reading.
+fn write_layout(writer: &mut CheckpointWriter, layout: &Layout) {
+    writer.write(
+        "layout",
+        vec![
+            layout.n_completed_routes.to_string(),
+            layout.n_failed_routes.to_string(),
+            layout.route_vec.len().to_string(),
+        ],
+    );
+    writer.write(
+        "route_status",
+        layout.route_status_vec.iter().map(|&is_routed| (is_routed as usize).to_string()).collect(),
+    );
+    for (route_idx, route_section_vec) in layout.route_vec.iter().enumerate() {
+        let c = layout.route_cost_vec.get(route_idx).copied().unwrap_or_default();
+        let connection_idx = layout.route_connection_idx_vec.get(route_idx).copied().unwrap_or_default();
+        let value_vec = [c.strip_len, c.wire_len, c.n_wires, c.n_vias, c.n_reused, c.cost, connection_idx];
+        let mut value_vec: Vec<String> = value_vec
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        value_vec.extend(route_section_vec.iter().map(format_section));
+        writer.write("route", value_vec);
+    }
+    writer.write("strip_cuts", layout.strip_cut_vec.iter().map(|via| format!("{},{}", via.x, via.y)).collect());
+}
+
+fn read_layout(reader: &mut CheckpointReader, layout: &mut Layout) -> Result<(), String> {
+    let line = reader.read("layout")?;
+    let n_completed_routes: usize = line.get(0)?;
+    let n_failed_routes: usize = line.get(1)?;
+    let n_routes: usize = line.get(2)?;
+    let n_connections = layout.circuit.connection_vec.len();
+    let line = reader.read("route_status")?;
+    let route_status_vec: Vec<usize> = line.get_vec(0)?;
+    // Empty if the layout has not been routed.
+    if !route_status_vec.is_empty() && route_status_vec.len() != n_connections {
+        return Err(line.error("route status count does not match the circuit"));
+    }
+    if route_status_vec.iter().any(|&v| v > 1) {
+        return Err(line.error("invalid route status"));
+    }
+    let mut route_vec = Vec::new();
+    let mut route_cost_vec = Vec::new();
+    let mut route_connection_idx_vec = Vec::new();
+    let mut cost_breakdown = CostBreakdown::new();
+    for _ in 0..n_routes {
+        let line = reader.read("route")?;
+        let route_cost = CostBreakdown {
+            strip_len: line.get(0)?,
+            wire_len: line.get(1)?,
+            n_wires: line.get(2)?,
+            n_vias: line.get(3)?,
+            n_reused: line.get(4)?,
+            n_cuts: 0,
+            used_area: 0,
+            len_mismatch: 0,
+            cost: line.get(5)?,
+        };
+        let connection_idx: usize = line.get(6)?;
+        if connection_idx >= n_connections {
+            return Err(line.error("invalid connection index"));
+        }
+        let route_section_vec = line.value_vec[7..]
+            .iter()
+            .map(|s| parse_section(s, layout))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| line.error("invalid route section"))?;
+        cost_breakdown.add(&route_cost);
+        route_cost_vec.push(route_cost);
+        route_connection_idx_vec.push(connection_idx);
+        route_vec.push(route_section_vec);
+    }
+    let line = reader.read("strip_cuts")?;
+    let strip_cut_vec = line
+        .value_vec
+        .iter()
+        .map(|s| parse_via(s, layout))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| line.error("invalid strip cut"))?;
+
+    layout.n_completed_routes = n_completed_routes;
+    layout.n_failed_routes = n_failed_routes;
+    layout.route_status_vec = route_status_vec.iter().map(|&v| v != 0).collect();
+    layout.route_vec = route_vec;
+    layout.route_cost_vec = route_cost_vec;
+    layout.route_connection_idx_vec = route_connection_idx_vec;
+    layout.strip_cut_vec = strip_cut_vec;
+    layout.cost_breakdown = cost_breakdown;
+    layout.cost = cost_breakdown.cost;
+
+    // The nets, as the router would have left them.
+    let board = layout.board;
+    let mut nets = Nets::new(board);
+    for c in layout.circuit.gen_connection_via_vec() {
+        nets.connect(board, layout, c.start, c.end);
+    }
+    for section in layout.route_vec.clone().iter().flatten() {
+        nets.connect(board, layout, section.start.via, section.end.via);
+    }
+    nets.copy_to_layout(layout);
+    layout.add_cut_cost();
+    layout.add_area_cost();
+    layout.add_length_mismatch_cost();
+    Ok(())
+}
+
+// A section is written as start-end, where each end is the layer ("s" for strip, "w"
+// for wire) followed by the position. E.g., s3,4-s3,9.
+fn format_section(section: &LayerStartEndVia) -> String {
+    let format_layer_via = |v: &LayerVia| format!("{}{},{}", if v.is_wire_layer { "w" } else { "s" }, v.via.x, v.via.y);
+    format!("{}-{}", format_layer_via(&section.start), format_layer_via(&section.end))
+}
+
+fn parse_section(s: &str, layout: &Layout) -> Option<LayerStartEndVia> {
+    let parse_layer_via = |s: &str| {
+        let is_wire_layer = match s.chars().next()? {
+            'w' => true,
+            's' => false,
+            _ => return None,
+        };
+        Some(LayerVia::from_via(parse_via(&s[1..], layout)?, is_wire_layer))
+    };
+    let (start, end) = s.split_once('-')?;
+    Some(LayerStartEndVia::from_layer_vias(parse_layer_via(start)?, parse_layer_via(end)?))
+}
+
+fn parse_via(s: &str, layout: &Layout) -> Option<Via> {
+    let (x, y) = s.split_once(',')?;
+    let via = Via::new(x.parse().ok()?, y.parse().ok()?);
+    if via.x < layout.board.w && via.y < layout.board.h {
+        Some(via)
+    } else {
+        None
+    }
+}
+
+//
+// Reading and writing
+//
+
+pub struct CheckpointWriter {
+    line_vec: Vec<String>,
+}
+
+impl CheckpointWriter {
+    pub fn new() -> Self {
+        Self {
+            line_vec: vec!["# Stripboard Autorouter checkpoint".to_string()],
+        }
+    }
+
+    pub fn write(&mut self, key: &str, value_vec: Vec<String>) {
+        let mut line = key.to_string();
+        for value in value_vec {
+            line.push(' ');
+            line.push_str(&value);
+        }
+        self.line_vec.push(line);
+    }
+
+    // Written to a temporary file first, so that an existing checkpoint is not lost if
+    // the app exits while saving.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        let mut contents = self.line_vec.join("\n");
+        contents.push('\n');
+        std::fs::write(&tmp_path, contents)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| format!("Unable to save checkpoint {}: {}", path.display(), e))
+    }
+}
+
+// The state of a ChaCha generator, as the seed, the stream and the position in the
+// stream. Takes up 3 values.
+pub fn format_rng(rng: &ChaCha8Rng) -> Vec<String> {
+    let seed_hex: String = rng.get_seed().iter().map(|b| format!("{:02x}", b)).collect();
+    vec![seed_hex, rng.get_stream().to_string(), rng.get_word_pos().to_string()]
+}
+
+pub struct CheckpointLine {
+    line_nr: usize,
+    pub value_vec: Vec<String>,
+}
+
+impl CheckpointLine {
+    pub fn get<T: FromStr>(&self, idx: usize) -> Result<T, String> {
+        let value = self.value_vec.get(idx).ok_or_else(|| self.error("missing value"))?;
+        value.parse().map_err(|_| self.error(&format!("invalid value: {}", value)))
+    }
+
+    // All the values from the given index.
+    pub fn get_vec<T: FromStr>(&self, first_idx: usize) -> Result<Vec<T>, String> {
+        (first_idx..self.value_vec.len()).map(|i| self.get(i)).collect()
+    }
+
+    // See format_rng().
+    pub fn get_rng(&self, first_idx: usize) -> Result<ChaCha8Rng, String> {
+        let seed_hex: String = self.get(first_idx)?;
+        let mut seed = [0u8; 32];
+        // Checked for ASCII so that the slicing below is on character boundaries.
+        if seed_hex.len() != 64 || !seed_hex.is_ascii() {
+            return Err(self.error("invalid random generator seed"));
+        }
+        for (i, b) in seed.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&seed_hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| self.error("invalid random generator seed"))?;
+        }
+        let mut rng = ChaCha8Rng::from_seed(seed);
+        rng.set_stream(self.get(first_idx + 1)?);
+        rng.set_word_pos(self.get(first_idx + 2)?);
+        Ok(rng)
+    }
+
+    pub fn error(&self, msg: &str) -> String {
+        format!("Checkpoint line {}: {}", self.line_nr, msg)
+    }
+}
+
+pub struct CheckpointReader {
+    // Line number and line, without the empty and comment lines.
+    line_vec: Vec<(usize, String)>,
+    next_line_idx: usize,
+}
+
+impl CheckpointReader {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read checkpoint {}: {}", path.display(), e))?;
+        let line_vec = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim().to_string()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        Ok(Self {
+            line_vec,
+            next_line_idx: 0,
+        })
+    }
+
+    // Read the next line, which must have the given key.
+    pub fn read(&mut self, key: &str) -> Result<CheckpointLine, String> {
+        let (line_nr, line) = self
+            .line_vec
+            .get(self.next_line_idx)
+            .ok_or_else(|| format!("Checkpoint ended before \"{}\"", key))?;
+        let mut value_iter = line.split_whitespace();
+        if value_iter.next() != Some(key) {
+            return Err(format!("Checkpoint line {}: expected \"{}\"", line_nr, key));
+        }
+        self.next_line_idx += 1;
+        Ok(CheckpointLine {
+            line_nr: *line_nr,
+            value_vec: value_iter.map(|s| s.to_string()).collect(),
+        })
+    }
+
+    // Skip over the next lines that have the given key, if any.
+    pub fn skip(&mut self, key: &str) {
+        while let Some((_, line)) = self.line_vec.get(self.next_line_idx) {
+            if line.split_whitespace().next() != Some(key) {
+                break;
+            }
+            self.next_line_idx += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_parser::CircuitFileParser;
+    use crate::ga_interface::GeneticAlgorithm;
+    use crate::local_search::LocalSearch;
+    use crate::router::Router;
+    use crate::settings::{GaSettings, OrderingSearchStrategy};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    fn example_layout() -> Layout {
+        let mut layout = Layout::new();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("circuits/example.circuit");
+        CircuitFileParser::new(&mut layout).parse(path.as_os_str());
+        layout
+    }
+
+    fn routed_layout(input_layout: &Layout) -> Layout {
+        let mut layout = input_layout.clone();
+        let board = layout.board;
+        let ordering = (0..layout.circuit.connection_vec.len()).collect();
+        let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+        Router::new(board).route(board, &mut layout, &mut Nets::new(board), ordering, &mut limit_routes);
+        layout
+    }
+
+    fn test_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("striprouter_{}_{}.checkpoint", name, std::process::id()))
+    }
+
+    fn small_ga_settings() -> GaSettings {
+        let mut ga_settings = GaSettings::new();
+        ga_settings.n_organisms_in_population = 10;
+        ga_settings
+    }
+
+    fn evaluated_genetic_algorithm(input_layout: &Layout) -> GeneticAlgorithm {
+        let mut genetic_algorithm = GeneticAlgorithm::new(&small_ga_settings(), 1);
+        genetic_algorithm.reset(&input_layout.circuit);
+        for i in 0..25 {
+            let ordering_idx = genetic_algorithm.reserve_ordering();
+            genetic_algorithm.release_ordering(ordering_idx, i % 3, 1000 - i);
+        }
+        genetic_algorithm
+    }
+
+    #[test]
+    fn resumed_checkpoint_continues_with_the_same_orderings() {
+        let input_layout = example_layout();
+        let best_layout = routed_layout(&input_layout);
+        let mut genetic_algorithm = evaluated_genetic_algorithm(&input_layout);
+        let path = test_path("resume");
+        save_checkpoint(&path, &input_layout, &genetic_algorithm, &best_layout).unwrap();
+
+        // The settings are not in the checkpoint.
+        let mut resumed_genetic_algorithm = GeneticAlgorithm::new(&small_ga_settings(), 2);
+        let mut resumed_best_layout = Layout::new();
+        let loaded = load_checkpoint(&path, &input_layout, &mut resumed_genetic_algorithm, &mut resumed_best_layout);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Ok(LoadedCheckpoint::Resumed));
+        assert_eq!(resumed_genetic_algorithm.get_n_generations(), genetic_algorithm.get_n_generations());
+        assert_eq!(resumed_best_layout.route_vec, best_layout.route_vec);
+        assert_eq!(resumed_best_layout.strip_cut_vec, best_layout.strip_cut_vec);
+        assert_eq!(resumed_best_layout.route_status_vec, best_layout.route_status_vec);
+        assert_eq!(resumed_best_layout.cost_breakdown, best_layout.cost_breakdown);
+        assert_eq!(resumed_best_layout.cost, best_layout.cost);
+        assert!(resumed_best_layout.is_based_on(&input_layout));
+
+        // Finish the current generation in the original, and the resumed one catches
+        // up through the cache. Both then breed the same next generation.
+        for i in 0..5 {
+            let ordering_idx = genetic_algorithm.reserve_ordering();
+            genetic_algorithm.release_ordering(ordering_idx, i % 3, 2000 - i);
+        }
+        for i in 0..5 {
+            let ordering_idx = resumed_genetic_algorithm.reserve_ordering();
+            resumed_genetic_algorithm.release_ordering(ordering_idx, i % 3, 2000 - i);
+        }
+        for _ in 0..10 {
+            let ordering_idx = genetic_algorithm.reserve_ordering();
+            let resumed_ordering_idx = resumed_genetic_algorithm.reserve_ordering();
+            assert_eq!(
+                resumed_genetic_algorithm.get_ordering(resumed_ordering_idx),
+                genetic_algorithm.get_ordering(ordering_idx)
+            );
+            genetic_algorithm.release_ordering(ordering_idx, 1, 500);
+            resumed_genetic_algorithm.release_ordering(resumed_ordering_idx, 1, 500);
+        }
+    }
+
+    #[test]
+    fn checkpoint_for_an_edited_circuit_warm_starts_without_the_best_layout() {
+        let input_layout = example_layout();
+        let genetic_algorithm = evaluated_genetic_algorithm(&input_layout);
+        let path = test_path("edited_circuit");
+        save_checkpoint(&path, &input_layout, &genetic_algorithm, &routed_layout(&input_layout)).unwrap();
+
+        let mut edited_layout = input_layout.clone();
+        edited_layout.circuit.connection_vec.remove(3);
+        let n_connections = edited_layout.circuit.connection_vec.len();
+        let mut best_layout = Layout::new();
+        let mut warm_genetic_algorithm = GeneticAlgorithm::new(&small_ga_settings(), 2);
+        let loaded = load_checkpoint(&path, &edited_layout, &mut warm_genetic_algorithm, &mut best_layout);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, Ok(LoadedCheckpoint::WarmStarted));
+        assert!(best_layout.route_vec.is_empty());
+        let ordering_idx = warm_genetic_algorithm.reserve_ordering();
+        let mut ordering = warm_genetic_algorithm.get_ordering(ordering_idx);
+        ordering.sort();
+        assert_eq!(ordering, (0..n_connections).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn corrupt_checkpoint_is_rejected_and_nothing_changes() {
+        let input_layout = example_layout();
+        let genetic_algorithm = evaluated_genetic_algorithm(&input_layout);
+        let path = test_path("corrupt");
+        save_checkpoint(&path, &input_layout, &genetic_algorithm, &routed_layout(&input_layout)).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let last_organism_idx = contents.rfind("organism").unwrap();
+        std::fs::write(&path, &contents[..last_organism_idx]).unwrap();
+
+        let mut best_layout = Layout::new();
+        let mut other_genetic_algorithm = GeneticAlgorithm::new(&small_ga_settings(), 2);
+        let loaded = load_checkpoint(&path, &input_layout, &mut other_genetic_algorithm, &mut best_layout);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.is_err());
+        assert!(best_layout.route_vec.is_empty());
+        assert_eq!(other_genetic_algorithm.reserve_ordering(), usize::MAX);
+    }
+
+    #[test]
+    fn route_values_that_do_not_match_the_circuit_are_rejected() {
+        let input_layout = example_layout();
+        let n_connections = input_layout.circuit.connection_vec.len();
+        let genetic_algorithm = evaluated_genetic_algorithm(&input_layout);
+        let path = test_path("route_values");
+        save_checkpoint(&path, &input_layout, &genetic_algorithm, &routed_layout(&input_layout)).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+
+        let edit_line = |key: &str, edit: &dyn Fn(&mut Vec<String>)| {
+            let line_vec: Vec<String> = contents
+                .lines()
+                .map(|line| {
+                    let mut value_vec: Vec<String> = line.split_whitespace().map(|s| s.to_string()).collect();
+                    if value_vec.first().map(String::as_str) == Some(key) {
+                        edit(&mut value_vec);
+                    }
+                    value_vec.join(" ")
+                })
+                .collect();
+            line_vec.join("\n")
+        };
+        for edited_contents in [
+            edit_line("route_status", &|value_vec| value_vec.push("1".to_string())),
+            edit_line("route_status", &|value_vec| value_vec[1] = "2".to_string()),
+            edit_line("route", &|value_vec| value_vec[7] = n_connections.to_string()),
+        ] {
+            std::fs::write(&path, edited_contents).unwrap();
+            let mut best_layout = Layout::new();
+            let mut other_genetic_algorithm = GeneticAlgorithm::new(&small_ga_settings(), 2);
+            let loaded = load_checkpoint(&path, &input_layout, &mut other_genetic_algorithm, &mut best_layout);
+            assert!(loaded.is_err());
+            assert!(best_layout.route_vec.is_empty());
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn non_ascii_random_generator_seed_is_rejected() {
+        let line = CheckpointLine {
+            line_nr: 1,
+            value_vec: vec!["é".repeat(32), "0".to_string(), "0".to_string()],
+        };
+        assert_eq!(line.get_rng(0).err(), Some(line.error("invalid random generator seed")));
+    }
+
+    #[test]
+    fn checkpoint_is_resumed_only_by_the_same_ordering_search() {
+        let input_layout = example_layout();
+        let mut annealing = LocalSearch::new(OrderingSearchStrategy::SimulatedAnnealing, 1);
+        annealing.reset(&input_layout.circuit);
+        for i in 0..25 {
+            let ordering_idx = annealing.reserve_ordering();
+            annealing.release_ordering(ordering_idx, 5 + i % 3, 1000 - i);
+        }
+        let path = test_path("ordering_search");
+        save_checkpoint(&path, &input_layout, &annealing, &routed_layout(&input_layout)).unwrap();
+
+        let mut best_layout = Layout::new();
+        let mut genetic_algorithm = GeneticAlgorithm::new(&small_ga_settings(), 2);
+        let ga_loaded = load_checkpoint(&path, &input_layout, &mut genetic_algorithm, &mut best_layout);
+        let mut resumed_annealing = LocalSearch::new(OrderingSearchStrategy::SimulatedAnnealing, 2);
+        let annealing_loaded = load_checkpoint(&path, &input_layout, &mut resumed_annealing, &mut best_layout);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(ga_loaded.is_err());
+        assert_eq!(annealing_loaded, Ok(LoadedCheckpoint::Resumed));
+        for i in 0..25 {
+            let ordering_idx = annealing.reserve_ordering();
+            let resumed_ordering_idx = resumed_annealing.reserve_ordering();
+            assert_eq!(resumed_annealing.get_ordering(resumed_ordering_idx), annealing.get_ordering(ordering_idx));
+            annealing.release_ordering(ordering_idx, 6, 900 - i);
+            resumed_annealing.release_ordering(resumed_ordering_idx, 6, 900 - i);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
 use std::usize;
 
+use crate::board::Board;
 use crate::via::{via_add_offset, OffsetVia, StartEndVia, Via};
 
 // Packages
@@ -46,18 +48,180 @@ impl ConnectionPoint {
             pin_idx,
         }
     }
+
+    // The pin, as in the .circuit file. Pin numbers are 1 based.
+    pub fn pin_key(&self) -> String {
+        format!("{}.{}", self.component_name, self.pin_idx + 1)
+    }
 }
 
 #[derive(Clone)]
 pub struct Connection {
-    start: ConnectionPoint,
-    end: ConnectionPoint,
+    pub start: ConnectionPoint,
+    pub end: ConnectionPoint,
 }
 
 impl Connection {
     pub fn new(start: ConnectionPoint, end: ConnectionPoint) -> Self {
         Self { start, end }
     }
+
+    // Identifies the connection independently of where it is in the .circuit file, so
+    // that it can be recognized after the file has been edited. The direction of the
+    // connection does not matter. Pin numbers are 1 based, as in the .circuit file.
+    pub fn key(&self) -> String {
+        let start = self.start.pin_key();
+        let end = self.end.pin_key();
+        if start <= end {
+            format!("{}-{}", start, end)
+        } else {
+            format!("{}-{}", end, start)
+        }
+    }
+}
+
+// Net attributes
+
+// Wire steps in power nets cost this many times the regular wire cost.
+pub const POWER_NET_WIRE_COST_FACTOR: usize = 3;
+
+// Routing constraints for the connections in a net. The nets are inferred from the
+// connections, so the attributes are given for a single pin, and apply to all the
+// connections in the net that the pin ends up in.
+//
+// - is_priority: The connections are routed before all the others, whatever the
+// ordering, and their routes are never ripped up to make room for other routes.
+// - max_strip_len: The routes use at most this many strip steps. The search finds the
+// lowest cost route within the limit, and the route fails if there is none.
+// - is_wire_forbidden: The routes may only use strips. Wire jumps are wires as well.
+// - is_strip_forbidden: The routes may only use the strips that the pins are on, and
+// wires between them.
+// - is_power: Wire steps cost POWER_NET_WIRE_COST_FACTOR times as much, so the routes
+// stay short and prefer the wide copper strips over thin wires.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub struct NetAttributes {
+    pub is_priority: bool,
+    pub max_strip_len: Option<usize>,
+    pub is_wire_forbidden: bool,
+    pub is_strip_forbidden: bool,
+    pub is_power: bool,
+}
+
+impl NetAttributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Combine the attributes given for two pins that turned out to be in the same net.
+    // The result is the strictest of the two.
+    pub fn merge(&mut self, other: &Self) {
+        self.is_priority |= other.is_priority;
+        self.max_strip_len = match (self.max_strip_len, other.max_strip_len) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.is_wire_forbidden |= other.is_wire_forbidden;
+        self.is_strip_forbidden |= other.is_strip_forbidden;
+        self.is_power |= other.is_power;
+    }
+}
+
+// Cost regions
+
+// Multipliers for the costs of the steps that enter a via. All are at least 1, so a
+// region can only make routing more expensive.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct CostFactors {
+    pub strip_factor: usize,
+    pub wire_factor: usize,
+    pub via_factor: usize,
+}
+
+impl CostFactors {
+    pub fn new() -> Self {
+        Self {
+            strip_factor: 1,
+            wire_factor: 1,
+            via_factor: 1,
+        }
+    }
+
+    // Where regions overlap, the highest factor of each kind applies.
+    pub fn merge(&mut self, other: &Self) {
+        self.strip_factor = self.strip_factor.max(other.strip_factor);
+        self.wire_factor = self.wire_factor.max(other.wire_factor);
+        self.via_factor = self.via_factor.max(other.via_factor);
+    }
+}
+
+impl Default for CostFactors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A rectangle on the board, in which routing is discouraged, such as under a heatsink
+// or a relay, or near the edge where a connector goes. Both corners are included.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct CostRegion {
+    pub rect: StartEndVia,
+    pub cost_factors: CostFactors,
+}
+
+impl CostRegion {
+    pub fn new(rect: StartEndVia, cost_factors: CostFactors) -> Self {
+        Self { rect, cost_factors }
+    }
+
+    pub fn contains(&self, via: Via) -> bool {
+        (self.rect.start.x..=self.rect.end.x).contains(&via.x) && (self.rect.start.y..=self.rect.end.y).contains(&via.y)
+    }
+}
+
+// Connection groups
+
+// Strip and wire steps in a pair route that are not next to the route of the other
+// connection in the pair cost this many times as much.
+pub const PAIR_DETOUR_COST_FACTOR: usize = 2;
+
+// Pair: Two connections that should run side by side, such as a differential signal.
+// The one that is routed last prefers the strips and wires next to the other.
+// MatchedLength: Connections that should have about the same length, such as clock
+// lines. The difference between the longest and the shortest route in the group is
+// added to the cost of the layout. See Settings::length_mismatch_cost.
+//
+// Pairs are matched in length as well.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GroupKind {
+    Pair,
+    MatchedLength,
+}
+
+impl GroupKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupKind::Pair => "pair",
+            GroupKind::MatchedLength => "match",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ConnectionGroup {
+    pub name: String,
+    pub kind: GroupKind,
+    // Indexes into Circuit::connection_vec.
+    pub connection_idx_vec: Vec<usize>,
+}
+
+impl ConnectionGroup {
+    pub fn new(name: String, kind: GroupKind, connection_idx_vec: Vec<usize>) -> Self {
+        Self {
+            name,
+            kind,
+            connection_idx_vec,
+        }
+    }
 }
 
 // Circuit
@@ -72,6 +236,10 @@ pub struct Circuit {
     pub package_to_pos_map: PackageToPosMap,
     pub component_name_to_component_map: ComponentNameToComponentMap,
     pub connection_vec: ConnectionVec,
+    // In the order they appear in the .circuit file. See NetAttributes.
+    pub net_attributes_vec: Vec<(ConnectionPoint, NetAttributes)>,
+    pub cost_region_vec: Vec<CostRegion>,
+    pub connection_group_vec: Vec<ConnectionGroup>,
     pub parser_error_vec: StringVec,
 }
 
@@ -81,6 +249,9 @@ impl Circuit {
             package_to_pos_map: PackageToPosMap::new(),
             component_name_to_component_map: ComponentNameToComponentMap::new(),
             connection_vec: ConnectionVec::new(),
+            net_attributes_vec: Vec::new(),
+            cost_region_vec: Vec::new(),
+            connection_group_vec: Vec::new(),
             parser_error_vec: StringVec::new(),
         }
     }
@@ -109,6 +280,105 @@ impl Circuit {
         v
     }
 
+    pub fn gen_connection_key_vec(&self) -> Vec<String> {
+        self.connection_vec.iter().map(|c| c.key()).collect()
+    }
+
+    // The net that each pin in a connection is in, by pin key. The pins are grouped into
+    // nets by following the connections, and the nets are numbered in the order in
+    // which their first pin appears in the connections.
+    pub fn gen_pin_net_idx_map(&self) -> HashMap<String, usize> {
+        let mut pin_idx_map: HashMap<String, usize> = HashMap::new();
+        let mut parent_vec: Vec<usize> = Vec::new();
+        let mut get_pin_idx = |pin_key: String, parent_vec: &mut Vec<usize>| {
+            *pin_idx_map.entry(pin_key).or_insert_with(|| {
+                parent_vec.push(parent_vec.len());
+                parent_vec.len() - 1
+            })
+        };
+        fn find_root(parent_vec: &mut [usize], mut idx: usize) -> usize {
+            while parent_vec[idx] != idx {
+                parent_vec[idx] = parent_vec[parent_vec[idx]];
+                idx = parent_vec[idx];
+            }
+            idx
+        }
+        for c in &self.connection_vec {
+            let start_idx = get_pin_idx(c.start.pin_key(), &mut parent_vec);
+            let end_idx = get_pin_idx(c.end.pin_key(), &mut parent_vec);
+            let start_root = find_root(&mut parent_vec, start_idx);
+            let end_root = find_root(&mut parent_vec, end_idx);
+            parent_vec[start_root] = end_root;
+        }
+        // Pin indexes are also in order of first appearance.
+        let mut root_net_idx_map: HashMap<usize, usize> = HashMap::new();
+        let mut pin_key_vec: Vec<(String, usize)> = pin_idx_map.into_iter().collect();
+        pin_key_vec.sort_by_key(|(_, pin_idx)| *pin_idx);
+        pin_key_vec
+            .into_iter()
+            .map(|(pin_key, pin_idx)| {
+                let root = find_root(&mut parent_vec, pin_idx);
+                let n_nets = root_net_idx_map.len();
+                (pin_key, *root_net_idx_map.entry(root).or_insert(n_nets))
+            })
+            .collect()
+    }
+
+    // The net that each connection is in. See gen_pin_net_idx_map().
+    pub fn gen_connection_net_idx_vec(&self) -> Vec<usize> {
+        let pin_net_idx_map = self.gen_pin_net_idx_map();
+        self.connection_vec
+            .iter()
+            .map(|c| pin_net_idx_map[&c.start.pin_key()])
+            .collect()
+    }
+
+    // The attributes of the net that each connection is in. The attributes given for
+    // any pin in a net are merged.
+    pub fn gen_connection_net_attributes_vec(&self) -> Vec<NetAttributes> {
+        if self.net_attributes_vec.is_empty() {
+            return vec![NetAttributes::new(); self.connection_vec.len()];
+        }
+        let pin_net_idx_map = self.gen_pin_net_idx_map();
+        let mut net_attributes_map: HashMap<usize, NetAttributes> = HashMap::new();
+        for (connection_point, net_attributes) in &self.net_attributes_vec {
+            // Pins that are not in any connection have no routes to constrain.
+            if let Some(&net_idx) = pin_net_idx_map.get(&connection_point.pin_key()) {
+                net_attributes_map.entry(net_idx).or_default().merge(net_attributes);
+            }
+        }
+        self.gen_connection_net_idx_vec()
+            .into_iter()
+            .map(|net_idx| net_attributes_map.get(&net_idx).copied().unwrap_or_default())
+            .collect()
+    }
+
+    // The connection that is paired with the connection, if it's in a pair.
+    pub fn find_pair_connection_idx(&self, connection_idx: usize) -> Option<usize> {
+        self.connection_group_vec
+            .iter()
+            .filter(|g| g.kind == GroupKind::Pair)
+            .find(|g| g.connection_idx_vec.contains(&connection_idx))
+            .and_then(|g| g.connection_idx_vec.iter().copied().find(|&i| i != connection_idx))
+    }
+
+    // The cost factors for each via on the board, indexed by Board::idx(). Empty if
+    // there are no cost regions, in which case all the factors are 1.
+    pub(crate) fn gen_cost_factors_vec(&self, board: Board) -> Vec<CostFactors> {
+        if self.cost_region_vec.is_empty() {
+            return Vec::new();
+        }
+        let mut cost_factors_vec = vec![CostFactors::new(); board.size()];
+        for cost_region in &self.cost_region_vec {
+            for y in cost_region.rect.start.y..=cost_region.rect.end.y.min(board.h.saturating_sub(1)) {
+                for x in cost_region.rect.start.x..=cost_region.rect.end.x.min(board.w.saturating_sub(1)) {
+                    cost_factors_vec[board.idx(Via::new(x, y))].merge(&cost_region.cost_factors);
+                }
+            }
+        }
+        cost_factors_vec
+    }
+
     pub fn calc_component_footprint(&self, component_name: String) -> StartEndVia {
         let mut v = StartEndVia::new(Via::new(usize::MAX, usize::MAX), Via::new(0, 0));
         let component = self.component_name_to_component_map.get(&component_name).unwrap();
@@ -142,4 +412,60 @@ impl Circuit {
         }
         v
     }
+
+    // Move all the components by the offset. The caller must make sure that no pin
+    // ends up at a negative position. The cost regions are moved along. Regions that
+    // end up partly at negative positions are clipped, and regions that end up
+    // entirely at negative positions are dropped.
+    pub fn move_components(&mut self, offset: &OffsetVia) {
+        for component in self.component_name_to_component_map.values_mut() {
+            component.pin0_abs_pos = via_add_offset(&component.pin0_abs_pos, offset);
+        }
+        let move_coord = |v: usize, d: isize| (v as isize + d).max(0) as usize;
+        self.cost_region_vec
+            .retain(|r| r.rect.end.x as isize + offset.x >= 0 && r.rect.end.y as isize + offset.y >= 0);
+        for cost_region in &mut self.cost_region_vec {
+            let rect = &mut cost_region.rect;
+            rect.start = Via::new(move_coord(rect.start.x, offset.x), move_coord(rect.start.y, offset.y));
+            rect.end = Via::new(move_coord(rect.end.x, offset.x), move_coord(rect.end.y, offset.y));
+        }
+    }
+}
+
+// Everything that affects routing: the packages, the components, the connections, the
+// net attributes, the cost regions and the connection groups.
+// The maps are hashed in sorted order, so that the hash doesn't depend on the
+// iteration order of the HashMaps. Parser errors are not included.
+impl Hash for Circuit {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut package_name_vec: Vec<&String> = self.package_to_pos_map.keys().collect();
+        package_name_vec.sort();
+        for package_name in package_name_vec {
+            package_name.hash(state);
+            self.package_to_pos_map[package_name].hash(state);
+        }
+        let mut component_name_vec: Vec<&String> = self.component_name_to_component_map.keys().collect();
+        component_name_vec.sort();
+        for component_name in component_name_vec {
+            let component = &self.component_name_to_component_map[component_name];
+            component_name.hash(state);
+            component.package_name.hash(state);
+            component.pin0_abs_pos.hash(state);
+            let mut dont_care_pin_idx_vec: Vec<&usize> = component.dont_care_pin_idx_set.iter().collect();
+            dont_care_pin_idx_vec.sort();
+            dont_care_pin_idx_vec.hash(state);
+        }
+        for connection in &self.connection_vec {
+            connection.start.component_name.hash(state);
+            connection.start.pin_idx.hash(state);
+            connection.end.component_name.hash(state);
+            connection.end.pin_idx.hash(state);
+        }
+        for (connection_point, net_attributes) in &self.net_attributes_vec {
+            connection_point.pin_key().hash(state);
+            net_attributes.hash(state);
+        }
+        self.cost_region_vec.hash(state);
+        self.connection_group_vec.hash(state);
+    }
 }
@@ -8,9 +8,11 @@ use std::vec::Vec;
 use lazy_static::lazy_static;
 use regex::Regex;
 
-use crate::circuit::{Component, Connection, ConnectionPoint};
+use crate::circuit::{
+    Component, Connection, ConnectionGroup, ConnectionPoint, CostFactors, CostRegion, GroupKind, NetAttributes,
+};
 use crate::layout::Layout;
-use crate::via::{via_add_offset, via_from_offset, OffsetVia, Via};
+use crate::via::{via_add_offset, via_from_offset, OffsetVia, StartEndVia, Via};
 
 pub struct CircuitFileParser<'a> {
     layout: &'a mut Layout,
@@ -28,6 +30,10 @@ lazy_static! {
     static ref PKG_POS_RX: Regex = Regex::new(r"(-?\d+),(-?\d+)").unwrap();
     static ref COMPONENT_FULL_RX: Regex = Regex::new(r"^(\w+) (\w+) ?(\d+),(\d+)$").unwrap();
     static ref CONNECTION_FULL_RX: Regex = Regex::new(r"^(\w+)\.(\d+) (\w+)\.(\d+)$").unwrap();
+    static ref NET_FULL_RX: Regex = Regex::new(r"^net (\w+)\.(\d+) (.+)$").unwrap();
+    static ref REGION_FULL_RX: Regex = Regex::new(r"^region (\d+),(\d+) (\d+),(\d+) (.+)$").unwrap();
+    static ref GROUP_FULL_RX: Regex = Regex::new(r"^group (\w+) (\w+) (.+)$").unwrap();
+    static ref GROUP_CONNECTION_FULL_RX: Regex = Regex::new(r"^(\w+)\.(\d+)-(\w+)\.(\d+)$").unwrap();
     static ref DONT_CARE_FULL_RX: Regex = Regex::new(r"^(\w+) ((\d+( |$))+)$").unwrap();
 }
 
@@ -41,7 +47,6 @@ impl<'a> CircuitFileParser<'a> {
     }
 
     pub fn parse(&mut self, circuit_file_path: &OsStr) {
-        println!("{:?}", circuit_file_path.to_str());
         let file = File::open(circuit_file_path).expect("Cannot read .circuit file");
         let reader = io::BufReader::new(file);
 
@@ -89,12 +94,16 @@ impl<'a> CircuitFileParser<'a> {
         // Connections are most common, so they are parsed first to improve
         // performance.
 
-        println!("{}", line);
-
         return if self.parse_connection(&line)? {
             Ok(())
         } else if self.parse_comment_or_empty(&line)? {
             Ok(())
+        } else if self.parse_net(&line)? {
+            Ok(())
+        } else if self.parse_group(&line)? {
+            Ok(())
+        } else if self.parse_region(&line)? {
+            Ok(())
         } else if self.parse_board(&line)? {
             Ok(())
         } else if self.parse_offset(&line)? {
@@ -264,8 +273,8 @@ impl<'a> CircuitFileParser<'a> {
     fn parse_connection(&mut self, line: &str) -> Result<bool, String> {
         return match CONNECTION_FULL_RX.captures(line) {
             Some(captures) => {
-                let start = ConnectionPoint::new(captures[1].to_string(), captures[2].parse::<usize>().unwrap() - 1);
-                let end = ConnectionPoint::new(captures[3].to_string(), captures[4].parse::<usize>().unwrap() - 1);
+                let start = ConnectionPoint::new(captures[1].to_string(), parse_pin_idx(&captures[2])?);
+                let end = ConnectionPoint::new(captures[3].to_string(), parse_pin_idx(&captures[4])?);
                 self.check_connection_point(&start)?;
                 self.check_connection_point(&end)?;
                 if start.component_name != end.component_name || start.pin_idx != end.pin_idx {
@@ -277,6 +286,135 @@ impl<'a> CircuitFileParser<'a> {
         };
     }
 
+    // Net attributes
+    // net <component name>.<pin number> <attribute> <...>
+    // Attributes: priority, max-strip=<length>, no-wire, no-strip, power
+    fn parse_net(&mut self, line: &str) -> Result<bool, String> {
+        let captures = match NET_FULL_RX.captures(line) {
+            Some(captures) => captures,
+            None => return Ok(false),
+        };
+        let connection_point = ConnectionPoint::new(captures[1].to_string(), parse_pin_idx(&captures[2])?);
+        self.check_connection_point(&connection_point)?;
+        let mut net_attributes = NetAttributes::new();
+        for attribute in WHITESPACE_SEP_RX.split(&captures[3]) {
+            match attribute.split_once('=') {
+                None if attribute == "priority" => net_attributes.is_priority = true,
+                None if attribute == "no-wire" => net_attributes.is_wire_forbidden = true,
+                None if attribute == "no-strip" => net_attributes.is_strip_forbidden = true,
+                None if attribute == "power" => net_attributes.is_power = true,
+                Some(("max-strip", value)) => match value.parse::<usize>() {
+                    Ok(max_strip_len) => net_attributes.max_strip_len = Some(max_strip_len),
+                    Err(_) => return Err(format!("Invalid max-strip length: {}", value)),
+                },
+                _ => return Err(format!("Unknown net attribute: {}", attribute)),
+            }
+        }
+        if net_attributes.is_wire_forbidden && net_attributes.is_strip_forbidden {
+            return Err("A net can't forbid both wires and strips".to_string());
+        }
+        self.layout
+            .circuit
+            .net_attributes_vec
+            .push((connection_point, net_attributes));
+        Ok(true)
+    }
+
+    // Connection group
+    // group <pair|match> <group name> <connection> <...>
+    // A connection is given by its pins, as <component name>.<pin number>-<component
+    // name>.<pin number>, in either order. A pair has exactly two connections.
+    fn parse_group(&mut self, line: &str) -> Result<bool, String> {
+        let captures = match GROUP_FULL_RX.captures(line) {
+            Some(captures) => captures,
+            None => return Ok(false),
+        };
+        let kind = match &captures[1] {
+            "pair" => GroupKind::Pair,
+            "match" => GroupKind::MatchedLength,
+            kind => return Err(format!("Unknown group kind: {}. Must be pair or match", kind)),
+        };
+        let connection_key_vec = self.layout.circuit.gen_connection_key_vec();
+        let mut connection_idx_vec = Vec::new();
+        for connection_str in WHITESPACE_SEP_RX.split(&captures[3]) {
+            let connection_captures = match GROUP_CONNECTION_FULL_RX.captures(connection_str) {
+                Some(connection_captures) => connection_captures,
+                None => return Err(format!("Invalid connection: {}", connection_str)),
+            };
+            let connection = Connection::new(
+                ConnectionPoint::new(connection_captures[1].to_string(), parse_pin_idx(&connection_captures[2])?),
+                ConnectionPoint::new(connection_captures[3].to_string(), parse_pin_idx(&connection_captures[4])?),
+            );
+            let connection_idx = match connection_key_vec.iter().position(|k| *k == connection.key()) {
+                Some(connection_idx) => connection_idx,
+                None => return Err(format!("Unknown connection: {}", connection_str)),
+            };
+            if connection_idx_vec.contains(&connection_idx) {
+                return Err(format!("Connection is in the group more than once: {}", connection_str));
+            }
+            connection_idx_vec.push(connection_idx);
+        }
+        match kind {
+            GroupKind::Pair if connection_idx_vec.len() != 2 => {
+                return Err("A pair must have exactly 2 connections".to_string())
+            }
+            GroupKind::MatchedLength if connection_idx_vec.len() < 2 => {
+                return Err("A matched length group must have at least 2 connections".to_string())
+            }
+            _ => {}
+        }
+        self.layout.circuit.connection_group_vec.push(ConnectionGroup::new(
+            captures[2].to_string(),
+            kind,
+            connection_idx_vec,
+        ));
+        Ok(true)
+    }
+
+    // Cost region
+    // region <x1>,<y1> <x2>,<y2> <factor> <...>
+    // Factors: strip=<n>, wire=<n>, via=<n>
+    // The corners are board positions, and are not affected by offset.
+    fn parse_region(&mut self, line: &str) -> Result<bool, String> {
+        let captures = match REGION_FULL_RX.captures(line) {
+            Some(captures) => captures,
+            None => return Ok(false),
+        };
+        let parse_coord = |i: usize| {
+            captures[i]
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid region position: {}", &captures[i]))
+        };
+        let (x1, y1, x2, y2) = (parse_coord(1)?, parse_coord(2)?, parse_coord(3)?, parse_coord(4)?);
+        let rect = StartEndVia::new(Via::new(x1.min(x2), y1.min(y2)), Via::new(x1.max(x2), y1.max(y2)));
+        let mut cost_factors = CostFactors::new();
+        for factor in WHITESPACE_SEP_RX.split(&captures[5]) {
+            let (name, value) = match factor.split_once('=') {
+                Some((name @ ("strip" | "wire" | "via"), value)) => (name, value),
+                _ => return Err(format!("Unknown cost factor: {}", factor)),
+            };
+            let value = match value.parse::<usize>() {
+                Ok(value) if value >= 1 => value,
+                _ => {
+                    return Err(format!(
+                        "Invalid {} cost factor: {}. Must be a whole number of 1 or more",
+                        name, value
+                    ))
+                }
+            };
+            match name {
+                "strip" => cost_factors.strip_factor = value,
+                "wire" => cost_factors.wire_factor = value,
+                _ => cost_factors.via_factor = value,
+            }
+        }
+        self.layout
+            .circuit
+            .cost_region_vec
+            .push(CostRegion::new(rect, cost_factors));
+        Ok(true)
+    }
+
     fn check_connection_point(&self, connection_point: &ConnectionPoint) -> Result<(), String> {
         let component = self
             .layout
@@ -312,3 +450,38 @@ impl<'a> CircuitFileParser<'a> {
         }
     }
 }
+
+// Pin numbers are 1 based in the .circuit file, and pin indexes are 0 based.
+fn parse_pin_idx(pin_number: &str) -> Result<usize, String> {
+    match pin_number.parse::<usize>() {
+        Ok(pin_number) if pin_number >= 1 => Ok(pin_number - 1),
+        _ => Err(format!("Invalid pin number: {}. Pin numbers start at 1", pin_number)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pin_zero_and_overflowing_numbers_are_errors() {
+        let mut layout = Layout::new();
+        let mut parser = CircuitFileParser::new(&mut layout);
+        for line in ["board 10,10", "pad2 0,0 1,0", "a pad2 1,1", "b pad2 5,1", "a.1 b.2"] {
+            parser.parse_line(line.to_string()).unwrap();
+        }
+        let huge = "99999999999999999999999";
+        for line in [
+            "a.0 b.1".to_string(),
+            format!("a.1 b.{}", huge),
+            "net a.0 priority".to_string(),
+            format!("net a.{} priority", huge),
+            "group match g a.0-b.2 a.1-b.2".to_string(),
+            format!("group match g a.1-b.{} a.1-b.2", huge),
+            format!("region 1,1 {},2 strip=2", huge),
+        ] {
+            assert!(parser.parse_line(line.clone()).is_err(), "{}", line);
+        }
+        assert_eq!(layout.circuit.connection_vec.len(), 1);
+    }
+}
@@ -0,0 +1,192 @@
+use std::path::PathBuf;
+
+use crate::assembly_pdf::PageSize;
+use crate::settings::{CrossoverOperator, GaSettings, GaStrategy, MutationOperator, OrderingSearchStrategy, Settings};
+
+// Command line arguments
+//
+// --circuit <path>: The .circuit file to load. The default is the example circuit.
+// --verify: Route the circuit once with the connections in the order they appear in
+// the .circuit file, print the electrical verification report for the result and
+// exit without starting the GUI. Exits with status 1 if any issues were found.
+// --seed <number>: Seed for all the random generators. If not given, a random seed
+// is used. The seed is printed at startup, so that a run can be repeated.
+// --deterministic: Use a single router thread, so that the orderings are checked in
+// the same sequence on every run with the same seed.
+// --checks <number>: Check the given number of orderings without starting the GUI,
+// then print the report for the best layout and exit. Always deterministic.
+// --shrink: Find the smallest board that the circuit can still be routed completely
+// on, print the board size and how far to move the components, and exit without
+// starting the GUI. Each board size is tried with the number of orderings given by
+// --checks (default 100). See BoardShrinker.
+// --area-cost <number>: With --checks, the cost per via in the rectangle used by the
+// layout. See Settings::area_cost.
+// --rip-up-passes <number>: With --checks, --verify or --shrink, the max number of
+// rip-up and reroute passes over the failed connections. 0 disables rip-up. See
+// Settings::rip_up_passes.
+// --ordering-search <ga|random-restart|annealing|hardest-first>: How the connection
+// orderings are searched for. The default is the GA. See OrderingSearchStrategy.
+// --ga-strategy <generational|steady-state>: See GaStrategy.
+// --population <number>, --crossover-rate <rate>, --mutation-rate <rate>,
+// --tournament <number>, --elites <number>, --fitness-cache <number>: Genetic
+// algorithm settings. See GaSettings.
+// --crossover <single-point|uniform|dependency>: See CrossoverOperator.
+// --mutation <dependency|swap|insert>: See MutationOperator.
+// --adaptive-mutation: Raise the mutation rate while the search is stagnating.
+// --checkpoint <path>: Resume the search from the checkpoint file if it exists, and
+// save the search to it while running and at exit. With --checks, it's saved when the
+// checks are done. If the circuit has been edited since the checkpoint was saved, the
+// saved population is used to warm-start the search.
+// --stats <path>: With --checks, write the statistics for each generation to the
+// file, as CSV. See GenerationStats.
+// --pareto <dir>: With --checks, write the objectives of the layouts on the Pareto
+// front to front.csv in the directory, and each layout to pareto-<id>.layout. See
+// ParetoArchive.
+// --build-sheet <path>: With --checks, --verify or --shrink, write the build sheet for
+// the resulting layout to the file. The format follows from the extension: .md for
+// Markdown, .csv for CSV and plain text for anything else. See BuildSheet.
+// --pdf <path>: With --checks, --verify or --shrink, write the printable assembly
+// sheets for the resulting layout to the PDF file. See AssemblyPdf.
+// --page-size <a4|letter>: The page size for --pdf. The default is A4.
+
+pub struct Args {
+    pub circuit_file_path: Option<PathBuf>,
+    pub verify: bool,
+    pub seed: Option<u64>,
+    pub is_deterministic: bool,
+    pub n_checks: Option<usize>,
+    pub shrink: bool,
+    pub area_cost: usize,
+    pub rip_up_passes: usize,
+    pub ordering_search_strategy: OrderingSearchStrategy,
+    pub ga_settings: GaSettings,
+    pub checkpoint_path: Option<PathBuf>,
+    pub stats_path: Option<PathBuf>,
+    pub pareto_dir_path: Option<PathBuf>,
+    pub build_sheet_path: Option<PathBuf>,
+    pub pdf_path: Option<PathBuf>,
+    pub page_size: PageSize,
+}
+
+impl Args {
+    pub fn new() -> Self {
+        Self {
+            circuit_file_path: None,
+            verify: false,
+            seed: None,
+            is_deterministic: false,
+            n_checks: None,
+            shrink: false,
+            area_cost: 0,
+            rip_up_passes: Settings::new().rip_up_passes,
+            ordering_search_strategy: OrderingSearchStrategy::GeneticAlgorithm,
+            ga_settings: GaSettings::new(),
+            checkpoint_path: None,
+            stats_path: None,
+            pareto_dir_path: None,
+            build_sheet_path: None,
+            pdf_path: None,
+            page_size: PageSize::A4,
+        }
+    }
+
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Self::new();
+        let mut arg_iter = std::env::args().skip(1);
+        while let Some(arg) = arg_iter.next() {
+            match arg.as_str() {
+                "--circuit" => {
+                    let path = arg_iter.next().ok_or("--circuit requires a path")?;
+                    args.circuit_file_path = Some(PathBuf::from(path));
+                }
+                "--verify" => args.verify = true,
+                "--stats" => {
+                    let path = arg_iter.next().ok_or("--stats requires a path")?;
+                    args.stats_path = Some(PathBuf::from(path));
+                }
+                "--pareto" => {
+                    let path = arg_iter.next().ok_or("--pareto requires a directory")?;
+                    args.pareto_dir_path = Some(PathBuf::from(path));
+                }
+                "--build-sheet" => {
+                    let path = arg_iter.next().ok_or("--build-sheet requires a path")?;
+                    args.build_sheet_path = Some(PathBuf::from(path));
+                }
+                "--pdf" => {
+                    let path = arg_iter.next().ok_or("--pdf requires a path")?;
+                    args.pdf_path = Some(PathBuf::from(path));
+                }
+                "--page-size" => {
+                    args.page_size = match arg_iter.next().as_deref() {
+                        Some("a4") => PageSize::A4,
+                        Some("letter") => PageSize::Letter,
+                        _ => return Err("--page-size requires a4 or letter".to_string()),
+                    }
+                }
+                "--checkpoint" => {
+                    let path = arg_iter.next().ok_or("--checkpoint requires a path")?;
+                    args.checkpoint_path = Some(PathBuf::from(path));
+                }
+                "--seed" => args.seed = Some(Self::parse_number(&arg, arg_iter.next())?),
+                "--deterministic" => args.is_deterministic = true,
+                "--checks" => args.n_checks = Some(Self::parse_number(&arg, arg_iter.next())?),
+                "--shrink" => args.shrink = true,
+                "--area-cost" => args.area_cost = Self::parse_number(&arg, arg_iter.next())?,
+                "--rip-up-passes" => args.rip_up_passes = Self::parse_number(&arg, arg_iter.next())?,
+                "--ordering-search" => {
+                    args.ordering_search_strategy = match arg_iter.next().as_deref() {
+                        Some("ga") => OrderingSearchStrategy::GeneticAlgorithm,
+                        Some("random-restart") => OrderingSearchStrategy::RandomRestart,
+                        Some("annealing") => OrderingSearchStrategy::SimulatedAnnealing,
+                        Some("hardest-first") => OrderingSearchStrategy::HardestFirst,
+                        _ => {
+                            return Err(
+                                "--ordering-search requires ga, random-restart, annealing or hardest-first".to_string(),
+                            )
+                        }
+                    }
+                }
+                "--ga-strategy" => {
+                    args.ga_settings.strategy = match arg_iter.next().as_deref() {
+                        Some("generational") => GaStrategy::Generational,
+                        Some("steady-state") => GaStrategy::SteadyState,
+                        _ => return Err("--ga-strategy requires generational or steady-state".to_string()),
+                    }
+                }
+                "--crossover" => {
+                    args.ga_settings.crossover_operator = match arg_iter.next().as_deref() {
+                        Some("single-point") => CrossoverOperator::SinglePoint,
+                        Some("uniform") => CrossoverOperator::Uniform,
+                        Some("dependency") => CrossoverOperator::DependencyPreserving,
+                        _ => return Err("--crossover requires single-point, uniform or dependency".to_string()),
+                    }
+                }
+                "--mutation" => {
+                    args.ga_settings.mutation_operator = match arg_iter.next().as_deref() {
+                        Some("dependency") => MutationOperator::Dependency,
+                        Some("swap") => MutationOperator::Swap,
+                        Some("insert") => MutationOperator::Insert,
+                        _ => return Err("--mutation requires dependency, swap or insert".to_string()),
+                    }
+                }
+                "--adaptive-mutation" => args.ga_settings.is_adaptive_mutation = true,
+                "--population" => args.ga_settings.n_organisms_in_population = Self::parse_number(&arg, arg_iter.next())?,
+                "--crossover-rate" => args.ga_settings.crossover_rate = Self::parse_number(&arg, arg_iter.next())?,
+                "--mutation-rate" => args.ga_settings.mutation_rate = Self::parse_number(&arg, arg_iter.next())?,
+                "--tournament" => args.ga_settings.n_tournament_candidates = Self::parse_number(&arg, arg_iter.next())?,
+                "--elites" => args.ga_settings.n_elites = Self::parse_number(&arg, arg_iter.next())?,
+                "--fitness-cache" => args.ga_settings.fitness_cache_size = Self::parse_number(&arg, arg_iter.next())?,
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        args.ga_settings.validate()?;
+        Ok(args)
+    }
+
+    fn parse_number<T: std::str::FromStr>(arg: &str, value: Option<String>) -> Result<T, String> {
+        let value = value.ok_or(format!("{} requires a number", arg))?;
+        value
+            .parse::<T>()
+            .map_err(|_| format!("{} requires a number, got: {}", arg, value))
+    }
+}
@@ -1,8 +1,10 @@
 use crate::render::Render;
+use crate::settings::{CrossoverOperator, GaStrategy, MutationOperator, OrderingSearchStrategy};
 use crate::{status, MyApp};
-use eframe::epaint::{Color32, FontId};
+use crate::ga_stats::GenerationStats;
+use eframe::epaint::{Color32, FontId, Pos2, Shape, Stroke};
 use egui::WidgetText::RichText;
-use egui::{Align, Button, Context, FontData, FontDefinitions, FontFamily, Rect, Sense, TextStyle, Ui};
+use egui::{Align, Align2, Button, Context, FontData, FontDefinitions, FontFamily, Rect, Sense, TextStyle, Ui};
 use num_format::{Locale, ToFormattedString};
 use std::fmt::format;
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -59,12 +61,31 @@ impl<'a> Controls {
                     });
                     ui.end_row();
 
+                    ui.horizontal(|ui| {
+                        ui.label("    ");
+                        if ui.button("Save build sheet").clicked() {
+                            status.is_build_sheet_export_requested = true;
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.horizontal(|ui| {
+                        ui.label("    ");
+                        if ui.button("Save PDF").clicked() {
+                            status.is_pdf_export_requested = true;
+                        }
+                    });
+                    ui.end_row();
+
                     Controls::header(ui, "Layouts", false);
 
                     Controls::section(ui, "Total");
 
                     Controls::name_int(ui, "Checked", status.checked_total);
                     Controls::name_float(ui, "Checked/s", status.checked_per_second);
+                    Controls::name_int(ui, "Generations", status.n_generations);
+                    let n_distinct_orderings = status.generation_stats_vec.last().map_or(0, |s| s.n_distinct_orderings);
+                    Controls::name_int(ui, "Diversity", n_distinct_orderings);
 
                     Controls::section(ui, "Current");
 
@@ -78,6 +99,23 @@ impl<'a> Controls {
                     Controls::name_int(ui, "Failed", status.best_layout_failed_routes);
                     Controls::name_int(ui, "Cost", status.best_layout_cost);
 
+                    Controls::section(ui, "Verify");
+
+                    Controls::name_int(ui, "Opens", status.best_layout_opens);
+                    Controls::name_int(ui, "Shorts", status.best_layout_shorts);
+                    Controls::name_int(ui, "Shared Strips", status.best_layout_shared_strips);
+
+                    if !status.best_layout_group_len_diff_vec.is_empty() {
+                        Controls::section(ui, "Length Mismatch");
+
+                        for (name, len_diff) in &status.best_layout_group_len_diff_vec {
+                            match len_diff {
+                                Some(len_diff) => Controls::name_int(ui, name, *len_diff),
+                                None => Controls::name_str(ui, name, "Not routed"),
+                            }
+                        }
+                    }
+
                     Controls::header(ui, "Router", false);
 
                     Controls::section(ui, "Costs");
@@ -111,12 +149,53 @@ impl<'a> Controls {
 
 
                     Controls::name_widget(ui, "Cut", egui::DragValue::new(&mut status.cut_cost).clamp_range(1..=100));
+                    Controls::name_widget(ui, "Area", egui::DragValue::new(&mut status.area_cost).clamp_range(0..=100));
+                    Controls::name_widget(ui, "Mismatch", egui::DragValue::new(&mut status.length_mismatch_cost).clamp_range(0..=100));
+
+                    Controls::section(ui, "Search");
+
+                    Controls::name_widget(ui, "A*", egui::Checkbox::new(&mut status.use_a_star, ""));
+                    Controls::name_widget(ui, "Rip-up Passes", egui::DragValue::new(&mut status.rip_up_passes).clamp_range(0..=10));
+                    Controls::name_combo(ui, "Ordering", &mut status.ordering_search_strategy, &[
+                        (OrderingSearchStrategy::GeneticAlgorithm, "Genetic Algorithm"),
+                        (OrderingSearchStrategy::RandomRestart, "Random Restart"),
+                        (OrderingSearchStrategy::SimulatedAnnealing, "Annealing"),
+                        (OrderingSearchStrategy::HardestFirst, "Hardest First"),
+                    ]);
+
+                    Controls::section(ui, "Genetic Algorithm");
+
+                    let ga_settings = &mut status.ga_settings;
+                    let mut is_steady_state = ga_settings.strategy == GaStrategy::SteadyState;
+                    Controls::name_widget(ui, "Steady-State", egui::Checkbox::new(&mut is_steady_state, ""));
+                    ga_settings.strategy = if is_steady_state { GaStrategy::SteadyState } else { GaStrategy::Generational };
+                    Controls::name_widget(ui, "Population", egui::DragValue::new(&mut ga_settings.n_organisms_in_population).clamp_range(2..=10000));
+                    Controls::name_widget(ui, "Crossover", egui::DragValue::new(&mut ga_settings.crossover_rate).clamp_range(0.0..=1.0).speed(0.01));
+                    Controls::name_widget(ui, "Mutation", egui::DragValue::new(&mut ga_settings.mutation_rate).clamp_range(0.0..=1.0).speed(0.001));
+                    // Can't be more than the population, which may just have been made smaller.
+                    let n_organisms = ga_settings.n_organisms_in_population;
+                    ga_settings.n_tournament_candidates = ga_settings.n_tournament_candidates.min(n_organisms);
+                    ga_settings.n_elites = ga_settings.n_elites.min(n_organisms);
+                    Controls::name_widget(ui, "Tournament", egui::DragValue::new(&mut ga_settings.n_tournament_candidates).clamp_range(1..=n_organisms.min(10)));
+                    Controls::name_widget(ui, "Elites", egui::DragValue::new(&mut ga_settings.n_elites).clamp_range(0..=n_organisms.min(100)));
+                    Controls::name_combo(ui, "Crossover Op", &mut ga_settings.crossover_operator, &[
+                        (CrossoverOperator::SinglePoint, "Single-Point"),
+                        (CrossoverOperator::Uniform, "Uniform"),
+                        (CrossoverOperator::DependencyPreserving, "Dependency"),
+                    ]);
+                    Controls::name_combo(ui, "Mutation Op", &mut ga_settings.mutation_operator, &[
+                        (MutationOperator::Dependency, "Dependency"),
+                        (MutationOperator::Swap, "Swap"),
+                        (MutationOperator::Insert, "Insert"),
+                    ]);
+                    Controls::name_widget(ui, "Adaptive", egui::Checkbox::new(&mut ga_settings.is_adaptive_mutation, ""));
 
                     Controls::section(ui, "Display");
 
                     Controls::name_widget(ui, "Rat's Nest", egui::Checkbox::new(&mut status.show_rats_nest, ""));
                     Controls::name_widget(ui, "Current", egui::Checkbox::new(&mut status.show_only_failed, ""));
                     Controls::name_widget(ui, "Only Failed", egui::Checkbox::new(&mut status.show_current_layout, ""));
+                    Controls::name_widget(ui, "Cost Regions", egui::Checkbox::new(&mut status.show_cost_regions, ""));
 
                     Controls::section(ui, "Misc");
 
@@ -124,10 +203,104 @@ impl<'a> Controls {
                     Controls::name_widget(ui, "Pause", egui::Checkbox::new(&mut status.pause_router, ""));
                     Controls::name_float(ui, "ms/frame", status.ms_per_frame);
                 });
+
+                Controls::search_progress(ui, &status.generation_stats_vec);
+                Controls::pareto_front(ui, status);
             });
         });
     }
 
+    // Plots of the GA statistics, oldest generation on the left.
+    fn search_progress(ui: &mut Ui, stats_vec: &[GenerationStats]) {
+        ui.add_space(8.0);
+        ui.heading("Search Progress");
+        let best_color = Color32::from_rgb(0, 160, 0);
+        let mean_color = Color32::GRAY;
+        let worst_color = Color32::from_rgb(200, 0, 0);
+        Controls::plot(ui, "Completed Routes", &[
+            (stats_vec.iter().map(|s| s.best_completed_routes as f64).collect(), best_color),
+            (stats_vec.iter().map(|s| s.mean_completed_routes).collect(), mean_color),
+            (stats_vec.iter().map(|s| s.worst_completed_routes as f64).collect(), worst_color),
+        ]);
+        Controls::plot(ui, "Cost", &[
+            (stats_vec.iter().map(|s| s.best_cost as f64).collect(), best_color),
+            (stats_vec.iter().map(|s| s.mean_cost).collect(), mean_color),
+        ]);
+        Controls::plot(ui, "Diversity", &[
+            (stats_vec.iter().map(|s| s.n_distinct_orderings as f64).collect(), best_color),
+        ]);
+    }
+
+    // The layouts in the Pareto archive, with the most completed routes first. Clicking
+    // a layout shows it instead of the best layout, and clicking it again goes back to
+    // the best layout.
+    fn pareto_front(ui: &mut Ui, status: &mut status::Status) {
+        ui.add_space(8.0);
+        ui.heading("Pareto Front");
+        let mut objectives_vec = status.pareto_objectives_vec.clone();
+        objectives_vec.sort_by_key(|(id, o)| (std::cmp::Reverse(o.n_completed_routes), o.n_cuts, o.n_wires, *id));
+        egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("pareto-grid").striped(true).show(ui, |ui| {
+                for title in ["Layout", "Routes", "Cuts", "Wires", "Wire Len", "Area"] {
+                    ui.strong(title);
+                }
+                ui.end_row();
+                for (id, o) in &objectives_vec {
+                    let is_selected = status.selected_pareto_layout_id == Some(*id);
+                    if ui.selectable_label(is_selected, format!("#{}", id)).clicked() {
+                        status.selected_pareto_layout_id = if is_selected { None } else { Some(*id) };
+                    }
+                    for v in [o.n_completed_routes, o.n_cuts, o.n_wires, o.wire_len, o.used_area] {
+                        ui.label(v.to_formatted_string(&Locale::en));
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+        if let Some(id) = status.selected_pareto_layout_id {
+            if ui.button(format!("Export #{}", id)).clicked() {
+                status.is_pareto_export_requested = true;
+            }
+        }
+    }
+
+    // Draw the series as lines, scaled so that together they fill the plot. The min and
+    // max values are printed in the corners.
+    fn plot(ui: &mut Ui, title: &str, series_vec: &[(Vec<f64>, Color32)]) {
+        ui.label(title);
+        let size = egui::vec2(ui.available_width(), 60.0);
+        let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(rect, 0.0, Stroke::new(1.0, ui.visuals().weak_text_color()));
+        let value_iter = series_vec.iter().flat_map(|(v, _)| v.iter().copied());
+        let min = value_iter.clone().fold(f64::INFINITY, f64::min);
+        let max = value_iter.fold(f64::NEG_INFINITY, f64::max);
+        if !min.is_finite() {
+            return;
+        }
+        let range = (max - min).max(1.0);
+        for (value_vec, color) in series_vec {
+            if value_vec.len() < 2 {
+                continue;
+            }
+            let x_step = rect.width() / (value_vec.len() - 1) as f32;
+            let point_vec = value_vec
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let y = rect.bottom() - rect.height() * ((v - min) / range) as f32;
+                    Pos2::new(rect.left() + x_step * i as f32, y)
+                })
+                .collect();
+            painter.add(Shape::line(point_vec, Stroke::new(1.5, *color)));
+        }
+        let font_id = FontId::monospace(10.0);
+        let text_color = ui.visuals().text_color();
+        let margin = egui::vec2(3.0, 2.0);
+        painter.text(rect.left_top() + margin, Align2::LEFT_TOP, format!("{:.0}", max), font_id.clone(), text_color);
+        painter.text(rect.left_bottom() + egui::vec2(margin.x, -margin.y), Align2::LEFT_BOTTOM, format!("{:.0}", min), font_id, text_color);
+    }
+
     fn header(ui: &mut Ui, s: &str, first: bool) {
         if !first {
             ui.end_row();
@@ -163,12 +336,29 @@ impl<'a> Controls {
         ui.end_row();
     }
 
+    fn name_str(ui: &mut Ui, name: &str, s: &str) {
+        ui.label(format!("    {}", name));
+        Controls::highlighted_label(ui, s);
+        ui.end_row();
+    }
+
     fn name_widget(ui: &mut Ui, name: &str, widget: impl egui::Widget) {
         ui.label(format!("    {}", name));
         ui.add(widget);
         ui.end_row();
     }
 
+    fn name_combo<T: PartialEq + Copy>(ui: &mut Ui, name: &str, value: &mut T, option_vec: &[(T, &str)]) {
+        ui.label(format!("    {}", name));
+        let selected_text = option_vec.iter().find(|(v, _)| v == value).map_or("", |(_, s)| s);
+        egui::ComboBox::from_id_source(name).selected_text(selected_text).show_ui(ui, |ui| {
+            for (v, s) in option_vec {
+                ui.selectable_value(value, *v, *s);
+            }
+        });
+        ui.end_row();
+    }
+
     // Draw a non-interactive value using a disabled button widget
     fn highlighted_label(ui: &mut Ui, s: &str) {
         let prefix = "  ";
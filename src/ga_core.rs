@@ -1,29 +1,57 @@
 use rand::distributions::{Distribution, Uniform};
-use rand::rngs::StdRng;
-use rand::{thread_rng, Rng, SeedableRng};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use crate::checkpoint::{format_rng, CheckpointLine, CheckpointReader, CheckpointWriter};
+use crate::ga_stats::{GenerationStats, StatsHistory};
+use crate::settings::{CrossoverOperator, GaSettings, MutationOperator};
 use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
 
 //
 // Random
 //
+// All the random generators are seeded, so that a run can be repeated exactly. The
+// population holds the single seeded generator from which the seeds for all the
+// other generators are drawn. ChaCha is used because, unlike StdRng, its output is
+// guaranteed to stay the same between versions of rand.
 
 type GeneIdx = usize;
 
 #[derive(Clone)]
 pub struct RandomIntGenerator {
-    random_engine: StdRng,
+    random_engine: ChaCha8Rng,
     uniform_distribution: Uniform<usize>,
+    max_exclusive: usize,
 }
 
 impl RandomIntGenerator {
-    pub fn new(max_exclusive: usize) -> Self {
+    pub fn new(max_exclusive: usize, seed: u64) -> Self {
         Self {
-            random_engine: StdRng::from_entropy(),
+            random_engine: ChaCha8Rng::seed_from_u64(seed),
             uniform_distribution: Uniform::new(0, max_exclusive),
+            max_exclusive,
         }
     }
 
+    // Checkpoint values: The range, followed by the state of the engine.
+    pub fn format(&self) -> Vec<String> {
+        let mut value_vec = vec![self.max_exclusive.to_string()];
+        value_vec.extend(format_rng(&self.random_engine));
+        value_vec
+    }
+
+    pub fn parse(line: &CheckpointLine, first_idx: usize) -> Result<Self, String> {
+        let max_exclusive: usize = line.get(first_idx)?;
+        if max_exclusive == 0 {
+            return Err(line.error("empty random range"));
+        }
+        Ok(Self {
+            random_engine: line.get_rng(first_idx + 1)?,
+            uniform_distribution: Uniform::new(0, max_exclusive),
+            max_exclusive,
+        })
+    }
+
     pub fn get_random_int(&mut self) -> usize {
         assert_ne!(self.uniform_distribution, Uniform::new_inclusive(0, 0));
         self.uniform_distribution.sample(&mut self.random_engine)
@@ -32,14 +60,14 @@ impl RandomIntGenerator {
 
 pub struct RandomFloatGenerator {
     // random_engine: rand::rngs::ThreadRng,
-    random_engine: StdRng,
+    random_engine: ChaCha8Rng,
     uniform_distribution: Uniform<f64>,
 }
 
 impl RandomFloatGenerator {
-    pub fn new() -> Self {
+    pub fn new(seed: u64) -> Self {
         Self {
-            random_engine: StdRng::from_entropy(),
+            random_engine: ChaCha8Rng::seed_from_u64(seed),
             uniform_distribution: Uniform::new(0.0, 1.0),
         }
     }
@@ -47,6 +75,17 @@ impl RandomFloatGenerator {
     pub fn get_normalized_random(&mut self) -> f64 {
         self.random_engine.sample(self.uniform_distribution)
     }
+
+    pub fn format(&self) -> Vec<String> {
+        format_rng(&self.random_engine)
+    }
+
+    pub fn parse(line: &CheckpointLine, first_idx: usize) -> Result<Self, String> {
+        Ok(Self {
+            random_engine: line.get_rng(first_idx)?,
+            uniform_distribution: Uniform::new(0.0, 1.0),
+        })
+    }
 }
 
 //
@@ -76,18 +115,21 @@ pub struct Organism {
     n_genes: usize,
     pub n_completed_routes: usize,
     pub completed_route_cost: usize,
+    // Set when the fitness has been assigned.
+    pub is_evaluated: bool,
     gene_vec: GeneVec,
     random_gene_selector: RandomIntGenerator,
 }
 
 impl Organism {
-    pub fn new(n_genes: usize) -> Self {
+    pub fn new(n_genes: usize, seed: u64) -> Self {
         Self {
             n_genes,
             n_completed_routes: 0,
             completed_route_cost: 0,
+            is_evaluated: false,
             gene_vec: Vec::new(),
-            random_gene_selector: RandomIntGenerator::new(n_genes),
+            random_gene_selector: RandomIntGenerator::new(n_genes, seed),
         }
     }
 
@@ -103,10 +145,77 @@ impl Organism {
         self.random_gene_selector.get_random_int()
     }
 
-    pub fn mutate(&mut self) {
-        let dependent_idx = self.random_gene_selector.get_random_int();
-        let dependency_idx = self.random_gene_selector.get_random_int();
-        self.gene_vec[dependent_idx] = dependency_idx;
+    pub fn mutate(&mut self, mutation_operator: MutationOperator) {
+        match mutation_operator {
+            MutationOperator::Dependency => {
+                let dependent_idx = self.random_gene_selector.get_random_int();
+                let dependency_idx = self.random_gene_selector.get_random_int();
+                self.gene_vec[dependent_idx] = dependency_idx;
+            }
+            MutationOperator::Swap => {
+                let a_idx = self.random_gene_selector.get_random_int();
+                let b_idx = self.random_gene_selector.get_random_int();
+                self.gene_vec.swap(a_idx, b_idx);
+            }
+            MutationOperator::Insert => {
+                let moved_idx = self.random_gene_selector.get_random_int();
+                let after_idx = self.random_gene_selector.get_random_int();
+                if moved_idx == after_idx {
+                    return;
+                }
+                let old_dependency_idx = self.gene_vec[moved_idx];
+                for gene in self.gene_vec.iter_mut().filter(|gene| **gene == moved_idx) {
+                    *gene = old_dependency_idx;
+                }
+                self.gene_vec[moved_idx] = after_idx;
+            }
+        }
+    }
+
+    // The gene and the genes it depends on, directly or indirectly. Stops when the
+    // chain loops back on itself.
+    pub fn get_dependency_chain(&self, gene_idx: GeneIdx) -> Vec<GeneIdx> {
+        let mut chain_vec = Vec::new();
+        let mut is_in_chain_vec = vec![false; self.gene_vec.len()];
+        let mut i = gene_idx;
+        while !is_in_chain_vec[i] {
+            is_in_chain_vec[i] = true;
+            chain_vec.push(i);
+            i = self.gene_vec[i];
+        }
+        chain_vec
+    }
+
+    // A copy of the organism for a circuit with a different set of connections.
+    // gene_map holds the new gene index for each of the old genes, or None if the
+    // connection has been removed. The dependencies between the remaining genes are
+    // kept. A gene that depended on a removed gene gets the dependency of the removed
+    // gene instead, so it stays in about the same place in the ordering. Genes for new
+    // connections, and genes that end up with no dependency, get a random one.
+    pub fn remap(&self, gene_map: &[Option<GeneIdx>], n_genes: usize, seed: u64) -> Organism {
+        let mut organism = Organism::new(n_genes, seed);
+        let mut gene_vec: Vec<Option<GeneIdx>> = vec![None; n_genes];
+        for (old_idx, new_idx) in gene_map.iter().enumerate() {
+            if let Some(new_idx) = *new_idx {
+                gene_vec[new_idx] = self
+                    .get_dependency_chain(self.gene_vec[old_idx])
+                    .into_iter()
+                    .find_map(|i| gene_map[i]);
+            }
+        }
+        organism.gene_vec = gene_vec
+            .into_iter()
+            .map(|gene| gene.unwrap_or_else(|| organism.random_gene_selector.get_random_int()))
+            .collect();
+        organism
+    }
+
+    // Greater is fitter: More completed routes, or the same number of completed routes
+    // at a lower cost.
+    pub fn cmp_fitness(&self, other: &Self) -> std::cmp::Ordering {
+        self.n_completed_routes
+            .cmp(&other.n_completed_routes)
+            .then(other.completed_route_cost.cmp(&self.completed_route_cost))
     }
 
     pub fn calc_connection_idx_vec(&self) -> GeneVec {
@@ -115,6 +224,34 @@ impl Organism {
         gene_vec
     }
 
+    // Checkpoint values: The fitness, the state of the gene selector and the genes.
+    pub fn format(&self) -> Vec<String> {
+        let mut value_vec = vec![
+            self.n_completed_routes.to_string(),
+            self.completed_route_cost.to_string(),
+            (self.is_evaluated as usize).to_string(),
+        ];
+        value_vec.extend(self.random_gene_selector.format());
+        value_vec.extend(self.gene_vec.iter().map(|gene| gene.to_string()));
+        value_vec
+    }
+
+    pub fn parse(line: &CheckpointLine, n_genes: usize) -> Result<Self, String> {
+        let random_gene_selector = RandomIntGenerator::parse(line, 3)?;
+        let gene_vec: GeneVec = line.get_vec(7)?;
+        if gene_vec.len() != n_genes || gene_vec.iter().any(|&gene| gene >= n_genes) {
+            return Err(line.error("invalid genes"));
+        }
+        Ok(Self {
+            n_genes,
+            n_completed_routes: line.get(0)?,
+            completed_route_cost: line.get(1)?,
+            is_evaluated: line.get::<usize>(2)? != 0,
+            gene_vec,
+            random_gene_selector,
+        })
+    }
+
     pub fn dump(&self) {
         print!(
             "nCompletedRoutes={} completedRouteCost={} nGenes={} genes=",
@@ -192,76 +329,357 @@ impl OrganismPair {
 type OrganismIdx = usize;
 type OrganismVec = Vec<Organism>;
 
+// Adaptive mutation: When the best fitness hasn't improved for this many generations,
+// the mutation rate is doubled, up to the max. It drops back to the configured rate
+// as soon as the best fitness improves. With the steady-state GA, a generation is
+// counted each time as many organisms as there are in the population have been bred.
+const N_STAGNANT_GENERATIONS_BEFORE_ADAPTING: usize = 5;
+const MAX_ADAPTIVE_MUTATION_RATE: f64 = 0.5;
+
 pub struct Population {
     pub n_organisms_in_population: usize,
     pub crossover_rate: f64,
     pub mutation_rate: f64,
+    pub crossover_operator: CrossoverOperator,
+    pub mutation_operator: MutationOperator,
+    pub is_adaptive_mutation: bool,
+    // The mutation rate in use, which differs from mutation_rate while adapting.
+    pub current_mutation_rate: f64,
+    pub n_tournament_candidates: usize,
+    pub n_elites: usize,
     pub n_genes_per_organism: usize,
     pub random_gene_selector: RandomIntGenerator,
     pub random_organism_selector: RandomIntGenerator,
     pub organism_vec: OrganismVec,
+    // Number of generations since the last reset. With the steady-state GA, counted
+    // the same way as for the adaptive mutation.
+    pub n_generations: usize,
+    pub stats_history: StatsHistory,
     rnd: RandomFloatGenerator,
+    seed_rng: ChaCha8Rng,
+    best_fitness: Option<(usize, usize)>,
+    n_stagnant_generations: usize,
+    n_bred_organisms: usize,
 }
 
 impl Population {
-    pub fn new(n_organisms_in_population: usize, crossover_rate: f64, mutation_rate: f64) -> Self {
-        assert_eq!(n_organisms_in_population % 2, 0); // Must have even number of organisms
-        Self {
-            n_organisms_in_population,
-            crossover_rate,
-            mutation_rate,
+    pub fn new(ga_settings: &GaSettings, seed: u64) -> Self {
+        let mut seed_rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut population = Self {
+            n_organisms_in_population: 0,
+            crossover_rate: 0.0,
+            mutation_rate: 0.0,
+            crossover_operator: CrossoverOperator::SinglePoint,
+            mutation_operator: MutationOperator::Dependency,
+            is_adaptive_mutation: false,
+            current_mutation_rate: 0.0,
+            n_tournament_candidates: 0,
+            n_elites: 0,
             n_genes_per_organism: 0,
-            random_gene_selector: RandomIntGenerator::new(1),
-            random_organism_selector: RandomIntGenerator::new(n_organisms_in_population),
+            random_gene_selector: RandomIntGenerator::new(1, seed_rng.gen()),
+            random_organism_selector: RandomIntGenerator::new(1, seed_rng.gen()),
             organism_vec: Vec::new(),
-            rnd: RandomFloatGenerator::new(),
+            n_generations: 0,
+            stats_history: StatsHistory::new(),
+            rnd: RandomFloatGenerator::new(seed_rng.gen()),
+            seed_rng,
+            best_fitness: None,
+            n_stagnant_generations: 0,
+            n_bred_organisms: 0,
+        };
+        population.set_settings(ga_settings);
+        population
+    }
+
+    // The new population size takes effect with the next generation. An adapted
+    // mutation rate is kept unless the mutation settings have changed.
+    pub fn set_settings(&mut self, ga_settings: &GaSettings) {
+        if self.mutation_rate != ga_settings.mutation_rate || !ga_settings.is_adaptive_mutation {
+            self.current_mutation_rate = ga_settings.mutation_rate;
         }
+        self.n_organisms_in_population = ga_settings.n_organisms_in_population;
+        self.crossover_rate = ga_settings.crossover_rate;
+        self.mutation_rate = ga_settings.mutation_rate;
+        self.crossover_operator = ga_settings.crossover_operator;
+        self.mutation_operator = ga_settings.mutation_operator;
+        self.is_adaptive_mutation = ga_settings.is_adaptive_mutation;
+        self.n_tournament_candidates = ga_settings.n_tournament_candidates;
+        self.n_elites = ga_settings.n_elites.min(ga_settings.n_organisms_in_population);
     }
 
     pub fn reset(&mut self, n_genes_per_organism: usize) {
         self.n_genes_per_organism = n_genes_per_organism;
-        self.random_gene_selector = RandomIntGenerator::new(n_genes_per_organism);
+        self.random_gene_selector = RandomIntGenerator::new(n_genes_per_organism, self.seed_rng.gen());
         self.create_random_population();
+        self.random_organism_selector = RandomIntGenerator::new(self.organism_vec.len(), self.seed_rng.gen());
+        self.n_generations = 0;
+        self.stats_history.clear();
+        self.best_fitness = None;
+        self.n_stagnant_generations = 0;
+        self.n_bred_organisms = 0;
+        self.current_mutation_rate = self.mutation_rate;
+    }
+
+    // Keep the organisms when the connections in the circuit change. See
+    // Organism::remap(). The fitness is no longer valid, so all the organisms must be
+    // evaluated again.
+    pub fn remap(&mut self, gene_map: &[Option<GeneIdx>], n_genes_per_organism: usize) {
+        self.n_genes_per_organism = n_genes_per_organism;
+        self.random_gene_selector = RandomIntGenerator::new(n_genes_per_organism, self.seed_rng.gen());
+        let old_organism_vec = std::mem::take(&mut self.organism_vec);
+        for old_organism in &old_organism_vec {
+            let organism = old_organism.remap(gene_map, n_genes_per_organism, self.seed_rng.gen());
+            self.organism_vec.push(organism);
+        }
+        self.random_organism_selector = RandomIntGenerator::new(self.organism_vec.len(), self.seed_rng.gen());
+        self.best_fitness = None;
+        self.n_stagnant_generations = 0;
+        self.n_bred_organisms = 0;
+        self.current_mutation_rate = self.mutation_rate;
+    }
+
+    // The settings are not included. They come from the command line or the GUI, also
+    // when resuming.
+    pub fn write_checkpoint(&self, writer: &mut CheckpointWriter) {
+        let best_fitness = match self.best_fitness {
+            Some((n_completed_routes, completed_route_cost)) => {
+                vec![n_completed_routes.to_string(), completed_route_cost.to_string()]
+            }
+            None => vec!["-".to_string(), "-".to_string()],
+        };
+        let mut value_vec = vec![
+            self.n_genes_per_organism.to_string(),
+            self.organism_vec.len().to_string(),
+            self.n_generations.to_string(),
+            self.n_stagnant_generations.to_string(),
+            self.n_bred_organisms.to_string(),
+            self.current_mutation_rate.to_string(),
+        ];
+        value_vec.extend(best_fitness);
+        writer.write("population", value_vec);
+        writer.write("seed_rng", format_rng(&self.seed_rng));
+        writer.write("random_gene_selector", self.random_gene_selector.format());
+        writer.write("random_organism_selector", self.random_organism_selector.format());
+        writer.write("rnd", self.rnd.format());
+        for organism in &self.organism_vec {
+            writer.write("organism", organism.format());
+        }
     }
 
+    // Everything is read before anything is changed, so the population is left as it
+    // was on error.
+    pub fn read_checkpoint(&mut self, reader: &mut CheckpointReader, n_genes_per_organism: usize) -> Result<(), String> {
+        let line = reader.read("population")?;
+        if line.get::<usize>(0)? != n_genes_per_organism {
+            return Err(line.error("the number of genes does not match the number of connections"));
+        }
+        let n_organisms: usize = line.get(1)?;
+        let n_generations = line.get(2)?;
+        let n_stagnant_generations = line.get(3)?;
+        let n_bred_organisms = line.get(4)?;
+        let current_mutation_rate = line.get(5)?;
+        let best_fitness = if line.get::<String>(6)? == "-" {
+            None
+        } else {
+            Some((line.get(6)?, line.get(7)?))
+        };
+        if n_genes_per_organism == 0 || n_organisms < 2 {
+            return Err(line.error("empty population"));
+        }
+        let seed_rng = reader.read("seed_rng")?.get_rng(0)?;
+        let random_gene_selector = RandomIntGenerator::parse(&reader.read("random_gene_selector")?, 0)?;
+        let random_organism_selector = RandomIntGenerator::parse(&reader.read("random_organism_selector")?, 0)?;
+        let rnd = RandomFloatGenerator::parse(&reader.read("rnd")?, 0)?;
+        let mut organism_vec = OrganismVec::new();
+        for _ in 0..n_organisms {
+            organism_vec.push(Organism::parse(&reader.read("organism")?, n_genes_per_organism)?);
+        }
+
+        self.n_genes_per_organism = n_genes_per_organism;
+        self.organism_vec = organism_vec;
+        self.n_generations = n_generations;
+        self.n_stagnant_generations = n_stagnant_generations;
+        self.n_bred_organisms = n_bred_organisms;
+        self.current_mutation_rate = current_mutation_rate;
+        self.best_fitness = best_fitness;
+        self.seed_rng = seed_rng;
+        self.random_gene_selector = random_gene_selector;
+        self.random_organism_selector = random_organism_selector;
+        self.rnd = rnd;
+        Ok(())
+    }
+
+    // The elites are copied over unchanged. The rest of the new generation is bred
+    // from parents selected by tournament from the whole current generation,
+    // including the elites.
     pub fn next_generation(&mut self) {
-        let mut new_generation_vec = Vec::new();
+        // Parents are selected from the current generation, which may have a
+        // different size than the next one if the settings have changed.
+        self.random_organism_selector = RandomIntGenerator::new(self.organism_vec.len(), self.seed_rng.gen());
+        self.end_generation();
+        let mut new_generation_vec = self.select_elites();
         let mut n_mutations = 0;
-        for _ in 0..self.n_organisms_in_population / 2 {
+        while new_generation_vec.len() < self.n_organisms_in_population {
             let crossover_rate = self.crossover_rate;
-            let mutation_rate = self.mutation_rate;
-            let mut pair = self.select_pair_tournament(2);
+            let mutation_rate = self.current_mutation_rate;
+            let mut pair = self.select_pair_tournament(self.n_tournament_candidates);
             if self.rnd.get_normalized_random() < crossover_rate {
                 self.crossover(&mut pair);
             }
             if self.rnd.get_normalized_random() < mutation_rate {
-                pair.a.mutate();
+                pair.a.mutate(self.mutation_operator);
                 n_mutations += 1;
             }
             if self.rnd.get_normalized_random() < mutation_rate {
-                pair.b.mutate();
+                pair.b.mutate(self.mutation_operator);
                 n_mutations += 1;
             }
+            pair.a.is_evaluated = false;
+            pair.b.is_evaluated = false;
             new_generation_vec.push(pair.a);
-            new_generation_vec.push(pair.b);
+            if new_generation_vec.len() < self.n_organisms_in_population {
+                new_generation_vec.push(pair.b);
+            }
         }
         assert_eq!(new_generation_vec.len(), self.n_organisms_in_population);
         self.organism_vec = new_generation_vec;
     }
 
+    // The n_elites fittest organisms, fittest first. Ties keep the population order.
+    fn select_elites(&self) -> OrganismVec {
+        let mut organism_idx_vec: Vec<OrganismIdx> = (0..self.organism_vec.len()).collect();
+        organism_idx_vec.sort_by(|&a, &b| self.organism_vec[b].cmp_fitness(&self.organism_vec[a]));
+        organism_idx_vec
+            .into_iter()
+            .take(self.n_elites)
+            .map(|i| self.organism_vec[i].clone())
+            .collect()
+    }
+
     pub fn create_random_population(&mut self) {
         self.organism_vec.clear();
         for _ in 0..self.n_organisms_in_population {
-            let mut organism = Organism::new(self.n_genes_per_organism);
-            organism.create_random();
+            let organism = self.create_random_organism();
             self.organism_vec.push(organism);
         }
     }
 
+    pub fn create_random_organism(&mut self) -> Organism {
+        let mut organism = Organism::new(self.n_genes_per_organism, self.seed_rng.gen());
+        organism.create_random();
+        organism
+    }
+
+    // Breed a single organism from parents selected by tournament, for the
+    // steady-state GA. All the organisms in the population must have been evaluated.
+    pub fn breed_organism(&mut self) -> Organism {
+        self.n_bred_organisms += 1;
+        if self.n_bred_organisms.is_multiple_of(self.n_organisms_in_population) {
+            self.end_generation();
+        }
+        let mut pair = self.select_pair_tournament(self.n_tournament_candidates);
+        if self.rnd.get_normalized_random() < self.crossover_rate {
+            self.crossover(&mut pair);
+        }
+        if self.rnd.get_normalized_random() < self.current_mutation_rate {
+            pair.a.mutate(self.mutation_operator);
+        }
+        pair.a.is_evaluated = false;
+        pair.a
+    }
+
+    // Replace the least fit of the evaluated organisms with the given one, if the given
+    // one is fitter. Returns true if it was replaced.
+    pub fn replace_least_fit(&mut self, organism: Organism) -> bool {
+        let least_fit_idx = (0..self.organism_vec.len())
+            .filter(|&i| self.organism_vec[i].is_evaluated)
+            .min_by(|&a, &b| self.organism_vec[a].cmp_fitness(&self.organism_vec[b]));
+        match least_fit_idx {
+            Some(i) if organism.cmp_fitness(&self.organism_vec[i]) == std::cmp::Ordering::Greater => {
+                self.organism_vec[i] = organism;
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn crossover(&mut self, pair: &mut OrganismPair) {
-        let cross_idx = pair.a.get_random_crossover_point();
-        for i in cross_idx..pair.a.gene_vec.len() {
-            std::mem::swap(&mut pair.a.gene_vec[i], &mut pair.b.gene_vec[i]);
+        match self.crossover_operator {
+            CrossoverOperator::SinglePoint => {
+                let cross_idx = pair.a.get_random_crossover_point();
+                for i in cross_idx..pair.a.gene_vec.len() {
+                    std::mem::swap(&mut pair.a.gene_vec[i], &mut pair.b.gene_vec[i]);
+                }
+            }
+            CrossoverOperator::Uniform => {
+                for i in 0..pair.a.gene_vec.len() {
+                    if self.rnd.get_normalized_random() < 0.5 {
+                        std::mem::swap(&mut pair.a.gene_vec[i], &mut pair.b.gene_vec[i]);
+                    }
+                }
+            }
+            CrossoverOperator::DependencyPreserving => {
+                // Swapping the genes in both chains gives each child the complete
+                // chain of the other parent.
+                let gene_idx = pair.a.get_random_crossover_point();
+                let mut chain_vec = pair.a.get_dependency_chain(gene_idx);
+                chain_vec.extend(pair.b.get_dependency_chain(gene_idx));
+                chain_vec.sort();
+                chain_vec.dedup();
+                for i in chain_vec {
+                    std::mem::swap(&mut pair.a.gene_vec[i], &mut pair.b.gene_vec[i]);
+                }
+            }
+        }
+    }
+
+    // Called when a generation has been evaluated, before the next one is bred.
+    fn end_generation(&mut self) {
+        self.update_adaptive_mutation();
+        self.record_stats();
+        self.n_generations += 1;
+    }
+
+    fn record_stats(&mut self) {
+        if !self.stats_history.is_recorded(self.n_generations) {
+            return;
+        }
+        let mut evaluated_vec: Vec<&Organism> =
+            self.organism_vec.iter().filter(|organism| organism.is_evaluated).collect();
+        evaluated_vec.sort_by(|a, b| b.cmp_fitness(a));
+        let fitness_vec: Vec<(usize, usize)> = evaluated_vec
+            .iter()
+            .map(|organism| (organism.n_completed_routes, organism.completed_route_cost))
+            .collect();
+        let ordering_set = evaluated_vec.iter().map(|organism| organism.calc_connection_idx_vec()).collect();
+        let time = self.stats_history.get_time();
+        if let Some(stats) = GenerationStats::new(self.n_generations, time, &fitness_vec, &ordering_set) {
+            self.stats_history.push(stats);
+        }
+    }
+
+    fn update_adaptive_mutation(&mut self) {
+        let best_fitness = self
+            .organism_vec
+            .iter()
+            .filter(|organism| organism.is_evaluated)
+            .max_by(|a, b| a.cmp_fitness(b))
+            .map(|organism| (organism.n_completed_routes, organism.completed_route_cost));
+        let is_improved = match (best_fitness, self.best_fitness) {
+            (Some(best), Some(prev_best)) => best.0 > prev_best.0 || (best.0 == prev_best.0 && best.1 < prev_best.1),
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if is_improved {
+            self.best_fitness = best_fitness;
+            self.n_stagnant_generations = 0;
+            self.current_mutation_rate = self.mutation_rate;
+        } else {
+            self.n_stagnant_generations += 1;
+            if self.is_adaptive_mutation && self.n_stagnant_generations >= N_STAGNANT_GENERATIONS_BEFORE_ADAPTING {
+                self.n_stagnant_generations = 0;
+                self.current_mutation_rate = (self.current_mutation_rate * 2.0).min(MAX_ADAPTIVE_MUTATION_RATE);
+            }
         }
     }
 
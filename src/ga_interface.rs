@@ -1,4 +1,10 @@
+use crate::checkpoint::{CheckpointReader, CheckpointWriter};
+use crate::circuit::Circuit;
 use crate::ga_core::{Organism, Population};
+use crate::ga_stats::StatsHistory;
+use crate::ordering_search::{map_connections, ConnectionIdxVec, Fitness, OrderingIdx, OrderingSearch};
+use crate::settings::{GaSettings, GaStrategy, OrderingSearchStrategy};
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::vec::Vec;
@@ -17,6 +23,8 @@ use std::vec::Vec;
 // of orderings can be retrieved. The result is returned as a simple vector of
 // connection indexes.
 //
+// The black box is the OrderingSearch trait, and this is the GA implementation of it.
+//
 // As all operations on the underlying GA objects are performed through this
 // class, the locking required by this class protects the rest of the GA system.
 //
@@ -27,10 +35,12 @@ use std::vec::Vec;
 // follows:
 //
 // - The client creates a single global instance of GeneticAlgorithm.
-// - The client calls reset() whenever the layout changes, which sets up an
-// initial population with randomized genes.
-// - TODO: When the layout changes, maybe it's better to just keep going on the
-// existing population.
+// - The client calls reset() when starting, which sets up an initial population
+// with randomized genes.
+// - When the connections in the circuit change, the client calls warm_start()
+// instead, which keeps going on the existing population. The genes are moved to the
+// new connection indexes, matching the connections by their keys, so that the
+// orderings found so far are kept for the connections that are still there.
 // - The object keeps track of how many organisms there are in the
 // population and how many organisms have received fitness scores.
 // - The object has a single lock and before a thread interacts with the
@@ -56,75 +66,418 @@ use std::vec::Vec;
 // In this situation, reserveOrdering() returns the invalid index of -1, at
 // which point the caller must release the lock and wait a bit before trying
 // again.
+//
+// Many organisms topo-sort to the same ordering, both within a generation and
+// across generations (the elites are always the same). Routing an ordering always
+// gives the same layout, so the fitness for each ordering is kept in a cache, and
+// reserveOrdering() assigns the cached fitness directly to organisms with an
+// ordering that is already in the cache, instead of handing them out for routing.
+// The cache must be cleared when anything that affects routing changes.
+//
+// With the steady-state strategy, there are no generations. Once all the organisms
+// in the initial population have been handed out, reserveOrdering() breeds a new
+// organism each time it's called, and the organism is kept outside of the
+// population until releaseOrdering() has provided its fitness. It then replaces the
+// least fit organism in the population, if it's fitter. Until the initial
+// population has been evaluated, there are no fitness scores to breed from, so
+// random organisms are handed out instead. reserveOrdering() never has to return -1
+// in this mode.
+//
+// When resuming from a checkpoint, the organisms and the fitness cache are restored,
+// and the current generation is handed out again from the start. The fitness of the
+// organisms that were already evaluated is also put in the cache, so only the rest are
+// routed again (unless the cache is disabled).
+//
+// Threads that get -1 wait on the Condvar that is stored together with the lock.
+// releaseOrdering() does not notify it, since the lock is held by the caller, so
+// the caller must notify after releasing the lock.
+
+// Orderings for organisms bred by the steady-state GA are numbered from here, so that
+// they can't be confused with the organisms in the population.
+const FIRST_BRED_ORDERING_IDX: OrderingIdx = usize::MAX / 2;
+
+// Bounded map from ordering to fitness. When full, the oldest entry is dropped.
+//
+// The epoch is bumped each time the cache is cleared. Orderings are stamped with the
+// epoch when they are reserved, so that the fitness of an ordering that was routed
+// before a clear is not inserted after it.
+struct FitnessCache {
+    max_size: usize,
+    epoch: usize,
+    fitness_map: HashMap<ConnectionIdxVec, Fitness>,
+    insertion_order: VecDeque<ConnectionIdxVec>,
+}
+
+impl FitnessCache {
+    fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            epoch: 0,
+            fitness_map: HashMap::new(),
+            insertion_order: VecDeque::new(),
+        }
+    }
+
+    fn get(&self, ordering: &ConnectionIdxVec) -> Option<Fitness> {
+        self.fitness_map.get(ordering).copied()
+    }
 
-type ConnectionIdx = usize;
-type ConnectionIdxVec = Vec<ConnectionIdx>;
-type OrderingIdx = usize;
+    fn insert(&mut self, ordering: ConnectionIdxVec, fitness: Fitness) {
+        if self.max_size == 0 || self.fitness_map.contains_key(&ordering) {
+            return;
+        }
+        self.evict(self.max_size - 1);
+        self.insertion_order.push_back(ordering.clone());
+        self.fitness_map.insert(ordering, fitness);
+    }
+
+    fn write_checkpoint(&self, writer: &mut CheckpointWriter) {
+        writer.write("fitness_cache", vec![self.insertion_order.len().to_string()]);
+        for ordering in &self.insertion_order {
+            let fitness = self.fitness_map[ordering];
+            let mut value_vec = vec![fitness.n_completed_routes.to_string(), fitness.completed_route_cost.to_string()];
+            value_vec.extend(ordering.iter().map(|connection_idx| connection_idx.to_string()));
+            writer.write("fitness", value_vec);
+        }
+    }
+
+    // Returns the entries, oldest first.
+    fn read_checkpoint(reader: &mut CheckpointReader) -> Result<Vec<(ConnectionIdxVec, Fitness)>, String> {
+        let n_entries: usize = reader.read("fitness_cache")?.get(0)?;
+        let mut entry_vec = Vec::new();
+        for _ in 0..n_entries {
+            let line = reader.read("fitness")?;
+            let fitness = Fitness {
+                n_completed_routes: line.get(0)?,
+                completed_route_cost: line.get(1)?,
+            };
+            entry_vec.push((line.get_vec(2)?, fitness));
+        }
+        Ok(entry_vec)
+    }
+
+    fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict(max_size);
+    }
+
+    fn clear(&mut self) {
+        self.epoch += 1;
+        self.fitness_map.clear();
+        self.insertion_order.clear();
+    }
+
+    // Drop the oldest entries until there are no more than the given number left.
+    fn evict(&mut self, max_size: usize) {
+        while self.insertion_order.len() > max_size {
+            let ordering = self.insertion_order.pop_front().unwrap();
+            self.fitness_map.remove(&ordering);
+        }
+    }
+}
 
 pub struct GeneticAlgorithm {
     n_organisms_in_population: usize,
-    crossover_rate: f64,
-    mutation_rate: f64,
     n_connections_in_circuit: usize,
+    // The key of each connection, by connection index. See Connection::key().
+    connection_key_vec: Vec<String>,
     next_ordering_idx: usize,
     n_unprocessed_orderings: usize,
     population: Population,
+    fitness_cache: FitnessCache,
+    // The ordering and the fitness cache epoch for each ordering that has been handed
+    // out, so that the topological sort is only done once per ordering.
+    reserved_ordering_map: HashMap<OrderingIdx, (ConnectionIdxVec, usize)>,
+    n_fitness_cache_hits: usize,
+    strategy: GaStrategy,
+    bred_organism_map: HashMap<OrderingIdx, Organism>,
+    next_bred_ordering_idx: OrderingIdx,
 }
 
 impl GeneticAlgorithm {
-    pub fn new(n_organisms_in_population: usize, crossover_rate: f64, mutation_rate: f64) -> Self {
+    pub fn new(ga_settings: &GaSettings, seed: u64) -> Self {
         Self {
-            n_organisms_in_population,
-            crossover_rate,
-            mutation_rate,
+            n_organisms_in_population: ga_settings.n_organisms_in_population,
             n_connections_in_circuit: 0,
+            connection_key_vec: Vec::new(),
             next_ordering_idx: 0,
             n_unprocessed_orderings: 0,
-            population: Population::new(n_organisms_in_population, crossover_rate, mutation_rate),
+            population: Population::new(ga_settings, seed),
+            fitness_cache: FitnessCache::new(ga_settings.fitness_cache_size),
+            reserved_ordering_map: HashMap::new(),
+            n_fitness_cache_hits: 0,
+            strategy: ga_settings.strategy,
+            bred_organism_map: HashMap::new(),
+            next_bred_ordering_idx: FIRST_BRED_ORDERING_IDX,
+        }
+    }
+
+    // Start over on the current population, with no fitness known.
+    fn set_connections(&mut self, connection_key_vec: Vec<String>) {
+        self.n_connections_in_circuit = connection_key_vec.len();
+        self.connection_key_vec = connection_key_vec;
+        self.fitness_cache.clear();
+        self.bred_organism_map.clear();
+        self.reserved_ordering_map.clear();
+        self.n_organisms_in_population = self.population.organism_vec.len();
+        self.next_ordering_idx = 0;
+        self.n_unprocessed_orderings = self.n_organisms_in_population;
+    }
+
+    fn next_ordering(&mut self) -> Option<OrderingIdx> {
+        if self.next_ordering_idx == self.n_organisms_in_population {
+            match self.strategy {
+                GaStrategy::Generational => {
+                    // Bred organisms may be left over from a switch from steady-state.
+                    let is_all_orderings_released =
+                        self.n_unprocessed_orderings == 0 && self.bred_organism_map.is_empty();
+                    if !is_all_orderings_released {
+                        return None;
+                    }
+                    self.population.next_generation();
+                    self.n_organisms_in_population = self.population.organism_vec.len();
+                    self.n_unprocessed_orderings = self.n_organisms_in_population;
+                    self.next_ordering_idx = 0;
+                }
+                GaStrategy::SteadyState => {
+                    let organism = if self.n_unprocessed_orderings == 0 {
+                        self.population.breed_organism()
+                    } else {
+                        self.population.create_random_organism()
+                    };
+                    let ordering_idx = self.next_bred_ordering_idx;
+                    self.next_bred_ordering_idx += 1;
+                    self.bred_organism_map.insert(ordering_idx, organism);
+                    return Some(ordering_idx);
+                }
+            }
+        }
+        let ordering_idx = self.next_ordering_idx;
+        self.next_ordering_idx += 1;
+        Some(ordering_idx)
+    }
+
+    fn calc_ordering(&self, ordering_idx: OrderingIdx) -> ConnectionIdxVec {
+        if ordering_idx >= FIRST_BRED_ORDERING_IDX {
+            self.bred_organism_map[&ordering_idx].calc_connection_idx_vec()
+        } else {
+            self.population.organism_vec[ordering_idx].calc_connection_idx_vec()
         }
     }
 
-    pub fn reset(&mut self, n_connections_in_circuit: usize) {
-        self.n_connections_in_circuit = n_connections_in_circuit;
-        self.population.reset(n_connections_in_circuit);
+    fn set_fitness(&mut self, ordering_idx: OrderingIdx, fitness: Fitness) {
+        if ordering_idx >= FIRST_BRED_ORDERING_IDX {
+            // Bred organisms handed out before a reset are gone.
+            if let Some(mut organism) = self.bred_organism_map.remove(&ordering_idx) {
+                Self::assign_fitness(&mut organism, fitness);
+                self.population.replace_least_fit(organism);
+            }
+        } else {
+            Self::assign_fitness(&mut self.population.organism_vec[ordering_idx], fitness);
+            self.n_unprocessed_orderings -= 1;
+        }
+    }
+
+    fn assign_fitness(organism: &mut Organism, fitness: Fitness) {
+        organism.n_completed_routes = fitness.n_completed_routes;
+        organism.completed_route_cost = fitness.completed_route_cost;
+        organism.is_evaluated = true;
+    }
+}
+
+impl OrderingSearch for GeneticAlgorithm {
+    fn get_strategy(&self) -> OrderingSearchStrategy {
+        OrderingSearchStrategy::GeneticAlgorithm
+    }
+
+    fn reset(&mut self, circuit: &Circuit) {
+        let connection_key_vec = circuit.gen_connection_key_vec();
+        self.population.reset(connection_key_vec.len());
+        self.set_connections(connection_key_vec);
+    }
+
+    // Keep the population when the connections in the circuit have changed. Falls back
+    // to reset() if there's no population yet, or if none of the connections are left.
+    // The whole population is evaluated again, since connections that are still there
+    // may have moved.
+    fn warm_start(&mut self, circuit: &Circuit) {
+        let connection_key_vec = circuit.gen_connection_key_vec();
+        let gene_map = map_connections(&self.connection_key_vec, &connection_key_vec);
+        if self.n_connections_in_circuit == 0 || gene_map.iter().all(Option::is_none) {
+            self.reset(circuit);
+            return;
+        }
+        self.population.remap(&gene_map, connection_key_vec.len());
+        self.set_connections(connection_key_vec);
+    }
+
+    // The settings take effect from the next generation, so that the orderings that
+    // have already been handed out stay valid.
+    fn set_settings(&mut self, ga_settings: &GaSettings) {
+        self.population.set_settings(ga_settings);
+        self.fitness_cache.set_max_size(ga_settings.fitness_cache_size);
+        self.strategy = ga_settings.strategy;
+    }
+
+    fn clear_fitness_cache(&mut self) {
+        self.fitness_cache.clear();
+    }
+
+    fn get_n_fitness_cache_hits(&self) -> usize {
+        self.n_fitness_cache_hits
+    }
+
+    fn get_n_generations(&self) -> usize {
+        self.population.n_generations
+    }
+
+    fn get_stats_history(&self) -> &StatsHistory {
+        &self.population.stats_history
+    }
+
+    fn write_checkpoint(&self, writer: &mut CheckpointWriter) {
+        writer.write("connections", self.connection_key_vec.clone());
+        self.fitness_cache.write_checkpoint(writer);
+        self.population.write_checkpoint(writer);
+    }
+
+    fn read_checkpoint(&mut self, reader: &mut CheckpointReader) -> Result<(), String> {
+        let line = reader.read("connections")?;
+        let connection_key_vec: Vec<String> = line.get_vec(0)?;
+        let fitness_cache_entry_vec = FitnessCache::read_checkpoint(reader)?;
+        self.population.read_checkpoint(reader, connection_key_vec.len())?;
+        self.n_connections_in_circuit = connection_key_vec.len();
+        self.connection_key_vec = connection_key_vec;
+        self.n_organisms_in_population = self.population.organism_vec.len();
         self.next_ordering_idx = 0;
         self.n_unprocessed_orderings = self.n_organisms_in_population;
+        self.bred_organism_map.clear();
+        self.reserved_ordering_map.clear();
+        self.fitness_cache.clear();
+        for (ordering, fitness) in fitness_cache_entry_vec {
+            self.fitness_cache.insert(ordering, fitness);
+        }
+        for organism in self.population.organism_vec.iter().filter(|organism| organism.is_evaluated) {
+            let fitness = Fitness {
+                n_completed_routes: organism.n_completed_routes,
+                completed_route_cost: organism.completed_route_cost,
+            };
+            self.fitness_cache.insert(organism.calc_connection_idx_vec(), fitness);
+        }
+        Ok(())
     }
 
-    pub fn reserve_ordering(&mut self) -> OrderingIdx {
+    fn reserve_ordering(&mut self) -> OrderingIdx {
         if self.n_connections_in_circuit == 0 {
             return usize::MAX;
         }
-        let is_new_generation_required = self.next_ordering_idx == self.n_organisms_in_population;
-        let is_all_orderings_released = self.n_unprocessed_orderings == 0;
-        if is_new_generation_required {
-            if is_all_orderings_released {
-                self.population.next_generation();
-                self.n_unprocessed_orderings = self.n_organisms_in_population;
-                self.next_ordering_idx = 0;
-            } else {
-                return usize::MAX;
+        // If the population has converged, every organism may be in the cache. To
+        // avoid looping over generations while holding the lock, the cache is skipped
+        // after a full population's worth of hits.
+        let mut n_hits = 0;
+        loop {
+            let ordering_idx = match self.next_ordering() {
+                Some(ordering_idx) => ordering_idx,
+                None => return usize::MAX,
+            };
+            let ordering = self.calc_ordering(ordering_idx);
+            if n_hits < self.n_organisms_in_population {
+                if let Some(fitness) = self.fitness_cache.get(&ordering) {
+                    self.set_fitness(ordering_idx, fitness);
+                    self.n_fitness_cache_hits += 1;
+                    n_hits += 1;
+                    continue;
+                }
             }
+            self.reserved_ordering_map
+                .insert(ordering_idx, (ordering, self.fitness_cache.epoch));
+            return ordering_idx;
         }
-        let result = self.next_ordering_idx;
-        self.next_ordering_idx += 1;
-        result
     }
 
-    pub fn get_ordering(&self, ordering_idx: OrderingIdx) -> ConnectionIdxVec {
+    fn get_ordering(&self, ordering_idx: OrderingIdx) -> ConnectionIdxVec {
         assert_ne!(ordering_idx, usize::MAX); // Must wait and try reserve_ordering() again
         assert_ne!(self.n_connections_in_circuit, 0); // Must call reset() first
-        self.population.organism_vec[ordering_idx].calc_connection_idx_vec()
-    }
-
-    pub fn release_ordering(
-        &mut self,
-        ordering_idx: OrderingIdx,
-        n_completed_routes: usize,
-        completed_route_cost: usize,
-    ) {
-        self.population.organism_vec[ordering_idx].n_completed_routes = n_completed_routes;
-        self.population.organism_vec[ordering_idx].completed_route_cost = completed_route_cost;
-        self.n_unprocessed_orderings -= 1;
+        self.reserved_ordering_map[&ordering_idx].0.clone()
+    }
+
+    fn release_ordering(&mut self, ordering_idx: OrderingIdx, n_completed_routes: usize, completed_route_cost: usize) {
+        let fitness = Fitness {
+            n_completed_routes,
+            completed_route_cost,
+        };
+        // Orderings handed out before a reset are gone.
+        let Some((ordering, epoch)) = self.reserved_ordering_map.remove(&ordering_idx) else {
+            return;
+        };
+        // Routed with the settings from before the cache was cleared.
+        if epoch == self.fitness_cache.epoch {
+            self.fitness_cache.insert(ordering, fitness);
+        }
+        self.set_fitness(ordering_idx, fitness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{Component, Connection, ConnectionPoint};
+    use crate::via::{OffsetVia, Via};
+
+    fn three_connection_circuit() -> Circuit {
+        let mut circuit = Circuit::new();
+        circuit
+            .package_to_pos_map
+            .insert("pin".to_string(), vec![OffsetVia::new(0, 0)]);
+        for (name, x) in [("a", 0), ("b", 2), ("c", 4)] {
+            circuit
+                .component_name_to_component_map
+                .insert(name.to_string(), Component::new("pin".to_string(), Via::new(x, 0)));
+        }
+        let connect = |start: &str, end: &str| {
+            Connection::new(
+                ConnectionPoint::new(start.to_string(), 0),
+                ConnectionPoint::new(end.to_string(), 0),
+            )
+        };
+        circuit.connection_vec = vec![connect("a", "b"), connect("b", "c"), connect("a", "c")];
+        circuit
+    }
+
+    #[test]
+    fn bred_ordering_released_after_a_reset_is_ignored() {
+        let circuit = three_connection_circuit();
+        let mut ga_settings = GaSettings::new();
+        ga_settings.strategy = GaStrategy::SteadyState;
+        ga_settings.n_organisms_in_population = 4;
+        let mut genetic_algorithm = GeneticAlgorithm::new(&ga_settings, 1);
+        genetic_algorithm.reset(&circuit);
+        let mut ordering_idx = genetic_algorithm.reserve_ordering();
+        while ordering_idx < FIRST_BRED_ORDERING_IDX {
+            ordering_idx = genetic_algorithm.reserve_ordering();
+        }
+        genetic_algorithm.reset(&circuit);
+        genetic_algorithm.release_ordering(ordering_idx, 3, 100);
+        assert!(genetic_algorithm.bred_organism_map.is_empty());
+        assert!(genetic_algorithm.fitness_cache.fitness_map.is_empty());
+    }
+
+    #[test]
+    fn ordering_routed_before_the_cache_was_cleared_is_not_cached() {
+        let mut genetic_algorithm = GeneticAlgorithm::new(&GaSettings::new(), 1);
+        genetic_algorithm.reset(&three_connection_circuit());
+        let stale_ordering_idx = genetic_algorithm.reserve_ordering();
+        let ordering_idx = genetic_algorithm.reserve_ordering();
+        genetic_algorithm.clear_fitness_cache();
+        let current_ordering_idx = genetic_algorithm.reserve_ordering();
+        let current_ordering = genetic_algorithm.get_ordering(current_ordering_idx);
+        for ordering_idx in [stale_ordering_idx, ordering_idx, current_ordering_idx] {
+            genetic_algorithm.release_ordering(ordering_idx, 3, 100);
+        }
+        assert_eq!(
+            genetic_algorithm.fitness_cache.insertion_order,
+            VecDeque::from([current_ordering])
+        );
+        assert!(genetic_algorithm.reserved_ordering_map.is_empty());
     }
 }
@@ -0,0 +1,175 @@
+use std::collections::HashSet;
+use std::time::Instant;
+
+// Statistics for how the GA search evolves, recorded once per generation. With the
+// steady-state GA, a generation is counted each time as many organisms as there are
+// in the population have been bred.
+//
+// "Best" and "worst" are the fittest and least fit organisms (see
+// Organism::cmp_fitness()), so the best cost is the cost of the fittest organism, not
+// the lowest cost in the population. Only organisms that have been evaluated are
+// included.
+//
+// The diversity is the number of distinct orderings in the population. Many organisms
+// topo-sort to the same ordering, so this is lower than the number of distinct genomes,
+// and it's what matters for the search.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GenerationStats {
+    pub generation: usize,
+    // Seconds since the GA was reset.
+    pub time: f64,
+    pub best_completed_routes: usize,
+    pub mean_completed_routes: f64,
+    pub worst_completed_routes: usize,
+    pub best_cost: usize,
+    pub mean_cost: f64,
+    pub worst_cost: usize,
+    pub n_distinct_orderings: usize,
+}
+
+impl GenerationStats {
+    pub const CSV_HEADER: &'static str = "generation,time,best_completed_routes,mean_completed_routes,\
+        worst_completed_routes,best_cost,mean_cost,worst_cost,n_distinct_orderings";
+
+    // fitness_vec holds the completed routes and cost of each evaluated organism, fittest
+    // first. Returns None if there are none.
+    pub fn new(
+        generation: usize,
+        time: f64,
+        fitness_vec: &[(usize, usize)],
+        ordering_set: &HashSet<Vec<usize>>,
+    ) -> Option<Self> {
+        let best = fitness_vec.first()?;
+        let worst = fitness_vec.last()?;
+        let n = fitness_vec.len() as f64;
+        Some(Self {
+            generation,
+            time,
+            best_completed_routes: best.0,
+            mean_completed_routes: fitness_vec.iter().map(|f| f.0 as f64).sum::<f64>() / n,
+            worst_completed_routes: worst.0,
+            best_cost: best.1,
+            mean_cost: fitness_vec.iter().map(|f| f.1 as f64).sum::<f64>() / n,
+            worst_cost: worst.1,
+            n_distinct_orderings: ordering_set.len(),
+        })
+    }
+
+    pub fn csv_row(&self) -> String {
+        format!(
+            "{},{:.3},{},{:.2},{},{},{:.2},{},{}",
+            self.generation,
+            self.time,
+            self.best_completed_routes,
+            self.mean_completed_routes,
+            self.worst_completed_routes,
+            self.best_cost,
+            self.mean_cost,
+            self.worst_cost,
+            self.n_distinct_orderings
+        )
+    }
+}
+
+// Long searches can run through millions of generations, so the history is thinned
+// out when it gets full: Every other entry is dropped, and from then on, only every
+// other generation is recorded. The history always covers the whole search, at a
+// resolution that halves each time it fills up.
+const MAX_N_HISTORY_ENTRIES: usize = 10000;
+
+pub struct StatsHistory {
+    stats_vec: Vec<GenerationStats>,
+    generation_stride: usize,
+    start_time: Instant,
+}
+
+impl StatsHistory {
+    pub fn new() -> Self {
+        Self {
+            stats_vec: Vec::new(),
+            generation_stride: 1,
+            start_time: Instant::now(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn get_time(&self) -> f64 {
+        self.start_time.elapsed().as_secs_f64()
+    }
+
+    // Whether the stats for the generation would be kept. Used for skipping the work of
+    // calculating them.
+    pub fn is_recorded(&self, generation: usize) -> bool {
+        generation.is_multiple_of(self.generation_stride)
+    }
+
+    pub fn push(&mut self, stats: GenerationStats) {
+        if !self.is_recorded(stats.generation) {
+            return;
+        }
+        if self.stats_vec.len() == MAX_N_HISTORY_ENTRIES {
+            self.generation_stride *= 2;
+            let stride = self.generation_stride;
+            self.stats_vec.retain(|s| s.generation.is_multiple_of(stride));
+            if !self.is_recorded(stats.generation) {
+                return;
+            }
+        }
+        self.stats_vec.push(stats);
+    }
+
+    pub fn get_stats_vec(&self) -> &Vec<GenerationStats> {
+        &self.stats_vec
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = GenerationStats::CSV_HEADER.to_string();
+        csv.push('\n');
+        for stats in &self.stats_vec {
+            csv.push_str(&stats.csv_row());
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(generation: usize) -> GenerationStats {
+        GenerationStats {
+            generation,
+            ..GenerationStats::default()
+        }
+    }
+
+    #[test]
+    fn stats_are_calculated_from_fittest_first_fitness() {
+        let ordering_set: HashSet<Vec<usize>> = [vec![0, 1], vec![1, 0]].into_iter().collect();
+        let s = GenerationStats::new(3, 1.5, &[(5, 100), (5, 200), (4, 50)], &ordering_set).unwrap();
+        assert_eq!((s.best_completed_routes, s.best_cost), (5, 100));
+        assert_eq!((s.worst_completed_routes, s.worst_cost), (4, 50));
+        assert!((s.mean_completed_routes - 14.0 / 3.0).abs() < 1e-9);
+        assert!((s.mean_cost - 350.0 / 3.0).abs() < 1e-9);
+        assert_eq!(s.n_distinct_orderings, 2);
+        assert_eq!(GenerationStats::new(3, 1.5, &[], &ordering_set), None);
+    }
+
+    #[test]
+    fn full_history_is_thinned_and_still_covers_the_whole_search() {
+        let mut history = StatsHistory::new();
+        for generation in 0..MAX_N_HISTORY_ENTRIES * 3 {
+            history.push(stats(generation));
+        }
+        let stats_vec = history.get_stats_vec();
+        assert!(stats_vec.len() <= MAX_N_HISTORY_ENTRIES);
+        assert_eq!(stats_vec[0].generation, 0);
+        assert!(stats_vec.last().unwrap().generation >= MAX_N_HISTORY_ENTRIES * 3 - 4);
+        assert!(stats_vec.windows(2).all(|w| w[1].generation - w[0].generation == 4));
+    }
+}
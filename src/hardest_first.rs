@@ -0,0 +1,215 @@
+use crate::checkpoint::{format_rng, CheckpointReader, CheckpointWriter};
+use crate::circuit::Circuit;
+use crate::ga_stats::StatsHistory;
+use crate::ordering_search::{ConnectionIdxVec, Fitness, OrderingIdx, OrderingSearch, RoundStats};
+use crate::settings::OrderingSearchStrategy;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+
+// Greedy "route the hardest first" heuristic. Connections that are routed late have to
+// find their way around the ones that were routed before them, so the ones that are
+// hardest to route go first, while the board is still empty.
+//
+// The difficulty of a connection is its length (the Manhattan distance between its
+// pins), plus the number of other pins in the rectangle spanned by its pins. The other
+// pins are in the way, and each of them has to be routed around or shares a strip.
+//
+// The first ordering is sorted strictly by difficulty. The heuristic is only a rough
+// guide, so after that, each ordering is sorted by the difficulty multiplied by a
+// random factor between 1 - DIFFICULTY_NOISE and 1 + DIFFICULTY_NOISE. Connections
+// with similar difficulty then trade places, while the overall order is kept. Nothing
+// is learned from the results, so a warm start is the same as a reset.
+
+const DIFFICULTY_NOISE: f64 = 0.3;
+
+pub struct HardestFirst {
+    random_engine: ChaCha8Rng,
+    // The key of each connection, by connection index. See Connection::key().
+    connection_key_vec: Vec<String>,
+    difficulty_vec: Vec<f64>,
+    n_handed_out_orderings: usize,
+    reserved_ordering_map: HashMap<OrderingIdx, ConnectionIdxVec>,
+    next_ordering_idx: OrderingIdx,
+    round_stats: RoundStats,
+}
+
+impl HardestFirst {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            random_engine: ChaCha8Rng::seed_from_u64(seed),
+            connection_key_vec: Vec::new(),
+            difficulty_vec: Vec::new(),
+            n_handed_out_orderings: 0,
+            reserved_ordering_map: HashMap::new(),
+            next_ordering_idx: 0,
+            round_stats: RoundStats::new(),
+        }
+    }
+
+    fn create_ordering(&mut self) -> ConnectionIdxVec {
+        let noise = if self.n_handed_out_orderings == 0 {
+            0.0
+        } else {
+            DIFFICULTY_NOISE
+        };
+        let mut sort_key_vec: Vec<(f64, usize)> = Vec::new();
+        for (connection_idx, difficulty) in self.difficulty_vec.iter().enumerate() {
+            let factor = 1.0 + noise * self.random_engine.gen_range(-1.0..=1.0);
+            sort_key_vec.push((difficulty * factor, connection_idx));
+        }
+        sort_key_vec.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        sort_key_vec
+            .into_iter()
+            .map(|(_, connection_idx)| connection_idx)
+            .collect()
+    }
+}
+
+fn calc_difficulty_vec(circuit: &Circuit) -> Vec<f64> {
+    let mut component_name_vec: Vec<&String> = circuit.component_name_to_component_map.keys().collect();
+    component_name_vec.sort();
+    let pin_vec: Vec<_> = component_name_vec
+        .into_iter()
+        .flat_map(|component_name| circuit.calc_component_pins(component_name))
+        .collect();
+    circuit
+        .gen_connection_via_vec()
+        .iter()
+        .map(|start_end| {
+            let (start, end) = (start_end.start, start_end.end);
+            let (min_x, max_x) = (start.x.min(end.x), start.x.max(end.x));
+            let (min_y, max_y) = (start.y.min(end.y), start.y.max(end.y));
+            let n_pins_in_the_way = pin_vec
+                .iter()
+                .filter(|pin| {
+                    (min_x..=max_x).contains(&pin.x)
+                        && (min_y..=max_y).contains(&pin.y)
+                        && **pin != start
+                        && **pin != end
+                })
+                .count();
+            ((max_x - min_x) + (max_y - min_y) + n_pins_in_the_way) as f64
+        })
+        .collect()
+}
+
+impl OrderingSearch for HardestFirst {
+    fn get_strategy(&self) -> OrderingSearchStrategy {
+        OrderingSearchStrategy::HardestFirst
+    }
+
+    fn reset(&mut self, circuit: &Circuit) {
+        self.connection_key_vec = circuit.gen_connection_key_vec();
+        self.difficulty_vec = calc_difficulty_vec(circuit);
+        self.n_handed_out_orderings = 0;
+        self.reserved_ordering_map.clear();
+        self.round_stats.clear();
+    }
+
+    fn warm_start(&mut self, circuit: &Circuit) {
+        self.reset(circuit);
+    }
+
+    fn reserve_ordering(&mut self) -> OrderingIdx {
+        if self.connection_key_vec.is_empty() {
+            return usize::MAX;
+        }
+        let ordering = self.create_ordering();
+        self.n_handed_out_orderings += 1;
+        let ordering_idx = self.next_ordering_idx;
+        self.next_ordering_idx += 1;
+        self.reserved_ordering_map.insert(ordering_idx, ordering);
+        ordering_idx
+    }
+
+    fn get_ordering(&self, ordering_idx: OrderingIdx) -> ConnectionIdxVec {
+        self.reserved_ordering_map[&ordering_idx].clone()
+    }
+
+    fn release_ordering(&mut self, ordering_idx: OrderingIdx, n_completed_routes: usize, completed_route_cost: usize) {
+        // Orderings handed out before a reset are gone.
+        if let Some(ordering) = self.reserved_ordering_map.remove(&ordering_idx) {
+            self.round_stats
+                .record(ordering, Fitness::new(n_completed_routes, completed_route_cost));
+        }
+    }
+
+    fn get_n_generations(&self) -> usize {
+        self.round_stats.get_n_rounds()
+    }
+
+    fn get_stats_history(&self) -> &StatsHistory {
+        self.round_stats.get_stats_history()
+    }
+
+    fn write_checkpoint(&self, writer: &mut CheckpointWriter) {
+        writer.write("connections", self.connection_key_vec.clone());
+        writer.write(
+            "difficulty",
+            self.difficulty_vec
+                .iter()
+                .map(|difficulty| difficulty.to_string())
+                .collect(),
+        );
+        let mut value_vec = vec![
+            self.round_stats.get_n_rounds().to_string(),
+            self.n_handed_out_orderings.to_string(),
+        ];
+        value_vec.extend(format_rng(&self.random_engine));
+        writer.write("hardest_first", value_vec);
+    }
+
+    fn read_checkpoint(&mut self, reader: &mut CheckpointReader) -> Result<(), String> {
+        let connection_key_vec: Vec<String> = reader.read("connections")?.get_vec(0)?;
+        let line = reader.read("difficulty")?;
+        let difficulty_vec: Vec<f64> = line.get_vec(0)?;
+        if difficulty_vec.len() != connection_key_vec.len() {
+            return Err(line.error("wrong number of connections"));
+        }
+        let line = reader.read("hardest_first")?;
+        let n_rounds: usize = line.get(0)?;
+        let n_handed_out_orderings: usize = line.get(1)?;
+        let random_engine = line.get_rng(2)?;
+        self.connection_key_vec = connection_key_vec;
+        self.difficulty_vec = difficulty_vec;
+        self.n_handed_out_orderings = n_handed_out_orderings;
+        self.random_engine = random_engine;
+        self.reserved_ordering_map.clear();
+        self.round_stats.set_n_rounds(n_rounds);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{Component, Connection, ConnectionPoint};
+    use crate::via::{OffsetVia, Via};
+
+    #[test]
+    fn long_connections_with_pins_in_the_way_are_routed_first() {
+        let mut circuit = Circuit::new();
+        circuit
+            .package_to_pos_map
+            .insert("pin".to_string(), vec![OffsetVia::new(0, 0)]);
+        for (name, x, y) in [("a", 0, 0), ("b", 2, 0), ("c", 10, 0), ("d", 5, 0), ("e", 0, 10)] {
+            circuit
+                .component_name_to_component_map
+                .insert(name.to_string(), Component::new("pin".to_string(), Via::new(x, y)));
+        }
+        let connect = |start: &str, end: &str| {
+            Connection::new(
+                ConnectionPoint::new(start.to_string(), 0),
+                ConnectionPoint::new(end.to_string(), 0),
+            )
+        };
+        // a-b: Length 2. a-c: Length 10, with b and d in the way. a-e: Length 10.
+        circuit.connection_vec = vec![connect("a", "b"), connect("a", "c"), connect("a", "e")];
+        assert_eq!(calc_difficulty_vec(&circuit), vec![2.0, 12.0, 10.0]);
+        let mut hardest_first = HardestFirst::new(1);
+        hardest_first.reset(&circuit);
+        let ordering_idx = hardest_first.reserve_ordering();
+        assert_eq!(hardest_first.get_ordering(ordering_idx), vec![1, 2, 0]);
+    }
+}
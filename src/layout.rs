@@ -6,13 +6,13 @@
 // was already locked, so it returns true.
 
 use crate::board::Board;
-use crate::circuit::Circuit;
+use crate::circuit::{Circuit, ConnectionGroup};
 use crate::settings::Settings;
 use std::collections::HashSet;
 use std::sync::Mutex;
 use std::time::Instant;
 
-use crate::via::{CostVia, CostViaVec, LayerStartEndVia, LayerVia, ValidVia, Via, WireLayerViaVec};
+use crate::via::{CostVia, CostViaVec, LayerStartEndVia, LayerVia, StartEndVia, ValidVia, Via, WireLayerViaVec};
 
 pub type RouteStepVec = Vec<LayerVia>;
 pub type RouteSectionVec = Vec<LayerStartEndVia>;
@@ -20,12 +20,108 @@ pub type RouteVec = Vec<RouteSectionVec>;
 pub type StringVec = Vec<String>;
 pub type RouteStatusVec = Vec<bool>;
 pub type StripCutVec = Vec<Via>;
+pub type CostBreakdownVec = Vec<CostBreakdown>;
 
 // Nets
 pub type ViaSet = HashSet<Via>;
 pub type ViaSetVec = Vec<ViaSet>;
 pub type SetIdxVec = Vec<usize>;
 
+// What the cost of a route, or of all the routes in a layout, is made up of. Lengths
+// are in steps between neighbouring vias.
+//
+// Reused sections are wire jumps, where the route follows a wire that is already
+// in its net instead of going through to the wire layer. They cost the same as a
+// single wire step.
+//
+// Cuts are placed after all the routes have been found, based on the nets. Like the
+// used area and the length mismatch, they are only counted for the layout as a whole,
+// and their costs are added to the cost of the layout after routing. See
+// Layout::add_cut_cost(), Layout::add_area_cost() and
+// Layout::add_length_mismatch_cost(). Wire steps in power nets, steps in cost regions
+// and detours from the other route in a pair cost more than the costs in the
+// settings, so the cost of such a route is more than the sum of the items. The
+// difference is shown as a separate item. See NetAttributes, CostRegion and
+// ConnectionGroup.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct CostBreakdown {
+    pub strip_len: usize,
+    pub wire_len: usize,
+    pub n_wires: usize,
+    pub n_vias: usize,
+    pub n_reused: usize,
+    pub n_cuts: usize,
+    pub used_area: usize,
+    pub len_mismatch: usize,
+    pub cost: usize,
+}
+
+impl CostBreakdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, other: &Self) {
+        self.strip_len += other.strip_len;
+        self.wire_len += other.wire_len;
+        self.n_wires += other.n_wires;
+        self.n_vias += other.n_vias;
+        self.n_reused += other.n_reused;
+        self.n_cuts += other.n_cuts;
+        self.used_area += other.used_area;
+        self.len_mismatch += other.len_mismatch;
+        self.cost += other.cost;
+    }
+
+    // The difference from an earlier breakdown that this one was built up from.
+    pub fn sub(&self, other: &Self) -> Self {
+        Self {
+            strip_len: self.strip_len - other.strip_len,
+            wire_len: self.wire_len - other.wire_len,
+            n_wires: self.n_wires - other.n_wires,
+            n_vias: self.n_vias - other.n_vias,
+            n_reused: self.n_reused - other.n_reused,
+            n_cuts: self.n_cuts - other.n_cuts,
+            used_area: self.used_area - other.used_area,
+            len_mismatch: self.len_mismatch - other.len_mismatch,
+            cost: self.cost - other.cost,
+        }
+    }
+
+    // One line per item, for display and export.
+    pub fn info_vec(&self, settings: &Settings) -> StringVec {
+        vec![
+            format!("Strip length: {} (cost {})", self.strip_len, self.strip_len * settings.strip_cost),
+            format!("Wire length: {} (cost {})", self.wire_len, self.wire_len * settings.wire_cost),
+            format!("Wires: {}", self.n_wires),
+            format!("Vias: {} (cost {})", self.n_vias, self.n_vias * settings.via_cost),
+            format!("Reused sections: {} (cost {})", self.n_reused, self.n_reused * settings.wire_cost),
+            format!("Cuts: {} (cost {})", self.n_cuts, self.n_cuts * settings.cut_cost),
+            format!("Used area: {} (cost {})", self.used_area, self.used_area * settings.area_cost),
+            format!(
+                "Length mismatch: {} (cost {})",
+                self.len_mismatch,
+                self.len_mismatch * settings.length_mismatch_cost
+            ),
+            format!(
+                "Extra cost for power nets, cost regions and pairs: {}",
+                self.cost.saturating_sub(self.calc_item_cost(settings))
+            ),
+            format!("Total cost: {}", self.cost),
+        ]
+    }
+
+    // The cost of the items at the costs in the settings, without the factors.
+    fn calc_item_cost(&self, settings: &Settings) -> usize {
+        self.strip_len * settings.strip_cost
+            + (self.wire_len + self.n_reused) * settings.wire_cost
+            + self.n_vias * settings.via_cost
+            + self.n_cuts * settings.cut_cost
+            + self.used_area * settings.area_cost
+            + self.len_mismatch * settings.length_mismatch_cost
+    }
+}
+
 pub struct Layout {
     pub circuit: Circuit,
     pub settings: Settings,
@@ -33,6 +129,12 @@ pub struct Layout {
     pub cost: usize,
     pub n_completed_routes: usize,
     pub n_failed_routes: usize,
+    pub cost_breakdown: CostBreakdown,
+    // Per route, in the same order as route_vec.
+    pub route_cost_vec: CostBreakdownVec,
+    // Per route, in the same order as route_vec. The connection that the route is for,
+    // as an index into Circuit::connection_vec.
+    pub route_connection_idx_vec: Vec<usize>,
     // pub has_error: bool,
     pub layout_info_vec: StringVec,
     pub route_vec: RouteVec,
@@ -62,6 +164,9 @@ impl Layout {
             cost: 0,
             n_completed_routes: 0,
             n_failed_routes: 0,
+            cost_breakdown: CostBreakdown::new(),
+            route_cost_vec: CostBreakdownVec::new(),
+            route_connection_idx_vec: Vec::new(),
 
             // has_error: false,
             layout_info_vec: StringVec::new(),
@@ -102,6 +207,9 @@ impl Layout {
         self.cost = other.cost;
         self.n_completed_routes = other.n_completed_routes;
         self.n_failed_routes = other.n_failed_routes;
+        self.cost_breakdown = other.cost_breakdown;
+        self.route_cost_vec = other.route_cost_vec.clone();
+        self.route_connection_idx_vec = other.route_connection_idx_vec.clone();
         // self.has_error = other.has_error;
         self.layout_info_vec = other.layout_info_vec.clone();
         self.route_vec = other.route_vec.clone();
@@ -115,6 +223,115 @@ impl Layout {
         self.timestamp_ = other.timestamp_;
     }
 
+    // The smallest rectangle that holds all the component pins, routes and strip cuts.
+    // None if there's nothing on the board.
+    pub fn calc_used_rect(&self) -> Option<StartEndVia> {
+        let mut via_vec: Vec<Via> = Vec::new();
+        for component_name in self.circuit.component_name_to_component_map.keys() {
+            via_vec.extend(self.circuit.calc_component_pins(component_name));
+        }
+        for route_section_vec in &self.route_vec {
+            for section in route_section_vec {
+                via_vec.push(section.start.via);
+                via_vec.push(section.end.via);
+            }
+        }
+        via_vec.extend(self.strip_cut_vec.iter().copied());
+        let mut rect = StartEndVia::new(*via_vec.first()?, *via_vec.first()?);
+        for via in &via_vec {
+            rect.start.x = rect.start.x.min(via.x);
+            rect.start.y = rect.start.y.min(via.y);
+            rect.end.x = rect.end.x.max(via.x);
+            rect.end.y = rect.end.y.max(via.y);
+        }
+        Some(rect)
+    }
+
+    // The number of vias in the used rectangle. See calc_used_rect().
+    pub fn calc_used_area(&self) -> usize {
+        self.calc_used_rect()
+            .map_or(0, |rect| (rect.end.x - rect.start.x + 1) * (rect.end.y - rect.start.y + 1))
+    }
+
+    // Must be called once, after the routes and strip cuts are in place.
+    pub fn add_cut_cost(&mut self) {
+        let n_cuts = self.strip_cut_vec.len();
+        let cut_cost = n_cuts * self.settings.cut_cost;
+        self.cost_breakdown.n_cuts = n_cuts;
+        self.cost_breakdown.cost += cut_cost;
+        self.cost += cut_cost;
+    }
+
+    pub fn add_area_cost(&mut self) {
+        let used_area = self.calc_used_area();
+        let area_cost = used_area * self.settings.area_cost;
+        self.cost_breakdown.used_area = used_area;
+        self.cost_breakdown.cost += area_cost;
+        self.cost += area_cost;
+    }
+
+    // The length of a route, in steps between neighbouring vias on either layer.
+    pub fn calc_route_len(&self, route_idx: usize) -> usize {
+        self.route_vec[route_idx]
+            .iter()
+            .map(|s| s.start.via.x.abs_diff(s.end.via.x) + s.start.via.y.abs_diff(s.end.via.y))
+            .sum()
+    }
+
+    // The lengths of the routes for the connections in the group, in the order they
+    // are in the group. None if any of them have not been routed.
+    pub fn calc_group_len_vec(&self, connection_group: &ConnectionGroup) -> Option<Vec<usize>> {
+        connection_group
+            .connection_idx_vec
+            .iter()
+            .map(|connection_idx| {
+                let route_idx = self.route_connection_idx_vec.iter().position(|i| i == connection_idx)?;
+                Some(self.calc_route_len(route_idx))
+            })
+            .collect()
+    }
+
+    // The difference between the longest and the shortest route in the group. None if
+    // any of them have not been routed.
+    pub fn calc_group_len_diff(&self, connection_group: &ConnectionGroup) -> Option<usize> {
+        let len_vec = self.calc_group_len_vec(connection_group)?;
+        Some(len_vec.iter().max()? - len_vec.iter().min()?)
+    }
+
+    // Must be called once, after the routes are in place. Groups with failed routes
+    // are not counted, as the failed routes already dominate the cost.
+    pub fn add_length_mismatch_cost(&mut self) {
+        let len_mismatch: usize = self
+            .circuit
+            .connection_group_vec
+            .iter()
+            .filter_map(|g| self.calc_group_len_diff(g))
+            .sum();
+        let len_mismatch_cost = len_mismatch * self.settings.length_mismatch_cost;
+        self.cost_breakdown.len_mismatch = len_mismatch;
+        self.cost_breakdown.cost += len_mismatch_cost;
+        self.cost += len_mismatch_cost;
+    }
+
+    // One line per connection group, with the route lengths and the difference between
+    // the longest and the shortest, for display and export.
+    pub fn group_info_vec(&self) -> StringVec {
+        self.circuit
+            .connection_group_vec
+            .iter()
+            .map(|g| match self.calc_group_len_vec(g) {
+                Some(len_vec) => format!(
+                    "Group {} ({}): lengths {}, difference {}",
+                    g.name,
+                    g.kind.name(),
+                    len_vec.iter().map(|l| l.to_string()).collect::<Vec<_>>().join(", "),
+                    self.calc_group_len_diff(g).unwrap_or(0)
+                ),
+                None => format!("Group {} ({}): not completely routed", g.name, g.kind.name()),
+            })
+            .collect()
+    }
+
     pub fn is_based_on(&self, other: &Self) -> bool {
         self.timestamp_ == other.timestamp_
     }
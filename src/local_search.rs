@@ -0,0 +1,344 @@
+use crate::checkpoint::{format_rng, CheckpointReader, CheckpointWriter};
+use crate::circuit::Circuit;
+use crate::ga_stats::StatsHistory;
+use crate::ordering_search::{
+    map_connections, parse_ordering, remap_ordering, ConnectionIdxVec, Fitness, OrderingIdx, OrderingSearch, RoundStats,
+};
+use crate::settings::OrderingSearchStrategy;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+
+// Local searches over the orderings. Both work from a current ordering, and each
+// ordering that is handed out is a neighbour of it: a copy with two connections
+// swapped, or with a single connection moved to a new position.
+//
+// RandomRestart: A neighbour replaces the current ordering if it's at least as fit.
+// When no neighbour has been fitter for a while, the climb is stuck in a local
+// optimum, and it starts over from a new random ordering.
+//
+// SimulatedAnnealing: A fitter neighbour always replaces the current ordering, and a
+// less fit one replaces it with a probability of exp(-delta / temperature), where delta
+// is how much worse it is. The temperature drops after each ordering, so the search
+// gradually settles. When it has cooled down, it is reheated and continues from the
+// best ordering found so far.
+//
+// For annealing, the fitness is turned into a single energy value. Failing a route
+// counts as FAILED_ROUTE_ENERGY_FACTOR times the average cost of a completed route,
+// so that failing a route is always worse than the cost it saves. The route energy
+// and the starting temperature are set from the first ordering that is routed, so that
+// they follow the cost settings.
+//
+// The router threads route several neighbours of the same current ordering at the same
+// time, and each neighbour is compared with the current ordering at the time when it's
+// released. Until the first ordering in a climb has been routed, there is no current
+// ordering, and random orderings are handed out. Each climb has an index, and
+// neighbours that were handed out during an earlier climb are not accepted.
+//
+// When the connections change, or the fitness is no longer valid, the best ordering is
+// routed again to start a new climb. The search then continues from there.
+
+const MIN_N_STUCK_ORDERINGS_BEFORE_RESTART: usize = 100;
+const N_STUCK_ORDERINGS_PER_CONNECTION_BEFORE_RESTART: usize = 2;
+
+const FAILED_ROUTE_ENERGY_FACTOR: f64 = 2.0;
+const COOLING_RATE: f64 = 0.999;
+// Reheat when the temperature has dropped to this fraction of the starting
+// temperature. With the cooling rate above, a cycle is about 7000 orderings.
+const MIN_TEMPERATURE_FRACTION: f64 = 0.001;
+
+pub struct LocalSearch {
+    strategy: OrderingSearchStrategy,
+    random_engine: ChaCha8Rng,
+    // The key of each connection, by connection index. See Connection::key().
+    connection_key_vec: Vec<String>,
+    climb_idx: usize,
+    current: Option<(ConnectionIdxVec, Fitness)>,
+    best: Option<(ConnectionIdxVec, Fitness)>,
+    // Handed out first when a climb starts, instead of a random ordering.
+    start_ordering: Option<ConnectionIdxVec>,
+    n_stuck_orderings: usize,
+    // 0 until the first ordering has been routed.
+    route_energy: f64,
+    temperature: f64,
+    // The ordering and the climb index for each ordering that has been handed out.
+    reserved_ordering_map: HashMap<OrderingIdx, (ConnectionIdxVec, usize)>,
+    next_ordering_idx: OrderingIdx,
+    round_stats: RoundStats,
+}
+
+impl LocalSearch {
+    pub fn new(strategy: OrderingSearchStrategy, seed: u64) -> Self {
+        assert!(matches!(
+            strategy,
+            OrderingSearchStrategy::RandomRestart | OrderingSearchStrategy::SimulatedAnnealing
+        ));
+        Self {
+            strategy,
+            random_engine: ChaCha8Rng::seed_from_u64(seed),
+            connection_key_vec: Vec::new(),
+            climb_idx: 0,
+            current: None,
+            best: None,
+            start_ordering: None,
+            n_stuck_orderings: 0,
+            route_energy: 0.0,
+            temperature: 0.0,
+            reserved_ordering_map: HashMap::new(),
+            next_ordering_idx: 0,
+            round_stats: RoundStats::new(),
+        }
+    }
+
+    fn n_connections(&self) -> usize {
+        self.connection_key_vec.len()
+    }
+
+    // Start a new climb. If a start ordering is given, it's routed first.
+    fn start_climb(&mut self, start_ordering: Option<ConnectionIdxVec>) {
+        self.climb_idx += 1;
+        self.current = None;
+        self.start_ordering = start_ordering;
+        self.n_stuck_orderings = 0;
+    }
+
+    fn create_random_ordering(&mut self) -> ConnectionIdxVec {
+        let mut ordering: ConnectionIdxVec = (0..self.n_connections()).collect();
+        ordering.shuffle(&mut self.random_engine);
+        ordering
+    }
+
+    fn create_neighbour(&mut self, ordering: &[usize]) -> ConnectionIdxVec {
+        let mut neighbour = ordering.to_vec();
+        let n_connections = neighbour.len();
+        if n_connections < 2 {
+            return neighbour;
+        }
+        let a = self.random_engine.gen_range(0..n_connections);
+        let b = self.random_engine.gen_range(0..n_connections);
+        if self.random_engine.gen_bool(0.5) {
+            neighbour.swap(a, b);
+        } else {
+            let connection_idx = neighbour.remove(a);
+            neighbour.insert(b, connection_idx);
+        }
+        neighbour
+    }
+
+    fn calc_energy(&self, fitness: &Fitness) -> f64 {
+        let n_failed_routes = self.n_connections().saturating_sub(fitness.n_completed_routes);
+        n_failed_routes as f64 * self.route_energy * FAILED_ROUTE_ENERGY_FACTOR + fitness.completed_route_cost as f64
+    }
+
+    fn climb(&mut self, ordering: ConnectionIdxVec, fitness: Fitness) {
+        let current_fitness = match &self.current {
+            Some((_, current_fitness)) => *current_fitness,
+            None => {
+                if self.route_energy == 0.0 {
+                    self.route_energy =
+                        (fitness.completed_route_cost as f64 / fitness.n_completed_routes.max(1) as f64).max(1.0);
+                    self.temperature = self.route_energy;
+                }
+                self.current = Some((ordering, fitness));
+                return;
+            }
+        };
+        match self.strategy {
+            OrderingSearchStrategy::RandomRestart => {
+                if fitness.is_fitter_than(&current_fitness) {
+                    self.n_stuck_orderings = 0;
+                } else {
+                    self.n_stuck_orderings += 1;
+                }
+                if !current_fitness.is_fitter_than(&fitness) {
+                    self.current = Some((ordering, fitness));
+                }
+                let n_stuck_orderings_before_restart = MIN_N_STUCK_ORDERINGS_BEFORE_RESTART
+                    .max(N_STUCK_ORDERINGS_PER_CONNECTION_BEFORE_RESTART * self.n_connections());
+                if self.n_stuck_orderings >= n_stuck_orderings_before_restart {
+                    self.start_climb(None);
+                }
+            }
+            _ => {
+                let delta = self.calc_energy(&fitness) - self.calc_energy(&current_fitness);
+                if delta <= 0.0 || self.random_engine.gen::<f64>() < (-delta / self.temperature).exp() {
+                    self.current = Some((ordering, fitness));
+                }
+                self.temperature *= COOLING_RATE;
+                if self.temperature < self.route_energy * MIN_TEMPERATURE_FRACTION {
+                    self.temperature = self.route_energy;
+                    self.climb_idx += 1;
+                    self.current = self.best.clone();
+                }
+            }
+        }
+    }
+
+    // Start over from the best ordering, with no fitness known.
+    fn restart_from_best(&mut self) {
+        let best_ordering = self.best.take().map(|(ordering, _)| ordering);
+        self.route_energy = 0.0;
+        self.start_climb(best_ordering);
+    }
+}
+
+impl OrderingSearch for LocalSearch {
+    fn get_strategy(&self) -> OrderingSearchStrategy {
+        self.strategy
+    }
+
+    fn reset(&mut self, circuit: &Circuit) {
+        self.connection_key_vec = circuit.gen_connection_key_vec();
+        self.best = None;
+        self.route_energy = 0.0;
+        self.reserved_ordering_map.clear();
+        self.round_stats.clear();
+        self.start_climb(None);
+    }
+
+    fn warm_start(&mut self, circuit: &Circuit) {
+        let connection_key_vec = circuit.gen_connection_key_vec();
+        let connection_map = map_connections(&self.connection_key_vec, &connection_key_vec);
+        if self.best.is_none() || connection_map.iter().all(Option::is_none) {
+            self.reset(circuit);
+            return;
+        }
+        if let Some((ordering, fitness)) = self.best.take() {
+            self.best = Some((
+                remap_ordering(&ordering, &connection_map, connection_key_vec.len()),
+                fitness,
+            ));
+        }
+        self.connection_key_vec = connection_key_vec;
+        self.reserved_ordering_map.clear();
+        self.restart_from_best();
+    }
+
+    fn clear_fitness_cache(&mut self) {
+        self.restart_from_best();
+    }
+
+    fn reserve_ordering(&mut self) -> OrderingIdx {
+        if self.n_connections() == 0 {
+            return usize::MAX;
+        }
+        let ordering = match (&self.current, self.start_ordering.take()) {
+            (None, Some(start_ordering)) => start_ordering,
+            (None, None) => self.create_random_ordering(),
+            (Some((current_ordering, _)), _) => {
+                let current_ordering = current_ordering.clone();
+                self.create_neighbour(&current_ordering)
+            }
+        };
+        let ordering_idx = self.next_ordering_idx;
+        self.next_ordering_idx += 1;
+        self.reserved_ordering_map
+            .insert(ordering_idx, (ordering, self.climb_idx));
+        ordering_idx
+    }
+
+    fn get_ordering(&self, ordering_idx: OrderingIdx) -> ConnectionIdxVec {
+        self.reserved_ordering_map[&ordering_idx].0.clone()
+    }
+
+    fn release_ordering(&mut self, ordering_idx: OrderingIdx, n_completed_routes: usize, completed_route_cost: usize) {
+        // Orderings handed out before a reset are gone.
+        let (ordering, climb_idx) = match self.reserved_ordering_map.remove(&ordering_idx) {
+            Some(reserved) => reserved,
+            None => return,
+        };
+        let fitness = Fitness::new(n_completed_routes, completed_route_cost);
+        self.round_stats.record(ordering.clone(), fitness);
+        if self
+            .best
+            .as_ref()
+            .is_none_or(|(_, best_fitness)| fitness.is_fitter_than(best_fitness))
+        {
+            self.best = Some((ordering.clone(), fitness));
+        }
+        if climb_idx == self.climb_idx {
+            self.climb(ordering, fitness);
+        }
+    }
+
+    fn get_n_generations(&self) -> usize {
+        self.round_stats.get_n_rounds()
+    }
+
+    fn get_stats_history(&self) -> &StatsHistory {
+        self.round_stats.get_stats_history()
+    }
+
+    // Orderings that were handed out but not released are not saved. The climb
+    // continues from the current ordering.
+    fn write_checkpoint(&self, writer: &mut CheckpointWriter) {
+        writer.write("connections", self.connection_key_vec.clone());
+        let mut value_vec = vec![
+            self.round_stats.get_n_rounds().to_string(),
+            self.climb_idx.to_string(),
+            self.n_stuck_orderings.to_string(),
+            self.route_energy.to_string(),
+            self.temperature.to_string(),
+        ];
+        value_vec.extend(format_rng(&self.random_engine));
+        writer.write("local_search", value_vec);
+        for (key, ordering_and_fitness) in [("current", &self.current), ("best", &self.best)] {
+            let mut value_vec = Vec::new();
+            if let Some((ordering, fitness)) = ordering_and_fitness {
+                value_vec.push(fitness.n_completed_routes.to_string());
+                value_vec.push(fitness.completed_route_cost.to_string());
+                value_vec.extend(ordering.iter().map(|connection_idx| connection_idx.to_string()));
+            }
+            writer.write(key, value_vec);
+        }
+        writer.write(
+            "start_ordering",
+            self.start_ordering
+                .iter()
+                .flatten()
+                .map(|connection_idx| connection_idx.to_string())
+                .collect(),
+        );
+    }
+
+    fn read_checkpoint(&mut self, reader: &mut CheckpointReader) -> Result<(), String> {
+        let connection_key_vec: Vec<String> = reader.read("connections")?.get_vec(0)?;
+        let n_connections = connection_key_vec.len();
+        let line = reader.read("local_search")?;
+        let n_rounds: usize = line.get(0)?;
+        let climb_idx: usize = line.get(1)?;
+        let n_stuck_orderings: usize = line.get(2)?;
+        let route_energy: f64 = line.get(3)?;
+        let temperature: f64 = line.get(4)?;
+        let random_engine = line.get_rng(5)?;
+        let mut ordering_and_fitness_vec = Vec::new();
+        for key in ["current", "best"] {
+            let line = reader.read(key)?;
+            if line.value_vec.is_empty() {
+                ordering_and_fitness_vec.push(None);
+                continue;
+            }
+            let fitness = Fitness::new(line.get(0)?, line.get(1)?);
+            ordering_and_fitness_vec.push(Some((parse_ordering(&line, 2, n_connections)?, fitness)));
+        }
+        let line = reader.read("start_ordering")?;
+        let start_ordering = if line.value_vec.is_empty() {
+            None
+        } else {
+            Some(parse_ordering(&line, 0, n_connections)?)
+        };
+        self.connection_key_vec = connection_key_vec;
+        self.climb_idx = climb_idx;
+        self.n_stuck_orderings = n_stuck_orderings;
+        self.route_energy = route_energy;
+        self.temperature = temperature;
+        self.random_engine = random_engine;
+        self.best = ordering_and_fitness_vec.pop().unwrap();
+        self.current = ordering_and_fitness_vec.pop().unwrap();
+        self.start_ordering = start_ordering;
+        self.reserved_ordering_map.clear();
+        self.round_stats.set_n_rounds(n_rounds);
+        Ok(())
+    }
+}
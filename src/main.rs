@@ -2,44 +2,98 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 use std::env;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 
 use eframe::egui;
 use rand::Rng;
 
+use crate::checkpoint::LoadedCheckpoint;
 use crate::controls::Controls;
 use crate::layout::Layout;
 use crate::render::Render;
 // use crate::thread_stop::ThreadStop;
 use crate::router_control::RouterControl;
+use crate::settings::{GaSettings, OrderingSearchStrategy, SearchAlgorithm};
 use crate::status::Status;
 
+mod assembly_pdf;
 mod board;
+mod build_sheet;
+mod checkpoint;
 pub mod circuit;
+mod cli;
 mod circuit_parser;
 mod controls;
 mod ga_core;
 mod ga_interface;
+mod ga_stats;
+mod hardest_first;
 mod layout;
+mod local_search;
 mod nets;
+mod ordering_search;
+mod pareto;
+mod pdf;
 mod render;
-mod render_ascii;
 mod router;
 mod router_control;
 mod router_thread;
 mod settings;
+mod shrink;
 mod status;
 mod thread_stop;
 mod ucs;
 mod util;
+mod verify;
 mod via;
 
 static CIRCUIT_FILE_PATH: &'static str = "../../circuits/example.circuit";
 
+// How often the GUI saves the checkpoint, if one was given on the command line.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+// How often the GUI checks if the .circuit file has been changed. When it has, the
+// circuit is reloaded and the search continues on the existing GA population.
+const CIRCUIT_FILE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// How often the GUI copies the GA statistics for the plots. The history can hold
+// thousands of generations, so it's not copied on every frame.
+const STATS_REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+// How many orderings are checked for each board size with --shrink, unless --checks
+// is given.
+const DEFAULT_SHRINK_N_CHECKS: usize = 100;
+
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
+
+    let args = match cli::Args::parse() {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+    };
+    let circuit_file_path = args.circuit_file_path.clone().unwrap_or_else(default_circuit_file_path);
+
+    if args.verify {
+        let is_ok = verify_circuit(&circuit_file_path, &args);
+        std::process::exit(if is_ok { 0 } else { 1 });
+    }
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    eprintln!("Seed: {}", seed);
+
+    if args.shrink {
+        let is_ok = shrink_circuit(&circuit_file_path, &args, seed);
+        std::process::exit(if is_ok { 0 } else { 1 });
+    }
+
+    if let Some(n_checks) = args.n_checks {
+        let is_ok = check_circuit(&circuit_file_path, &args, seed, n_checks);
+        std::process::exit(if is_ok { 0 } else { 1 });
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([1000.0, 800.0]),
         ..Default::default()
@@ -47,18 +101,211 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Stripboard Autorouter",
         options,
-        Box::new(|cc| {
+        Box::new(move |cc| {
             // This gives us image support:
             // egui_extras::install_image_loaders(&cc.egui_ctx);
 
             // setup_custom_fonts(&cc.egui_ctx);
 
-            Box::<MyApp>::default()
+            Box::new(MyApp::new(
+                circuit_file_path,
+                args.ordering_search_strategy,
+                args.ga_settings,
+                seed,
+                args.is_deterministic,
+                args.checkpoint_path,
+            ))
             // Box::<MyApp>::MyApp::new(cc)
         }),
     )
 }
 
+fn default_circuit_file_path() -> PathBuf {
+    let mut bin_path = env::current_exe().unwrap();
+    bin_path.pop();
+    bin_path.push(CIRCUIT_FILE_PATH);
+    bin_path
+}
+
+// Route the circuit once, headless, and print the verification report. Returns true
+// if the layout has no electrical issues.
+fn verify_circuit(circuit_file_path: &Path, args: &cli::Args) -> bool {
+    let mut layout = load_circuit(circuit_file_path);
+    layout.settings.rip_up_passes = args.rip_up_passes;
+    let board = layout.board;
+    let mut router = router::Router::new(board);
+    let mut nets = nets::Nets::new(board);
+    let connection_idx_vec = (0..layout.circuit.connection_vec.len()).collect();
+    let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+    router.route(board, &mut layout, &mut nets, connection_idx_vec, &mut limit_routes);
+    write_build_sheet(args.build_sheet_path.as_deref(), &layout);
+    write_assembly_pdf(args.pdf_path.as_deref(), args.page_size, circuit_file_path, &layout);
+    print_layout_report(&layout)
+}
+
+// Run the GA for the given number of orderings, headless and deterministic, and print
+// the report for the best layout. Returns true if the layout has no electrical
+// issues. If a checkpoint is given, the search resumes from it if it exists, and is
+// saved to it when done. The per-generation statistics and the Pareto front are written
+// if requested.
+fn check_circuit(circuit_file_path: &Path, args: &cli::Args, seed: u64, n_checks: usize) -> bool {
+    let checkpoint_path = args.checkpoint_path.as_deref();
+    let mut layout = load_circuit(circuit_file_path);
+    layout.settings.area_cost = args.area_cost;
+    layout.settings.rip_up_passes = args.rip_up_passes;
+    let input_layout = Arc::new(Mutex::new(layout));
+    let best_layout = Arc::new(Mutex::new(Layout::new()));
+    let mut router_control = RouterControl::new(
+        Arc::clone(&input_layout),
+        Arc::new(Mutex::new(Layout::new())),
+        Arc::clone(&best_layout),
+        Arc::new(AtomicUsize::new(0)),
+        Arc::new(AtomicUsize::new(usize::MAX)),
+        args.ordering_search_strategy,
+        &args.ga_settings,
+        seed,
+        true,
+    );
+    if let Some(checkpoint_path) = checkpoint_path {
+        if checkpoint_path.exists() {
+            if let Err(error) = load_checkpoint(&mut router_control, checkpoint_path) {
+                eprintln!("{}", error);
+                std::process::exit(2);
+            }
+        }
+    }
+    router_control.check_orderings(n_checks);
+    if let Some(checkpoint_path) = checkpoint_path {
+        if let Err(error) = router_control.save_checkpoint(checkpoint_path) {
+            eprintln!("{}", error);
+        }
+    }
+    if let Some(stats_path) = &args.stats_path {
+        if let Err(error) = std::fs::write(stats_path, router_control.get_stats_csv()) {
+            eprintln!("Unable to write statistics {}: {}", stats_path.display(), error);
+        }
+    }
+    if let Some(pareto_dir_path) = &args.pareto_dir_path {
+        if let Err(error) = router_control.export_pareto_front(pareto_dir_path) {
+            eprintln!("Unable to write the Pareto front: {}", error);
+        }
+    }
+    println!("Generations: {}", router_control.get_n_generations());
+    println!("Pareto layouts: {}", router_control.get_pareto_objectives_vec().len());
+    println!("Fitness cache hits: {}", router_control.get_n_fitness_cache_hits());
+    let best_layout = best_layout.lock().unwrap().clone();
+    write_build_sheet(args.build_sheet_path.as_deref(), &best_layout);
+    write_assembly_pdf(args.pdf_path.as_deref(), args.page_size, circuit_file_path, &best_layout);
+    print_layout_report(&best_layout)
+}
+
+// Find the smallest board that the circuit still routes completely on, headless and
+// deterministic, and print the new board size and how far to move the components, and
+// the report for the layout on the new board. Returns true if a complete layout with
+// no electrical issues was found.
+fn shrink_circuit(circuit_file_path: &Path, args: &cli::Args, seed: u64) -> bool {
+    let mut input_layout = load_circuit(circuit_file_path);
+    input_layout.settings.rip_up_passes = args.rip_up_passes;
+    let n_checks = args.n_checks.unwrap_or(DEFAULT_SHRINK_N_CHECKS);
+    let shrinker = shrink::BoardShrinker::new(args.ordering_search_strategy, &args.ga_settings, seed, n_checks);
+    let result = match shrinker.shrink(&input_layout) {
+        Ok(result) => result,
+        Err(error) => {
+            eprintln!("{}", error);
+            return false;
+        }
+    };
+    println!(
+        "Board: {},{} (was {},{})",
+        result.layout.board.w, result.layout.board.h, input_layout.board.w, input_layout.board.h
+    );
+    println!(
+        "Move the components by {},{} (add it to the offset lines)",
+        result.offset.x, result.offset.y
+    );
+    write_build_sheet(args.build_sheet_path.as_deref(), &result.layout);
+    write_assembly_pdf(args.pdf_path.as_deref(), args.page_size, circuit_file_path, &result.layout);
+    print_layout_report(&result.layout)
+}
+
+// Write the build sheet for the layout, if a path was given. The format follows from
+// the extension. See BuildSheetFormat.
+fn write_build_sheet(build_sheet_path: Option<&Path>, layout: &Layout) {
+    let Some(path) = build_sheet_path else {
+        return;
+    };
+    let build_sheet = build_sheet::BuildSheet::new(layout);
+    let format = build_sheet::BuildSheetFormat::from_path(path);
+    match std::fs::write(path, build_sheet.format(format)) {
+        Ok(()) => println!("Wrote build sheet to {}", path.display()),
+        Err(error) => eprintln!("Unable to write build sheet {}: {}", path.display(), error),
+    }
+}
+
+// Write the printable assembly sheets for the layout, if a path was given. The title is
+// the name of the circuit file.
+fn write_assembly_pdf(
+    pdf_path: Option<&Path>,
+    page_size: assembly_pdf::PageSize,
+    circuit_file_path: &Path,
+    layout: &Layout,
+) {
+    let Some(path) = pdf_path else {
+        return;
+    };
+    let title = circuit_file_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let document = assembly_pdf::AssemblyPdf::new(layout, &title, page_size).gen_document();
+    match document.save(path) {
+        Ok(()) => println!("Wrote {} assembly sheet pages to {}", document.n_pages(), path.display()),
+        Err(error) => eprintln!("{}", error),
+    }
+}
+
+fn load_checkpoint(router_control: &mut RouterControl, checkpoint_path: &Path) -> Result<(), String> {
+    match router_control.load_checkpoint(checkpoint_path)? {
+        LoadedCheckpoint::Resumed => {
+            println!("Resumed from checkpoint at generation {}", router_control.get_n_generations())
+        }
+        LoadedCheckpoint::WarmStarted => println!("Circuit has changed, warm-starting from checkpoint"),
+    }
+    Ok(())
+}
+
+fn load_circuit(circuit_file_path: &Path) -> Layout {
+    let mut layout = Layout::new();
+    circuit_parser::CircuitFileParser::new(&mut layout).parse(circuit_file_path.as_os_str());
+    for error in &layout.circuit.parser_error_vec {
+        eprintln!("{}", error);
+    }
+    layout
+}
+
+// Print the route counts, cost breakdown and verification report for a layout.
+// Returns true if the layout has no electrical issues.
+fn print_layout_report(layout: &Layout) -> bool {
+    let report = verify::verify_layout(layout);
+    println!("Routes: {} completed, {} failed", layout.n_completed_routes, layout.n_failed_routes);
+    for line in layout.cost_breakdown.info_vec(&layout.settings) {
+        println!("{}", line);
+    }
+    for line in layout.group_info_vec() {
+        println!("{}", line);
+    }
+    for issue in &report.issue_vec {
+        println!("{}", issue);
+    }
+    println!(
+        "Verify: {} opens, {} shorts, {} unused pins on shared strips",
+        report.n_opens(),
+        report.n_shorts(),
+        report.n_shared_strips()
+    );
+    report.is_ok()
+}
+
 struct MyApp {
     input_layout: Arc<Mutex<Layout>>,
     current_layout: Arc<Mutex<Layout>>,
@@ -71,6 +318,20 @@ struct MyApp {
     // controls: controls::Controls<'a>,
 
     limit_routes: Arc<AtomicUsize>,
+
+    // None if no checkpoint was given, or if it could not be resumed, in which case it
+    // must not be overwritten.
+    checkpoint_path: Option<PathBuf>,
+    last_checkpoint_save: Instant,
+
+    circuit_file_path: PathBuf,
+    circuit_file_modified: Option<SystemTime>,
+    last_circuit_file_check: Instant,
+
+    last_stats_refresh: Instant,
+    // The best layout that the verification report in the status is for. A new best
+    // layout always has a lower cost, or is based on a new input layout.
+    verified_layout_key: Option<(Instant, usize, usize, usize)>,
 }
 
 // impl MyApp {
@@ -83,14 +344,31 @@ struct MyApp {
 
 impl<'a> Default for MyApp {
     fn default() -> Self {
+        Self::new(
+            default_circuit_file_path(),
+            OrderingSearchStrategy::GeneticAlgorithm,
+            GaSettings::new(),
+            rand::random(),
+            false,
+            None,
+        )
+    }
+}
+
+impl MyApp {
+    fn new(
+        circuit_file_path: PathBuf,
+        ordering_search_strategy: OrderingSearchStrategy,
+        ga_settings: GaSettings,
+        seed: u64,
+        is_deterministic: bool,
+        mut checkpoint_path: Option<PathBuf>,
+    ) -> Self {
         let input_layout = Arc::new(Mutex::new(Layout::new()));
         let current_layout = Arc::new(Mutex::new(Layout::new()));
         let best_layout = Arc::new(Mutex::new(Layout::new()));
 
-        let mut bin_path = env::current_exe().unwrap();
-        bin_path.pop();
-        bin_path.push(CIRCUIT_FILE_PATH);
-        circuit_parser::CircuitFileParser::new(&mut input_layout.lock().unwrap()).parse(bin_path.as_os_str());
+        circuit_parser::CircuitFileParser::new(&mut input_layout.lock().unwrap()).parse(circuit_file_path.as_os_str());
 
         let limit_routes = Arc::new(AtomicUsize::new(0));
         // layout.via_set_vec = nets.via_set_vec;
@@ -115,9 +393,21 @@ impl<'a> Default for MyApp {
             Arc::clone(&best_layout),
             counter.clone(),
             Arc::clone(&limit_routes),
+            ordering_search_strategy,
+            &ga_settings,
+            seed,
+            is_deterministic,
             // Arc::clone(&current_layout),
         );
 
+        if let Some(path) = checkpoint_path.clone().filter(|path| path.exists()) {
+            if let Err(error) = load_checkpoint(&mut router_control, &path) {
+                eprintln!("{}", error);
+                eprintln!("Not saving checkpoints");
+                checkpoint_path = None;
+            }
+        }
+
         router_control.start();
 
         let mut zoom = 15.0;
@@ -130,12 +420,19 @@ impl<'a> Default for MyApp {
             // router_stop_signal: thread_stop,
             counter: counter.clone(),
             start: Instant::now(),
-            status: Status::new(),
+            status: Status::new(ga_settings, ordering_search_strategy),
             // controls: Controls::new(
             //     0.0, 0, 0.0, 0, 0, 0, 0,
             //     &mut 0.0, 0, 0, 0, 0, 0, 0, false, false, false, false),
 
             limit_routes,
+            checkpoint_path,
+            last_checkpoint_save: Instant::now(),
+            circuit_file_modified: get_modified_time(&circuit_file_path),
+            circuit_file_path,
+            last_circuit_file_check: Instant::now(),
+            last_stats_refresh: Instant::now(),
+            verified_layout_key: None,
         }
 
         // app.controls = Controls::new(
@@ -145,13 +442,69 @@ impl<'a> Default for MyApp {
     }
 }
 
+impl MyApp {
+    fn save_checkpoint(&mut self) {
+        if let Some(checkpoint_path) = &self.checkpoint_path {
+            if let Err(error) = self.router_control.save_checkpoint(checkpoint_path) {
+                eprintln!("{}", error);
+            }
+        }
+        self.last_checkpoint_save = Instant::now();
+    }
+
+    // Write the layout next to the .circuit file, in the checkpoint format.
+    fn export_pareto_layout(&self, id: usize, layout: &Layout) {
+        let mut path = self.circuit_file_path.clone();
+        path.set_extension(format!("pareto-{}.layout", id));
+        match checkpoint::save_layout(&path, layout) {
+            Ok(()) => println!("Exported Pareto layout #{} to {}", id, path.display()),
+            Err(error) => eprintln!("{}", error),
+        }
+    }
+
+    fn reload_circuit_if_changed(&mut self) {
+        self.last_circuit_file_check = Instant::now();
+        let modified = get_modified_time(&self.circuit_file_path);
+        if modified.is_none() || modified == self.circuit_file_modified {
+            return;
+        }
+        self.circuit_file_modified = modified;
+        println!("Circuit file has changed, reloading");
+        let mut layout = load_circuit(&self.circuit_file_path);
+        // The settings are not in the .circuit file, and may have been changed in the
+        // GUI.
+        layout.settings = self.input_layout.lock().unwrap().settings.clone();
+        self.router_control.update_circuit(layout);
+    }
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
 impl<'a> eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Exit if the Escape key is pressed
         if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.save_checkpoint();
             std::process::exit(0);
         }
 
+        if self.last_checkpoint_save.elapsed() > CHECKPOINT_INTERVAL {
+            self.save_checkpoint();
+        }
+
+        if self.last_circuit_file_check.elapsed() > CIRCUIT_FILE_CHECK_INTERVAL {
+            self.reload_circuit_if_changed();
+        }
+
+        if self.last_stats_refresh.elapsed() > STATS_REFRESH_INTERVAL {
+            self.status.n_generations = self.router_control.get_n_generations();
+            self.status.generation_stats_vec = self.router_control.get_stats_vec();
+            self.status.pareto_objectives_vec = self.router_control.get_pareto_objectives_vec();
+            self.last_stats_refresh = Instant::now();
+        }
+
         // This scales all UI elements
         ctx.set_pixels_per_point(1.3);
 
@@ -173,8 +526,91 @@ impl<'a> eframe::App for MyApp {
         // println!("x.cost = {}", x.cost);
 
         self.status.best_layout_cost = best_layout.cost;
+
+        let layout_key = Some((
+            *best_layout.get_base_timestamp(),
+            best_layout.cost,
+            best_layout.n_completed_routes,
+            best_layout.n_failed_routes,
+        ));
+        if layout_key != self.verified_layout_key {
+            self.verified_layout_key = layout_key;
+            let report = verify::verify_layout(&best_layout);
+            self.status.best_layout_opens = report.n_opens();
+            self.status.best_layout_shorts = report.n_shorts();
+            self.status.best_layout_shared_strips = report.n_shared_strips();
+            self.status.best_layout_group_len_diff_vec = best_layout
+                .circuit
+                .connection_group_vec
+                .iter()
+                .map(|g| (g.name.clone(), best_layout.calc_group_len_diff(g)))
+                .collect();
+        }
+        let limit_routes_before = self.limit_routes.load(Ordering::SeqCst);
         controls.render(ctx, &mut self.status, &mut self.limit_routes);
 
+        // The router threads pick up the settings with their next copy of the input
+        // layout. Orderings give different layouts after a change, so the fitness
+        // cached by the GA is no longer valid.
+        let search_algorithm = if self.status.use_a_star {
+            SearchAlgorithm::AStar
+        } else {
+            SearchAlgorithm::UniformCost
+        };
+        let is_settings_changed = {
+            let mut input_layout = self.input_layout.lock().unwrap();
+            let is_changed = input_layout.settings.search_algorithm != search_algorithm
+                || input_layout.settings.area_cost != self.status.area_cost
+                || input_layout.settings.length_mismatch_cost != self.status.length_mismatch_cost
+                || input_layout.settings.rip_up_passes != self.status.rip_up_passes;
+            input_layout.settings.search_algorithm = search_algorithm;
+            input_layout.settings.area_cost = self.status.area_cost;
+            input_layout.settings.length_mismatch_cost = self.status.length_mismatch_cost;
+            input_layout.settings.rip_up_passes = self.status.rip_up_passes;
+            is_changed
+        };
+        if is_settings_changed || self.limit_routes.load(Ordering::SeqCst) != limit_routes_before {
+            self.router_control.clear_fitness_cache();
+        }
+
+        if self.status.ga_settings.validate().is_ok() {
+            self.router_control.set_ga_settings(&self.status.ga_settings);
+            self.router_control
+                .set_ordering_search_strategy(self.status.ordering_search_strategy, &self.status.ga_settings);
+        }
+
+        // Show the selected Pareto layout instead of the best layout. The selection is
+        // dropped when the layout is dropped from the archive.
+        if let Some(id) = self.status.selected_pareto_layout_id {
+            match self.router_control.get_pareto_layout(id) {
+                Some(pareto_layout) => best_layout = pareto_layout,
+                None => self.status.selected_pareto_layout_id = None,
+            }
+        }
+        if self.status.is_build_sheet_export_requested {
+            self.status.is_build_sheet_export_requested = false;
+            let mut path = self.circuit_file_path.clone();
+            path.set_extension("build.md");
+            write_build_sheet(Some(&path), &best_layout);
+        }
+        if self.status.is_pdf_export_requested {
+            self.status.is_pdf_export_requested = false;
+            let mut path = self.circuit_file_path.clone();
+            path.set_extension("pdf");
+            write_assembly_pdf(
+                Some(&path),
+                assembly_pdf::PageSize::A4,
+                &self.circuit_file_path,
+                &best_layout,
+            );
+        }
+        if self.status.is_pareto_export_requested {
+            self.status.is_pareto_export_requested = false;
+            if let Some(id) = self.status.selected_pareto_layout_id {
+                self.export_pareto_layout(id, &best_layout);
+            }
+        }
+
         let mut input_layout = self.input_layout.lock().unwrap().clone();
 
         // println!("via_cost: {}", best_layout.settings.via_cost);
@@ -189,12 +625,8 @@ impl<'a> eframe::App for MyApp {
 
             // {
             //     let _timer = Timer::new();
-            // render.draw(ctx, ui, &input_layout, true, false);
-            render.draw(ctx, ui, &best_layout, false, false);
-
-
-            // let render_ascii = render_ascii::RenderAscii::new(60, 40);
-            // render_ascii.draw(&best_layout);
+            // render.draw(ctx, ui, &input_layout, true, false, false);
+            render.draw(ctx, ui, &best_layout, false, false, self.status.show_cost_regions);
 
             // if self.start.elapsed().as_millis() > 1000 {
             //     println!(
@@ -212,4 +644,8 @@ impl<'a> eframe::App for MyApp {
             ctx.request_repaint();
         });
     }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.save_checkpoint();
+    }
 }
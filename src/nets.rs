@@ -1,7 +1,6 @@
 use crate::board::Board;
-use crate::layout::{Layout, RouteStepVec};
+use crate::layout::{Layout, RouteStepVec, ViaSet};
 use crate::via::Via;
-use std::collections::HashSet;
 
 // This class keeps track of nets, which are pins and traces which are
 // electrically connected and so can be considered to be equivalents by the
@@ -12,117 +11,370 @@ use std::collections::HashSet;
 // The nets are also what allows creating multiple routes from a single pin or
 // to a single pin. Without the nets, the first route connected to a pin would
 // block the pin off for other routes.
+//
+// The nets are stored in a union-find structure over the vias of the board, with
+// union by size and path compression. Each net also has a circular linked list of
+// its members, so that the members can be enumerated without scanning the board.
+//
+// Connections are added in groups, each with an id. Every change to the
+// structure, including the ones made by path compression, is recorded in an undo
+// log, so that a group can be removed again. This is done by rolling back to where
+// the group was added, and then replaying the groups that were added after it. The
+// router uses this for ripping up routes.
+
+pub type NetGroupId = usize;
+
+#[derive(Clone, Copy)]
+enum UndoEntry {
+    Parent(usize, usize),
+    Size(usize, usize),
+    Next(usize, usize),
+    IsMember(usize, bool),
+}
 
+#[derive(Clone)]
+struct NetGroup {
+    id: NetGroupId,
+    via_pair_vec: Vec<(Via, Via)>,
+    undo_log_len: usize,
+}
+
+#[derive(Clone)]
 pub struct Nets {
-    pub set_idx_vec: Vec<usize>,
-    pub via_set_vec: Vec<HashSet<Via>>,
+    board: Board,
+    parent_vec: Vec<usize>,
+    size_vec: Vec<usize>,
+    next_vec: Vec<usize>,
+    // Vias that have not been connected to anything are not in any net.
+    is_member_vec: Vec<bool>,
+    undo_vec: Vec<UndoEntry>,
+    group_vec: Vec<NetGroup>,
+    next_group_id: NetGroupId,
 }
 
 impl Nets {
     pub fn new(board: Board) -> Self {
         Self {
-            set_idx_vec: vec![usize::MAX; board.size()],
-            via_set_vec: Vec::new(),
-        }
-    }
-
-    // pub fn clear(&mut self, board: Board) {
-    //     self.set_idx_vec = vec![usize::MAX; board.size()];
-    //     // self.set_idx_vec.clear();
-    //     self.via_set_vec.clear();
-    // }
-
-    pub fn connect(&mut self, board: Board, layout: &mut Layout, via_a: Via, via_b: Via) {
-        let set_idx_a = self.get_via_set_idx(board, layout, via_a);
-        let set_idx_b = self.get_via_set_idx(board, layout, via_b);
-
-        // TODO: These asserts get triggered by a fast component drag out of the board on the top
-        // side (on C++ version).
-        // assert!(set_idx_a < self.via_set_vec.len());
-        // assert!(set_idx_b < self.via_set_vec.len());
-
-        if set_idx_a == usize::MAX && set_idx_b == usize::MAX {
-            let via_set_idx = self.create_via_set();
-            self.via_set_vec[via_set_idx].insert(via_a);
-            self.via_set_vec[via_set_idx].insert(via_b);
-            self.set_idx_vec[board.idx(via_a)] = via_set_idx;
-            self.set_idx_vec[board.idx(via_b)] = via_set_idx;
-        } else if set_idx_a != usize::MAX && set_idx_b == usize::MAX {
-            let via_set = &mut self.via_set_vec[set_idx_a];
-            via_set.insert(via_b);
-            self.set_idx_vec[board.idx(via_b)] = set_idx_a;
-        } else if set_idx_a == usize::MAX && set_idx_b != usize::MAX {
-            let via_set = &mut self.via_set_vec[set_idx_b];
-            via_set.insert(via_a);
-            self.set_idx_vec[board.idx(via_a)] = set_idx_b;
-        } else {
-            let via_set_b = self.via_set_vec[set_idx_b].clone();
-            let via_set_a = &mut self.via_set_vec[set_idx_a];
-            via_set_a.extend(via_set_b);
-            for v in &mut self.set_idx_vec {
-                if *v == set_idx_b {
-                    *v = set_idx_a;
-                }
-            }
+            board,
+            parent_vec: (0..board.size()).collect(),
+            size_vec: vec![1; board.size()],
+            next_vec: (0..board.size()).collect(),
+            is_member_vec: vec![false; board.size()],
+            undo_vec: Vec::new(),
+            group_vec: Vec::new(),
+            next_group_id: 0,
         }
     }
 
-    pub fn connect_route(&mut self, board: Board, layout: &mut Layout, route_step_vec: &RouteStepVec) {
-        let mut first = true;
-        for c in route_step_vec {
-            if first {
-                first = false;
-                continue;
-            }
-            if !c.is_wire_layer {
-                self.connect(board, layout, route_step_vec[0].via, c.via);
+    pub fn connect(&mut self, board: Board, layout: &mut Layout, via_a: Via, via_b: Via) -> NetGroupId {
+        self.add_group(vec![(via_a, via_b)])
+    }
+
+    // Connect all the vias on the strip layer of the route to each other. Returns the
+    // id with which the route can be disconnected again.
+    pub fn connect_route(&mut self, board: Board, layout: &mut Layout, route_step_vec: &RouteStepVec) -> NetGroupId {
+        let via_pair_vec = route_step_vec
+            .iter()
+            .skip(1)
+            .filter(|c| !c.is_wire_layer)
+            .map(|c| (route_step_vec[0].via, c.via))
+            .collect();
+        let group_id = self.add_group(via_pair_vec);
+        assert!(self.is_connected(board, layout, route_step_vec[0].via, route_step_vec[1].via));
+        group_id
+    }
+
+    // Remove the connections that were added with the given id. Returns false if
+    // there is no such group.
+    pub fn disconnect(&mut self, group_id: NetGroupId) -> bool {
+        let group_idx = match self.group_vec.iter().position(|g| g.id == group_id) {
+            Some(group_idx) => group_idx,
+            None => return false,
+        };
+        self.rollback_log(self.group_vec[group_idx].undo_log_len);
+        let replay_group_vec = self.group_vec.split_off(group_idx + 1);
+        self.group_vec.pop();
+        for group in replay_group_vec {
+            let undo_log_len = self.undo_vec.len();
+            for &(via_a, via_b) in &group.via_pair_vec {
+                self.union(via_a, via_b);
             }
+            self.group_vec.push(NetGroup { undo_log_len, ..group });
+        }
+        true
+    }
+
+    // Checkpoint for what-if evaluation. Everything connected after the checkpoint
+    // can be removed in one go by passing it to rollback().
+    pub fn checkpoint(&self) -> usize {
+        self.group_vec.len()
+    }
+
+    pub fn rollback(&mut self, checkpoint: usize) {
+        if checkpoint < self.group_vec.len() {
+            self.rollback_log(self.group_vec[checkpoint].undo_log_len);
+            self.group_vec.truncate(checkpoint);
         }
-        assert!(self.is_connected(board, layout, route_step_vec[0].via, route_step_vec[1].via));
     }
 
     // Register a single via as an equivalent to itself to simplify later checking
     // for equivalents.
-    pub fn register_pin(&mut self, board: Board, layout: &mut Layout, via: Via) {
-        let set_idx = self.get_via_set_idx(board, layout, via);
-        if set_idx != usize::MAX {
-            let via_set = &mut self.via_set_vec[set_idx];
-            via_set.insert(via);
-        } else {
-            let via_set_idx = self.create_via_set();
-            self.via_set_vec[via_set_idx].insert(via);
-            self.set_idx_vec[board.idx(via)] = via_set_idx;
-        }
+    pub fn register_pin(&mut self, board: Board, layout: &mut Layout, via: Via) -> NetGroupId {
+        self.add_group(vec![(via, via)])
     }
 
-    pub fn is_connected(&self, board: Board, layout: &mut Layout, current_via: Via, target_via: Via) -> bool {
-        let via_set_idx = self.set_idx_vec[board.idx(current_via)];
-        if via_set_idx == usize::MAX {
+    pub fn is_connected(&mut self, board: Board, layout: &mut Layout, current_via: Via, target_via: Via) -> bool {
+        let i = board.idx(current_via);
+        let j = board.idx(target_via);
+        if !self.is_member_vec[i] || !self.is_member_vec[j] {
             return false;
         }
-        let r = self.via_set_vec[via_set_idx].contains(&target_via);
-        r
+        self.find(i) == self.find(j)
+    }
+
+    // An id for the net that the via is in, or None if the via is not in any net. Vias
+    // in the same net get the same id, until the nets are changed.
+    pub fn get_net_id(&mut self, board: Board, via: Via) -> Option<usize> {
+        let i = board.idx(via);
+        if self.is_member_vec[i] {
+            Some(self.find(i))
+        } else {
+            None
+        }
     }
 
     pub fn has_connection(&self, board: Board, layout: &mut Layout, via: Via) -> bool {
-        let via_set_idx = self.set_idx_vec[board.idx(via)];
-        via_set_idx != usize::MAX
+        self.is_member_vec[board.idx(via)]
+    }
+
+    // All the vias in the same net as the given via, including the via itself. Empty
+    // if the via is not in any net.
+    pub fn get_net_members(&self, board: Board, via: Via) -> Vec<Via> {
+        let i = board.idx(via);
+        let mut member_vec = Vec::new();
+        if !self.is_member_vec[i] {
+            return member_vec;
+        }
+        let mut j = i;
+        loop {
+            member_vec.push(self.to_via(j));
+            j = self.next_vec[j];
+            if j == i {
+                break;
+            }
+        }
+        member_vec
+    }
+
+    // The members of each net.
+    pub fn get_net_vec(&self) -> Vec<Vec<Via>> {
+        (0..self.board.size())
+            .filter(|&i| self.is_member_vec[i] && self.parent_vec[i] == i)
+            .map(|i| self.get_net_members(self.board, self.to_via(i)))
+            .collect()
+    }
+
+    // Store the nets in the layout, in the form used by the layout.
+    pub fn copy_to_layout(&self, layout: &mut Layout) {
+        layout.via_set_vec.clear();
+        layout.set_idx_vec = vec![usize::MAX; self.board.size()];
+        for member_vec in self.get_net_vec() {
+            for via in &member_vec {
+                layout.set_idx_vec[self.board.idx(*via)] = layout.via_set_vec.len();
+            }
+            layout.via_set_vec.push(member_vec.into_iter().collect::<ViaSet>());
+        }
+    }
+
+    //
+    // Union-find
+    //
+
+    fn add_group(&mut self, via_pair_vec: Vec<(Via, Via)>) -> NetGroupId {
+        let undo_log_len = self.undo_vec.len();
+        for &(via_a, via_b) in &via_pair_vec {
+            self.union(via_a, via_b);
+        }
+        let id = self.next_group_id;
+        self.next_group_id += 1;
+        self.group_vec.push(NetGroup {
+            id,
+            via_pair_vec,
+            undo_log_len,
+        });
+        id
+    }
+
+    fn union(&mut self, via_a: Via, via_b: Via) {
+        let a = self.board.idx(via_a);
+        let b = self.board.idx(via_b);
+        self.set_is_member(a, true);
+        self.set_is_member(b, true);
+        let mut root_a = self.find(a);
+        let mut root_b = self.find(b);
+        if root_a == root_b {
+            return;
+        }
+        if self.size_vec[root_a] < self.size_vec[root_b] {
+            std::mem::swap(&mut root_a, &mut root_b);
+        }
+        self.set_parent(root_b, root_a);
+        self.set_size(root_a, self.size_vec[root_a] + self.size_vec[root_b]);
+        // Splice the two circular member lists into one.
+        let next_a = self.next_vec[root_a];
+        let next_b = self.next_vec[root_b];
+        self.set_next(root_a, next_b);
+        self.set_next(root_b, next_a);
+    }
+
+    // Vias that already point at the root are not written, so repeating a query
+    // doesn't add to the undo log.
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent_vec[root] != root {
+            root = self.parent_vec[root];
+        }
+        let mut j = i;
+        while self.parent_vec[j] != root {
+            let next = self.parent_vec[j];
+            self.set_parent(j, root);
+            j = next;
+        }
+        root
+    }
+
+    fn set_parent(&mut self, i: usize, parent: usize) {
+        self.undo_vec.push(UndoEntry::Parent(i, self.parent_vec[i]));
+        self.parent_vec[i] = parent;
+    }
+
+    fn set_size(&mut self, i: usize, size: usize) {
+        self.undo_vec.push(UndoEntry::Size(i, self.size_vec[i]));
+        self.size_vec[i] = size;
     }
 
-    pub fn get_via_set(&self, board: Board, layout: &mut Layout, via: Via) -> &HashSet<Via> {
-        let trace_idx = board.idx(via);
-        let set_idx = self.set_idx_vec[trace_idx];
-        assert_ne!(set_idx, usize::MAX);
-        &self.via_set_vec[set_idx]
+    fn set_next(&mut self, i: usize, next: usize) {
+        self.undo_vec.push(UndoEntry::Next(i, self.next_vec[i]));
+        self.next_vec[i] = next;
     }
 
-    fn create_via_set(&mut self) -> usize {
-        self.via_set_vec.push(HashSet::new());
-        (self.via_set_vec.len() - 1)
+    fn set_is_member(&mut self, i: usize, is_member: bool) {
+        if self.is_member_vec[i] != is_member {
+            self.undo_vec.push(UndoEntry::IsMember(i, self.is_member_vec[i]));
+            self.is_member_vec[i] = is_member;
+        }
+    }
+
+    fn rollback_log(&mut self, undo_log_len: usize) {
+        while self.undo_vec.len() > undo_log_len {
+            match self.undo_vec.pop().unwrap() {
+                UndoEntry::Parent(i, v) => self.parent_vec[i] = v,
+                UndoEntry::Size(i, v) => self.size_vec[i] = v,
+                UndoEntry::Next(i, v) => self.next_vec[i] = v,
+                UndoEntry::IsMember(i, v) => self.is_member_vec[i] = v,
+            }
+        }
     }
 
-    pub fn get_via_set_idx(&self, board: Board, layout: &mut Layout, via: Via) -> usize {
-        let trace_idx = board.idx(via);
-        self.set_idx_vec[trace_idx]
+    fn to_via(&self, i: usize) -> Via {
+        Via::new(i % self.board.w, i / self.board.w)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted_net_members(nets: &Nets, via: Via) -> Vec<Via> {
+        let mut member_vec = nets.get_net_members(nets.board, via);
+        member_vec.sort_by_key(|via| (via.y, via.x));
+        member_vec
+    }
+
+    #[test]
+    fn disconnect_removes_the_net_members() {
+        let board = Board::new(5, 5);
+        let mut layout = Layout::new();
+        let mut nets = Nets::new(board);
+        let (a, b, c) = (Via::new(0, 0), Via::new(1, 0), Via::new(2, 0));
+        nets.connect(board, &mut layout, a, b);
+        let group_id = nets.connect(board, &mut layout, b, c);
+        assert_eq!(sorted_net_members(&nets, a), vec![a, b, c]);
+        assert!(nets.is_connected(board, &mut layout, a, c));
+
+        assert!(nets.disconnect(group_id));
+        assert!(!nets.disconnect(group_id));
+        assert_eq!(sorted_net_members(&nets, a), vec![a, b]);
+        assert!(sorted_net_members(&nets, c).is_empty());
+        assert!(!nets.is_connected(board, &mut layout, a, c));
+        assert!(!nets.has_connection(board, &mut layout, c));
+    }
+
+    #[test]
+    fn disconnect_of_an_early_group_replays_the_later_groups() {
+        let board = Board::new(5, 5);
+        let mut layout = Layout::new();
+        let mut nets = Nets::new(board);
+        let via_vec: Vec<Via> = (0..5).map(|x| Via::new(x, 1)).collect();
+        let first_group_id = nets.connect(board, &mut layout, via_vec[0], via_vec[1]);
+        nets.connect(board, &mut layout, via_vec[1], via_vec[2]);
+        nets.connect(board, &mut layout, via_vec[3], via_vec[4]);
+        nets.connect(board, &mut layout, via_vec[2], via_vec[3]);
+        assert_eq!(nets.get_net_vec().len(), 1);
+        // Compress the paths before they are rolled back.
+        for &via in &via_vec {
+            assert!(nets.is_connected(board, &mut layout, via, via_vec[0]));
+        }
+
+        assert!(nets.disconnect(first_group_id));
+        assert!(sorted_net_members(&nets, via_vec[0]).is_empty());
+        assert_eq!(sorted_net_members(&nets, via_vec[4]), via_vec[1..].to_vec());
+        assert_eq!(nets.get_net_vec().len(), 1);
+        assert!(!nets.is_connected(board, &mut layout, via_vec[0], via_vec[4]));
+        for &via in &via_vec[1..] {
+            assert!(nets.is_connected(board, &mut layout, via, via_vec[4]));
+        }
+        // The replayed groups can still be disconnected.
+        let checkpoint = nets.checkpoint();
+        let group_id = nets.connect(board, &mut layout, via_vec[0], via_vec[4]);
+        assert_eq!(sorted_net_members(&nets, via_vec[0]), via_vec);
+        assert!(nets.disconnect(group_id));
+        assert_eq!(nets.checkpoint(), checkpoint);
+        assert_eq!(sorted_net_members(&nets, via_vec[4]), via_vec[1..].to_vec());
+    }
+
+    #[test]
+    fn compressed_paths_are_rolled_back_with_the_group() {
+        let board = Board::new(5, 5);
+        let mut layout = Layout::new();
+        let mut nets = Nets::new(board);
+        let (a, b, c, d) = (Via::new(0, 3), Via::new(1, 3), Via::new(2, 3), Via::new(3, 3));
+        nets.connect(board, &mut layout, a, b);
+        nets.connect(board, &mut layout, c, d);
+        // d is now two steps from the root, and the query points it at the root.
+        let group_id = nets.connect(board, &mut layout, a, c);
+        assert!(nets.is_connected(board, &mut layout, d, a));
+        assert!(nets.disconnect(group_id));
+        assert!(!nets.is_connected(board, &mut layout, d, a));
+        assert!(nets.is_connected(board, &mut layout, d, c));
+    }
+
+    #[test]
+    fn repeated_queries_do_not_grow_the_undo_log() {
+        let board = Board::new(5, 5);
+        let mut layout = Layout::new();
+        let mut nets = Nets::new(board);
+        for x in 0..4 {
+            nets.connect(board, &mut layout, Via::new(x, 2), Via::new(x + 1, 2));
+        }
+        for x in 0..5 {
+            assert!(nets.is_connected(board, &mut layout, Via::new(x, 2), Via::new(0, 2)));
+        }
+        let undo_log_len = nets.undo_vec.len();
+        for x in 0..5 {
+            assert!(nets.is_connected(board, &mut layout, Via::new(x, 2), Via::new(0, 2)));
+            assert!(nets.get_net_id(board, Via::new(x, 2)).is_some());
+        }
+        assert_eq!(nets.undo_vec.len(), undo_log_len);
     }
 }
@@ -0,0 +1,263 @@
+use crate::checkpoint::{CheckpointLine, CheckpointReader, CheckpointWriter};
+use crate::circuit::Circuit;
+use crate::ga_interface::GeneticAlgorithm;
+use crate::ga_stats::{GenerationStats, StatsHistory};
+use crate::hardest_first::HardestFirst;
+use crate::local_search::LocalSearch;
+use crate::settings::{GaSettings, OrderingSearchStrategy};
+use std::collections::{HashMap, VecDeque};
+
+// The router threads work on an infinite series of connection orderings. An
+// OrderingSearch is the black box that the orderings come from, and that the result of
+// routing each ordering is passed back to, so that later orderings can improve on the
+// earlier ones.
+//
+// The protocol is the one described for GeneticAlgorithm, which was the first
+// implementation:
+//
+// - reset() is called for the circuit before the first ordering is reserved.
+// - warm_start() is called instead when the connections in the circuit have changed,
+// to keep what has been learned about the connections that are still there.
+// - A thread reserves an ordering with reserve_ordering(), gets it with
+// get_ordering(), routes it, and releases it with release_ordering(), passing the
+// number of completed routes and the cost of the resulting layout.
+// - reserve_ordering() returns usize::MAX if no ordering is available right now. The
+// thread must then release the lock and wait for another thread to release an
+// ordering.
+//
+// The caller holds a single lock around all calls. The searches keep no state that
+// depends on the input layout other than the circuit, so the routing settings can
+// change while the search is running, as long as clear_fitness_cache() is called.
+//
+// The searches that don't have generations record their statistics in rounds of a
+// fixed number of orderings instead. See RoundStats.
+
+pub type ConnectionIdxVec = Vec<usize>;
+pub type OrderingIdx = usize;
+
+pub trait OrderingSearch: Send {
+    fn get_strategy(&self) -> OrderingSearchStrategy;
+
+    fn reset(&mut self, circuit: &Circuit);
+
+    fn warm_start(&mut self, circuit: &Circuit);
+
+    fn set_settings(&mut self, _ga_settings: &GaSettings) {}
+
+    // Must be called when something changes that causes an ordering to give a
+    // different layout than before.
+    fn clear_fitness_cache(&mut self) {}
+
+    fn get_n_fitness_cache_hits(&self) -> usize {
+        0
+    }
+
+    fn reserve_ordering(&mut self) -> OrderingIdx;
+
+    fn get_ordering(&self, ordering_idx: OrderingIdx) -> ConnectionIdxVec;
+
+    fn release_ordering(&mut self, ordering_idx: OrderingIdx, n_completed_routes: usize, completed_route_cost: usize);
+
+    // Generations for the GA, rounds for the other searches.
+    fn get_n_generations(&self) -> usize;
+
+    fn get_stats_history(&self) -> &StatsHistory;
+
+    // See StatsHistory.
+    fn get_stats_vec(&self) -> Vec<GenerationStats> {
+        self.get_stats_history().get_stats_vec().clone()
+    }
+
+    fn get_stats_csv(&self) -> String {
+        self.get_stats_history().to_csv()
+    }
+
+    fn write_checkpoint(&self, writer: &mut CheckpointWriter);
+
+    // The search is restored for the connections it was saved for. Call warm_start()
+    // afterwards if the connections have changed. On error, nothing is changed.
+    fn read_checkpoint(&mut self, reader: &mut CheckpointReader) -> Result<(), String>;
+}
+
+pub fn create_ordering_search(
+    strategy: OrderingSearchStrategy,
+    ga_settings: &GaSettings,
+    seed: u64,
+) -> Box<dyn OrderingSearch> {
+    match strategy {
+        OrderingSearchStrategy::GeneticAlgorithm => Box::new(GeneticAlgorithm::new(ga_settings, seed)),
+        OrderingSearchStrategy::RandomRestart | OrderingSearchStrategy::SimulatedAnnealing => {
+            Box::new(LocalSearch::new(strategy, seed))
+        }
+        OrderingSearchStrategy::HardestFirst => Box::new(HardestFirst::new(seed)),
+    }
+}
+
+// For each of the old connections, the index of the same connection among the new
+// ones, or None if it has been removed. If the same connection appears more than once,
+// the occurrences are matched in order.
+pub fn map_connections(old_key_vec: &[String], new_key_vec: &[String]) -> Vec<Option<usize>> {
+    let mut new_idx_map: HashMap<&String, VecDeque<usize>> = HashMap::new();
+    for (new_idx, key) in new_key_vec.iter().enumerate() {
+        new_idx_map.entry(key).or_default().push_back(new_idx);
+    }
+    old_key_vec
+        .iter()
+        .map(|key| new_idx_map.get_mut(key).and_then(|idx_queue| idx_queue.pop_front()))
+        .collect()
+}
+
+// Move an ordering to the new connection indexes. The connections that are still there
+// keep their relative order, and the new connections are added at the end.
+pub fn remap_ordering(ordering: &[usize], connection_map: &[Option<usize>], n_connections: usize) -> ConnectionIdxVec {
+    let mut remapped_ordering: ConnectionIdxVec = ordering
+        .iter()
+        .filter_map(|&connection_idx| connection_map[connection_idx])
+        .collect();
+    let mut is_included_vec = vec![false; n_connections];
+    for &connection_idx in &remapped_ordering {
+        is_included_vec[connection_idx] = true;
+    }
+    remapped_ordering.extend((0..n_connections).filter(|&connection_idx| !is_included_vec[connection_idx]));
+    remapped_ordering
+}
+
+// Read an ordering of all the connections from a checkpoint line.
+pub fn parse_ordering(
+    line: &CheckpointLine,
+    first_idx: usize,
+    n_connections: usize,
+) -> Result<ConnectionIdxVec, String> {
+    let ordering: ConnectionIdxVec = line.get_vec(first_idx)?;
+    let mut sorted_ordering = ordering.clone();
+    sorted_ordering.sort();
+    if !sorted_ordering.iter().copied().eq(0..n_connections) {
+        return Err(line.error("not an ordering of the connections"));
+    }
+    Ok(ordering)
+}
+
+// The fitness of an ordering is the fitness of the layout it gives. More completed
+// routes is always better, and the cost only decides between layouts with the same
+// number of completed routes, as for the GA organisms.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Fitness {
+    pub n_completed_routes: usize,
+    pub completed_route_cost: usize,
+}
+
+impl Fitness {
+    pub fn new(n_completed_routes: usize, completed_route_cost: usize) -> Self {
+        Self {
+            n_completed_routes,
+            completed_route_cost,
+        }
+    }
+
+    pub fn cmp_fitness(&self, other: &Self) -> std::cmp::Ordering {
+        self.n_completed_routes
+            .cmp(&other.n_completed_routes)
+            .then(other.completed_route_cost.cmp(&self.completed_route_cost))
+    }
+
+    pub fn is_fitter_than(&self, other: &Self) -> bool {
+        self.cmp_fitness(other) == std::cmp::Ordering::Greater
+    }
+}
+
+// For the searches that don't have generations, the orderings are grouped in rounds of
+// N_ORDERINGS_PER_ROUND, in the order they are released, and the statistics for each
+// round are recorded as if it was a generation.
+const N_ORDERINGS_PER_ROUND: usize = 100;
+
+pub struct RoundStats {
+    stats_history: StatsHistory,
+    n_rounds: usize,
+    fitness_vec: Vec<(usize, usize)>,
+    ordering_vec: Vec<ConnectionIdxVec>,
+}
+
+impl RoundStats {
+    pub fn new() -> Self {
+        Self {
+            stats_history: StatsHistory::new(),
+            n_rounds: 0,
+            fitness_vec: Vec::new(),
+            ordering_vec: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn get_n_rounds(&self) -> usize {
+        self.n_rounds
+    }
+
+    // When resuming from a checkpoint. The history is not saved, so it starts over.
+    pub fn set_n_rounds(&mut self, n_rounds: usize) {
+        self.clear();
+        self.n_rounds = n_rounds;
+    }
+
+    pub fn get_stats_history(&self) -> &StatsHistory {
+        &self.stats_history
+    }
+
+    pub fn record(&mut self, ordering: ConnectionIdxVec, fitness: Fitness) {
+        self.fitness_vec
+            .push((fitness.n_completed_routes, fitness.completed_route_cost));
+        self.ordering_vec.push(ordering);
+        if self.fitness_vec.len() < N_ORDERINGS_PER_ROUND {
+            return;
+        }
+        if self.stats_history.is_recorded(self.n_rounds) {
+            self.fitness_vec
+                .sort_by(|a, b| Fitness::new(b.0, b.1).cmp_fitness(&Fitness::new(a.0, a.1)));
+            let ordering_set = self.ordering_vec.drain(..).collect();
+            let time = self.stats_history.get_time();
+            if let Some(stats) = GenerationStats::new(self.n_rounds, time, &self.fitness_vec, &ordering_set) {
+                self.stats_history.push(stats);
+            }
+        }
+        self.fitness_vec.clear();
+        self.ordering_vec.clear();
+        self.n_rounds += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remapped_ordering_keeps_the_order_of_the_remaining_connections() {
+        let old_key_vec: Vec<String> = ["a", "b", "c", "d"].iter().map(|s| s.to_string()).collect();
+        let new_key_vec: Vec<String> = ["e", "d", "b", "a"].iter().map(|s| s.to_string()).collect();
+        let connection_map = map_connections(&old_key_vec, &new_key_vec);
+        assert_eq!(connection_map, vec![Some(3), Some(2), None, Some(1)]);
+        assert_eq!(remap_ordering(&[2, 1, 3, 0], &connection_map, 4), vec![2, 1, 3, 0]);
+        assert_eq!(remap_ordering(&[3, 2, 1, 0], &connection_map, 4), vec![1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn every_search_hands_out_complete_orderings() {
+        let mut layout = crate::layout::Layout::new();
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("circuits/example.circuit");
+        crate::circuit_parser::CircuitFileParser::new(&mut layout).parse(path.as_os_str());
+        let circuit = layout.circuit;
+        let n_connections = circuit.connection_vec.len();
+        for strategy in OrderingSearchStrategy::ALL {
+            let mut ordering_search = create_ordering_search(strategy, &GaSettings::new(), 1);
+            ordering_search.reset(&circuit);
+            for i in 0..250 {
+                let ordering_idx = ordering_search.reserve_ordering();
+                let mut ordering = ordering_search.get_ordering(ordering_idx);
+                ordering.sort();
+                assert_eq!(ordering, (0..n_connections).collect::<Vec<_>>(), "{:?}", strategy);
+                ordering_search.release_ordering(ordering_idx, i % 3, 1000 - i);
+            }
+        }
+    }
+}
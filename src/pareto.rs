@@ -0,0 +1,242 @@
+use crate::layout::Layout;
+
+// Archive of the layouts that are best in some trade-off between the objectives below.
+//
+// The ordering searches rank layouts by the number of completed routes and then the
+// cost, which weighs the objectives against each other with the cost settings. The
+// archive keeps the alternatives, so that a layout with, say, a few more wires but fewer
+// cuts can be picked by hand.
+//
+// A layout dominates another if it's at least as good in every objective and better in
+// at least one. The archive holds the layouts found so far that are not dominated by any
+// other layout found so far (the Pareto front). A layout with the same objectives as one
+// that is already in the archive is dropped.
+//
+// The front can grow large, so it's limited to MAX_N_PARETO_LAYOUTS. When it's full, the
+// layout in the most crowded part of the front is dropped, as in NSGA-II: For each
+// objective, the layouts are sorted by it, and each layout gets the gap between its
+// neighbours, relative to the range of the objective. The crowding distance is the sum
+// of the gaps, and the layouts at the ends of the range for any objective are always
+// kept.
+
+const MAX_N_PARETO_LAYOUTS: usize = 50;
+const N_OBJECTIVES: usize = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LayoutObjectives {
+    pub n_completed_routes: usize,
+    pub n_cuts: usize,
+    pub n_wires: usize,
+    pub wire_len: usize,
+    // See Layout::calc_used_area().
+    pub used_area: usize,
+}
+
+impl LayoutObjectives {
+    pub const CSV_HEADER: &'static str = "id,completed_routes,cuts,wires,wire_length,used_area";
+
+    pub fn new(layout: &Layout) -> Self {
+        Self {
+            n_completed_routes: layout.n_completed_routes,
+            n_cuts: layout.strip_cut_vec.len(),
+            n_wires: layout.cost_breakdown.n_wires,
+            wire_len: layout.cost_breakdown.wire_len,
+            used_area: layout.calc_used_area(),
+        }
+    }
+
+    // The objectives as values where lower is better.
+    fn to_min_vec(self) -> [f64; N_OBJECTIVES] {
+        [
+            -(self.n_completed_routes as f64),
+            self.n_cuts as f64,
+            self.n_wires as f64,
+            self.wire_len as f64,
+            self.used_area as f64,
+        ]
+    }
+
+    pub fn dominates(&self, other: &Self) -> bool {
+        let a = self.to_min_vec();
+        let b = other.to_min_vec();
+        a.iter().zip(b.iter()).all(|(a, b)| a <= b) && a != b
+    }
+
+    pub fn csv_row(&self, id: usize) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            id, self.n_completed_routes, self.n_cuts, self.n_wires, self.wire_len, self.used_area
+        )
+    }
+}
+
+pub struct ParetoLayout {
+    // Stays the same while the layout is in the archive, so that it can be selected in
+    // the GUI while the archive changes.
+    pub id: usize,
+    pub objectives: LayoutObjectives,
+    pub layout: Layout,
+}
+
+pub struct ParetoArchive {
+    pareto_layout_vec: Vec<ParetoLayout>,
+    next_id: usize,
+}
+
+impl ParetoArchive {
+    pub fn new() -> Self {
+        Self {
+            pareto_layout_vec: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    // Must be called when the circuit changes, since the layouts are for the old one.
+    pub fn clear(&mut self) {
+        self.pareto_layout_vec.clear();
+    }
+
+    // Add the layout if it's not dominated. Returns true if it's in the archive
+    // afterwards. The layout is only copied if it's added.
+    pub fn insert(&mut self, layout: &Layout) -> bool {
+        let objectives = LayoutObjectives::new(layout);
+        let is_dominated = self
+            .pareto_layout_vec
+            .iter()
+            .any(|p| p.objectives == objectives || p.objectives.dominates(&objectives));
+        if is_dominated {
+            return false;
+        }
+        self.pareto_layout_vec.retain(|p| !objectives.dominates(&p.objectives));
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pareto_layout_vec.push(ParetoLayout {
+            id,
+            objectives,
+            layout: layout.clone(),
+        });
+        if self.pareto_layout_vec.len() > MAX_N_PARETO_LAYOUTS {
+            let most_crowded_idx = self.find_most_crowded_idx();
+            self.pareto_layout_vec.remove(most_crowded_idx);
+        }
+        self.pareto_layout_vec.last().is_some_and(|p| p.id == id)
+    }
+
+    // The ID and objectives of each layout, without the layouts themselves.
+    pub fn get_objectives_vec(&self) -> Vec<(usize, LayoutObjectives)> {
+        self.pareto_layout_vec.iter().map(|p| (p.id, p.objectives)).collect()
+    }
+
+    // None if the layout has been dropped from the archive.
+    pub fn get_layout(&self, id: usize) -> Option<Layout> {
+        self.pareto_layout_vec
+            .iter()
+            .find(|p| p.id == id)
+            .map(|p| p.layout.clone())
+    }
+
+    pub fn get_pareto_layout_vec(&self) -> &Vec<ParetoLayout> {
+        &self.pareto_layout_vec
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = LayoutObjectives::CSV_HEADER.to_string();
+        csv.push('\n');
+        for p in &self.pareto_layout_vec {
+            csv.push_str(&p.objectives.csv_row(p.id));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    fn find_most_crowded_idx(&self) -> usize {
+        let n = self.pareto_layout_vec.len();
+        let min_vec_vec: Vec<[f64; N_OBJECTIVES]> = self
+            .pareto_layout_vec
+            .iter()
+            .map(|p| p.objectives.to_min_vec())
+            .collect();
+        let mut crowding_distance_vec = vec![0.0; n];
+        for objective_idx in 0..N_OBJECTIVES {
+            let value_vec: Vec<f64> = min_vec_vec.iter().map(|min_vec| min_vec[objective_idx]).collect();
+            let value = |idx: usize| value_vec[idx];
+            let mut idx_vec: Vec<usize> = (0..n).collect();
+            idx_vec.sort_by(|&a, &b| value(a).total_cmp(&value(b)));
+            let min = value(idx_vec[0]);
+            let max = value(idx_vec[n - 1]);
+            if max == min {
+                continue;
+            }
+            crowding_distance_vec[idx_vec[0]] = f64::INFINITY;
+            crowding_distance_vec[idx_vec[n - 1]] = f64::INFINITY;
+            for i in 1..n - 1 {
+                let gap = value(idx_vec[i + 1]) - value(idx_vec[i - 1]);
+                crowding_distance_vec[idx_vec[i]] += gap / (max - min);
+            }
+        }
+        (0..n)
+            .min_by(|&a, &b| crowding_distance_vec[a].total_cmp(&crowding_distance_vec[b]))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::via::Via;
+
+    fn layout(n_completed_routes: usize, n_cuts: usize, n_wires: usize) -> Layout {
+        let mut layout = Layout::new();
+        layout.n_completed_routes = n_completed_routes;
+        layout.strip_cut_vec = vec![Via::new(0, 0); n_cuts];
+        layout.cost_breakdown.n_wires = n_wires;
+        layout
+    }
+
+    #[test]
+    fn only_non_dominated_layouts_are_kept() {
+        let mut archive = ParetoArchive::new();
+        assert!(archive.insert(&layout(10, 5, 5)));
+        assert!(archive.insert(&layout(10, 3, 8)));
+        assert!(!archive.insert(&layout(10, 3, 8)));
+        assert!(!archive.insert(&layout(9, 3, 8)));
+        assert!(!archive.insert(&layout(10, 6, 6)));
+        assert!(archive.insert(&layout(9, 1, 1)));
+        let objectives_vec = archive.get_objectives_vec();
+        assert_eq!(
+            objectives_vec.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+
+        // Dominates the first two.
+        assert!(archive.insert(&layout(11, 3, 5)));
+        assert_eq!(
+            archive
+                .get_objectives_vec()
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(archive.get_layout(0).is_none());
+        assert_eq!(archive.get_layout(3).unwrap().n_completed_routes, 11);
+    }
+
+    #[test]
+    fn full_archive_drops_the_most_crowded_layout_and_keeps_the_extremes() {
+        let mut archive = ParetoArchive::new();
+        // A front where fewer cuts cost more wires.
+        for n_cuts in 0..=MAX_N_PARETO_LAYOUTS {
+            let n_wires = if n_cuts < MAX_N_PARETO_LAYOUTS {
+                1000 - n_cuts
+            } else {
+                0
+            };
+            archive.insert(&layout(10, n_cuts, n_wires));
+        }
+        let objectives_vec = archive.get_objectives_vec();
+        assert_eq!(objectives_vec.len(), MAX_N_PARETO_LAYOUTS);
+        assert!(objectives_vec.iter().any(|(_, o)| o.n_cuts == 0));
+        assert!(objectives_vec.iter().any(|(_, o)| o.n_wires == 0));
+    }
+}
@@ -0,0 +1,248 @@
+use std::path::Path;
+
+// Minimal PDF writer
+//
+// Just enough of PDF for the assembly sheets: pages with lines, rectangles, circles
+// and text in the standard fonts. The standard fonts are built into all PDF readers,
+// so nothing is embedded, and the content streams are not compressed.
+//
+// Positions and sizes are in points (1/72 inch), with the origin at the top left of
+// the page and y growing downwards, as on the board. They are flipped to the PDF
+// convention, with the origin at the bottom left, when written.
+//
+// The document asks the reader not to scale the pages when printing (PrintScaling
+// /None), so that they are printed at their actual size. That was added in PDF 1.6.
+
+pub const MM_TO_PT: f32 = 72.0 / 25.4;
+
+pub type Rgb = [f32; 3];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PdfFont {
+    Helvetica,
+    // Monospaced, so the width of a text is known. See PdfPage::text_width().
+    Courier,
+}
+
+impl PdfFont {
+    fn resource_name(&self) -> &'static str {
+        match self {
+            PdfFont::Helvetica => "F1",
+            PdfFont::Courier => "F2",
+        }
+    }
+}
+
+// Bezier control point distance for drawing a quarter circle.
+const CIRCLE_KAPPA: f32 = 0.5523;
+
+pub struct PdfPage {
+    w: f32,
+    h: f32,
+    content: String,
+}
+
+impl PdfPage {
+    pub fn new(w: f32, h: f32) -> Self {
+        Self {
+            w,
+            h,
+            content: String::new(),
+        }
+    }
+
+    pub fn set_stroke_color(&mut self, color: Rgb) {
+        self.content += &format!("{:.3} {:.3} {:.3} RG\n", color[0], color[1], color[2]);
+    }
+
+    pub fn set_fill_color(&mut self, color: Rgb) {
+        self.content += &format!("{:.3} {:.3} {:.3} rg\n", color[0], color[1], color[2]);
+    }
+
+    pub fn set_line_width(&mut self, line_w: f32) {
+        self.content += &format!("{:.3} w\n", line_w);
+    }
+
+    pub fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        self.content += &format!("{:.3} {:.3} m {:.3} {:.3} l S\n", x1, self.h - y1, x2, self.h - y2);
+    }
+
+    // The top left corner and the size.
+    pub fn rect(&mut self, x: f32, y: f32, w: f32, h: f32, is_filled: bool) {
+        let op = if is_filled { "f" } else { "S" };
+        self.content += &format!("{:.3} {:.3} {:.3} {:.3} re {}\n", x, self.h - y - h, w, h, op);
+    }
+
+    pub fn circle(&mut self, cx: f32, cy: f32, r: f32, is_filled: bool) {
+        let cy = self.h - cy;
+        let k = r * CIRCLE_KAPPA;
+        self.content += &format!("{:.3} {:.3} m\n", cx + r, cy);
+        for (x1, y1, x2, y2, x3, y3) in [
+            (cx + r, cy + k, cx + k, cy + r, cx, cy + r),
+            (cx - k, cy + r, cx - r, cy + k, cx - r, cy),
+            (cx - r, cy - k, cx - k, cy - r, cx, cy - r),
+            (cx + k, cy - r, cx + r, cy - k, cx + r, cy),
+        ] {
+            self.content += &format!("{:.3} {:.3} {:.3} {:.3} {:.3} {:.3} c\n", x1, y1, x2, y2, x3, y3);
+        }
+        self.content += if is_filled { "f\n" } else { "S\n" };
+    }
+
+    // A zero length line with round caps, which is much shorter to write than a circle.
+    // Used for the holes, which there are thousands of.
+    pub fn dot(&mut self, cx: f32, cy: f32, d: f32, color: Rgb) {
+        self.content += &format!(
+            "q 1 J {:.3} w {:.3} {:.3} {:.3} RG {:.3} {:.3} m {:.3} {:.3} l S Q\n",
+            d,
+            color[0],
+            color[1],
+            color[2],
+            cx,
+            self.h - cy,
+            cx,
+            self.h - cy
+        );
+    }
+
+    // The text starts at x, with its baseline at y. Only ASCII is supported, and other
+    // characters are written as "?".
+    pub fn text(&mut self, x: f32, y: f32, size: f32, font: PdfFont, text: &str) {
+        self.content += &format!(
+            "BT /{} {:.3} Tf {:.3} {:.3} Td ({}) Tj ET\n",
+            font.resource_name(),
+            size,
+            x,
+            self.h - y,
+            escape_text(text)
+        );
+    }
+
+    // The width of a text in Courier, where all characters are 0.6 of the font size
+    // wide.
+    pub fn text_width(size: f32, text: &str) -> f32 {
+        text.chars().count() as f32 * size * 0.6
+    }
+}
+
+pub struct PdfDocument {
+    title: String,
+    page_vec: Vec<PdfPage>,
+}
+
+impl PdfDocument {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            page_vec: Vec::new(),
+        }
+    }
+
+    pub fn add_page(&mut self, page: PdfPage) {
+        self.page_vec.push(page);
+    }
+
+    pub fn n_pages(&self) -> usize {
+        self.page_vec.len()
+    }
+
+    // Objects 1 to 5 are the catalog, the page tree, the two fonts and the document
+    // info. Each page is then a page object followed by its content stream.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut object_vec = vec![
+            "<< /Type /Catalog /Pages 2 0 R /ViewerPreferences << /PrintScaling /None >> >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                (0..self.page_vec.len())
+                    .map(|i| format!("{} 0 R", 6 + i * 2))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                self.page_vec.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>".to_string(),
+            format!("<< /Title ({}) /Producer (striprouter) >>", escape_text(&self.title)),
+        ];
+        for (i, page) in self.page_vec.iter().enumerate() {
+            object_vec.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.3} {:.3}] \
+                 /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                page.w,
+                page.h,
+                7 + i * 2
+            ));
+            object_vec.push(format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                page.content.len(),
+                page.content
+            ));
+        }
+
+        let mut pdf = String::from("%PDF-1.6\n");
+        let mut offset_vec = Vec::new();
+        for (i, object) in object_vec.iter().enumerate() {
+            offset_vec.push(pdf.len());
+            pdf += &format!("{} 0 obj\n{}\nendobj\n", i + 1, object);
+        }
+        let xref_offset = pdf.len();
+        pdf += &format!("xref\n0 {}\n0000000000 65535 f \n", object_vec.len() + 1);
+        for offset in offset_vec {
+            pdf += &format!("{:010} 00000 n \n", offset);
+        }
+        pdf += &format!(
+            "trailer\n<< /Size {} /Root 1 0 R /Info 5 0 R >>\nstartxref\n{}\n%%EOF\n",
+            object_vec.len() + 1,
+            xref_offset
+        );
+        pdf.into_bytes()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes()).map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' | '(' | ')' => format!("\\{}", c),
+            ' '..='~' => c.to_string(),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xref_offsets_point_at_the_objects() {
+        let mut document = PdfDocument::new("Test");
+        for _ in 0..2 {
+            let mut page = PdfPage::new(100.0, 200.0);
+            page.line(0.0, 0.0, 10.0, 10.0);
+            page.text(5.0, 5.0, 10.0, PdfFont::Courier, "(x)");
+            document.add_page(page);
+        }
+        let pdf = String::from_utf8(document.to_bytes()).unwrap();
+        assert!(pdf.starts_with("%PDF-1.6\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        let xref_offset: usize = pdf.lines().rev().nth(1).unwrap().parse().unwrap();
+        assert!(pdf[xref_offset..].starts_with("xref\n0 10\n"));
+        for (i, line) in pdf[xref_offset..].lines().skip(3).take(9).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj\n", i + 1)));
+        }
+    }
+
+    #[test]
+    fn positions_are_flipped_and_text_is_escaped() {
+        let mut page = PdfPage::new(100.0, 200.0);
+        page.line(1.0, 2.0, 3.0, 4.0);
+        page.text(5.0, 10.0, 8.0, PdfFont::Helvetica, "a(b)\\ø");
+        assert_eq!(
+            page.content,
+            "1.000 198.000 m 3.000 196.000 l S\nBT /F1 8.000 Tf 5.000 190.000 Td (a\\(b\\)\\\\?) Tj ET\n"
+        );
+    }
+}
@@ -49,6 +49,9 @@ pub struct Render {
 
     border_color: Color32,
 
+    cost_region_color: Color32,
+    cost_region_label_color: Color32,
+
     diag_wire_layer_color: Color32,
     diag_strip_layer_color: Color32,
     diag_wire_cost_color: Color32,
@@ -86,6 +89,8 @@ impl Render {
             rats_nest_success_color: Self::color(0.0, 0.584, 0.192, 0.5),
             rats_nest_failed_color: Self::color(0.784, 0.3, 0.0, 0.5),
             border_color: Self::color(0.0, 0.0, 0.0, 1.0),
+            cost_region_color: Self::color(0.6, 0.0, 0.8, 0.25),
+            cost_region_label_color: Self::color(0.6, 0.0, 0.8, 1.0),
             diag_wire_layer_color: Self::color(1.0, 0.0, 0.0, 1.0),
             diag_strip_layer_color: Self::color(0.0, 1.0, 0.0, 1.0),
             diag_wire_cost_color: Self::color(1.0, 0.0, 0.0, 1.0),
@@ -117,6 +122,7 @@ impl Render {
         // window_h: usize,
         show_rats_nest: bool,
         show_only_failed: bool,
+        show_cost_regions: bool,
     ) {
         let mouse_via = self.get_mouse_via(ui, layout);
         // println!("mouse_via: {:?}", mouse_via);
@@ -127,6 +133,9 @@ impl Render {
         self.draw_strip_sections(ui, layout, &mouse_net, is_mouse_on_net);
         self.draw_wire_sections(ui, layout, &mouse_net, is_mouse_on_net);
         self.draw_strip_cuts(ui, layout);
+        if show_cost_regions {
+            self.draw_cost_regions(ui, layout);
+        }
         self.draw_components(ui, layout);
         if show_rats_nest {
             self.draw_rats_nest(ui, layout, show_only_failed);
@@ -135,6 +144,9 @@ impl Render {
         if layout.circuit.has_parser_error() {
             self.draw_diag(ui, layout);
         }
+        if let Some(route_idx) = self.get_mouse_route_idx(layout, &mouse_via) {
+            self.draw_route_info(ctx, layout, route_idx);
+        }
     }
 
     pub fn draw_strip_sections(&self, ui: &mut Ui, layout: &Layout, mouse_net: &Vec<Via>, is_mouse_on_net: bool) {
@@ -256,6 +268,35 @@ impl Render {
         }
     }
 
+    // Shade the cost regions, and label each with the factors that are above 1, at its
+    // top left corner.
+    pub fn draw_cost_regions(&self, ui: &mut Ui, layout: &Layout) {
+        for cost_region in &layout.circuit.cost_region_vec {
+            let start = cost_region.rect.start.cast::<f32>() - Pos::new(0.5, 0.5);
+            let end = cost_region.rect.end.cast::<f32>() + Pos::new(0.5, 0.5);
+            self.draw_filled_rectangle(ui, start, end, &self.cost_region_color);
+            let cost_factors = &cost_region.cost_factors;
+            let label = [
+                ("strip", cost_factors.strip_factor),
+                ("wire", cost_factors.wire_factor),
+                ("via", cost_factors.via_factor),
+            ]
+            .iter()
+            .filter(|(_, factor)| *factor > 1)
+            .map(|(name, factor)| format!("{}x{}", name, factor))
+            .collect::<Vec<_>>()
+            .join(" ");
+            let pos = self.to_draw_pos2(ui, &start);
+            ui.painter().text(
+                pos,
+                Align2::LEFT_TOP,
+                label,
+                self.label_font_id.clone(),
+                self.cost_region_label_color,
+            );
+        }
+    }
+
     pub fn draw_rats_nest(&self, ui: &mut Ui, layout: &Layout, show_only_failed: bool) {
         let routed_con_vec = &layout.route_status_vec;
         let all_con_vec = layout.circuit.gen_connection_via_vec();
@@ -468,6 +509,35 @@ impl Render {
         }
     }
 
+    // Get the index of the route that passes through the via that the mouse is
+    // hovering over, if any.
+    pub fn get_mouse_route_idx(&self, layout: &Layout, mouse_via: &ValidVia) -> Option<usize> {
+        if !mouse_via.is_valid {
+            return None;
+        }
+        let v = mouse_via.via;
+        layout.route_vec.iter().position(|route_section_vec| {
+            route_section_vec.iter().any(|section| {
+                let start = section.start.via;
+                let end = section.end.via;
+                v.x >= start.x.min(end.x) && v.x <= start.x.max(end.x) && v.y >= start.y.min(end.y) && v.y <= start.y.max(end.y)
+            })
+        })
+    }
+
+    // Show the cost breakdown of a route in a tooltip at the mouse pointer.
+    pub fn draw_route_info(&self, ctx: &Context, layout: &Layout, route_idx: usize) {
+        let Some(cost_breakdown) = layout.route_cost_vec.get(route_idx) else {
+            return;
+        };
+        show_tooltip_at_pointer(ctx, Id::new("route-info"), |ui| {
+            ui.label(format!("Route {}", route_idx + 1));
+            for line in cost_breakdown.info_vec(&layout.settings) {
+                ui.label(line);
+            }
+        });
+    }
+
     // Determine if the mouse is hovering over a stripboard section that is used in
     // the circuit.
     pub fn get_net(&self, ui: &mut Ui, layout: &Layout, mouse_via: &ValidVia) -> Vec<Via> {
@@ -1,6 +1,7 @@
 use crate::board::Board;
-use crate::layout::Layout;
-use crate::nets::Nets;
+use crate::circuit::{CostFactors, NetAttributes, PAIR_DETOUR_COST_FACTOR};
+use crate::layout::{CostBreakdown, Layout, RouteStepVec};
+use crate::nets::{NetGroupId, Nets};
 // use crate::thread_stop::ThreadStop;
 use crate::ucs::UniformCostSearch;
 use crate::via::{LayerStartEndVia, LayerVia, StartEndVia, ValidVia, Via, WireLayerVia};
@@ -10,11 +11,32 @@ use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 
+// A route that has been added to the layout. The steps are kept so that the route
+// can be ripped up again.
+#[derive(Clone)]
+struct RoutedConnection {
+    connection_idx: usize,
+    route_step_vec: RouteStepVec,
+    cost_breakdown: CostBreakdown,
+    net_group_id: NetGroupId,
+}
+
 pub struct Router {
     board: Board,
     // connection_idx_vec: Vec<usize>,
     all_pin_set: HashSet<Via>,
     via_trace_vec: Vec<WireLayerVia>,
+    // Reused for all the routes in the layout.
+    ucs: UniformCostSearch,
+    routed_vec: Vec<RoutedConnection>,
+    // The attributes of the net that each connection is in, by connection index.
+    net_attributes_vec: Vec<NetAttributes>,
+    // The cost factors of the cost regions, by via index. Empty if there are none.
+    cost_factors_vec: Vec<CostFactors>,
+    // While routing a connection in a pair whose other connection has been routed,
+    // whether each via is next to the route of the other connection, by via index.
+    // Empty otherwise. See ConnectionGroup.
+    is_next_to_pair_vec: Vec<bool>,
     // input_layout: & Layout,
     // current_layout: & Layout,
     // thread_stop: & ThreadStop,
@@ -29,6 +51,11 @@ impl Router {
             all_pin_set: HashSet::new(),
             // THIS FILLS WITH 0,0 VIAS WHILE THE C++ VERSION FILLS WITH -1,-1.
             via_trace_vec: vec![WireLayerVia::new(); board.size()],
+            ucs: UniformCostSearch::new(board),
+            routed_vec: Vec::new(),
+            net_attributes_vec: Vec::new(),
+            cost_factors_vec: Vec::new(),
+            is_next_to_pair_vec: Vec::new(),
         }
     }
 
@@ -40,14 +67,22 @@ impl Router {
         connection_idx_vec: Vec<usize>,
         limit_routes: &mut Arc<AtomicUsize>,
     ) -> bool {
+        self.net_attributes_vec = layout.circuit.gen_connection_net_attributes_vec();
+        self.cost_factors_vec = layout.circuit.gen_cost_factors_vec(board);
+        let connection_idx_vec = self.move_priority_connections_first(connection_idx_vec);
         self.block_component_footprints(board, layout);
         self.join_all_connections(board, layout, nets);
         self.register_active_component_pins(layout);
-        let is_aborted = self.route_all(board, layout, nets, connection_idx_vec, limit_routes);
-        let strip_cut_vec = self.find_strip_cuts(board, layout, nets);
-
-        // TODO: Renable!
-        // layout.cost += (layout.settings.cut_cost * strip_cut_vec.len());
+        let is_aborted = self.route_all(board, layout, nets, connection_idx_vec.clone(), limit_routes);
+        for _ in 0..layout.settings.rip_up_passes {
+            if !self.rip_up_and_reroute(board, layout, nets, &connection_idx_vec) {
+                break;
+            }
+        }
+        layout.strip_cut_vec = self.find_strip_cuts(board, layout, nets);
+        layout.add_cut_cost();
+        layout.add_area_cost();
+        layout.add_length_mismatch_cost();
 
         // if layout.has_error {
         //     layout.diag_trace_vec = self.via_trace_vec.clone();
@@ -56,6 +91,16 @@ impl Router {
         is_aborted
     }
 
+    // The connections in priority nets are routed first, in the order they have in the
+    // ordering, so that the ordering search can't push them back.
+    fn move_priority_connections_first(&self, connection_idx_vec: Vec<usize>) -> Vec<usize> {
+        let (mut priority_vec, other_vec): (Vec<usize>, Vec<usize>) = connection_idx_vec
+            .into_iter()
+            .partition(|&i| self.net_attributes_vec.get(i).is_some_and(|a| a.is_priority));
+        priority_vec.extend(other_vec);
+        priority_vec
+    }
+
     fn route_all(
         &mut self,
         board: Board,
@@ -85,7 +130,7 @@ impl Router {
             limit -= 1;
 
             let start_end_via = connection_via_vec[connection_idx];
-            let route_was_found = self.find_complete_route(board, layout, nets, start_end_via);
+            let route_was_found = self.find_complete_route(board, layout, nets, connection_idx, start_end_via);
             layout.route_status_vec[connection_idx] = route_was_found;
             // if self.thread_stop.is_stopped() {
             //     is_aborted = true;
@@ -130,9 +175,10 @@ impl Router {
         board: Board,
         layout: &mut Layout,
         nets: &mut Nets,
+        connection_idx: usize,
         start_end_via: StartEndVia,
     ) -> bool {
-        let route_was_found = self.find_route(board, layout, nets, start_end_via);
+        let route_was_found = self.find_route(board, layout, nets, connection_idx, start_end_via);
         if route_was_found {
             layout.n_completed_routes += 1;
         } else {
@@ -141,23 +187,158 @@ impl Router {
         route_was_found
     }
 
-    fn find_route(&mut self, board: Board, layout: &mut Layout, nets: &mut Nets, start_end_via: StartEndVia) -> bool {
-        let mut ucs = UniformCostSearch::new(board);
-        let route_step_vec = ucs.find_lowest_cost_route(board, layout, nets, self, start_end_via);
+    fn find_route(
+        &mut self,
+        board: Board,
+        layout: &mut Layout,
+        nets: &mut Nets,
+        connection_idx: usize,
+        start_end_via: StartEndVia,
+    ) -> bool {
+        let cost_breakdown_before = layout.cost_breakdown;
+        // The search needs access to the router while it runs, so it's moved out for
+        // the duration. The placeholder doesn't allocate.
+        let mut ucs = std::mem::replace(&mut self.ucs, UniformCostSearch::new(Board::new(0, 0)));
+        let net_attributes = self.net_attributes_vec.get(connection_idx).copied().unwrap_or_default();
+        self.is_next_to_pair_vec = self.gen_is_next_to_pair_vec(board, layout, connection_idx);
+        let route_step_vec = ucs.find_lowest_cost_route(board, layout, nets, self, start_end_via, &net_attributes);
+        self.is_next_to_pair_vec.clear();
+        self.ucs = ucs;
         if route_step_vec.is_empty() {
             return false;
         }
+        let cost_breakdown = layout.cost_breakdown.sub(&cost_breakdown_before);
         // if layout.circuit.has_parser_error() || route_step_vec.is_empty() {
         //     return false;
         // }
-        self.block_route(board, route_step_vec.clone());
-        nets.connect_route(board, layout, &route_step_vec);
-        let route_section_vec = self.condense_route(route_step_vec);
-        self.add_wire_jumps(board, route_section_vec.clone());
-        layout.route_vec.push(route_section_vec);
+        self.add_route_to_wire_layer(board, &route_step_vec);
+        let net_group_id = nets.connect_route(board, layout, &route_step_vec);
+        layout.route_vec.push(self.condense_route(route_step_vec.clone()));
+        layout.route_cost_vec.push(cost_breakdown);
+        layout.route_connection_idx_vec.push(connection_idx);
+        self.routed_vec.push(RoutedConnection {
+            connection_idx,
+            route_step_vec,
+            cost_breakdown,
+            net_group_id,
+        });
         true
     }
 
+    // Block the wire layer along the route and register its wires for wire jumps.
+    fn add_route_to_wire_layer(&mut self, board: Board, route_step_vec: &RouteStepVec) {
+        self.block_route(board, route_step_vec.clone());
+        let route_section_vec = self.condense_route(route_step_vec.clone());
+        self.add_wire_jumps(board, route_section_vec);
+    }
+
+    //
+    // Rip-up and reroute
+    //
+
+    // Once a connection has failed in the first pass, it would stay failed for this
+    // ordering. Instead, for each failed connection, we rip up the routes that pass
+    // through the area between its pins, route the failed connection, and then try
+    // to reroute the ripped up routes. The new layout is kept if it has more
+    // completed routes than the old one. If not, the old one is restored.
+    //
+    // Returns true if any failed connection was completed, in which case another pass
+    // may complete more.
+    fn rip_up_and_reroute(
+        &mut self,
+        board: Board,
+        layout: &mut Layout,
+        nets: &mut Nets,
+        connection_idx_vec: &[usize],
+    ) -> bool {
+        let connection_via_vec = layout.circuit.gen_connection_via_vec();
+        // Only the connections that were attempted in the first pass, which may have
+        // been limited to the first part of the ordering.
+        let n_attempted_routes = layout.n_completed_routes + layout.n_failed_routes;
+        let failed_connection_idx_vec: Vec<usize> = connection_idx_vec
+            .iter()
+            .take(n_attempted_routes)
+            .copied()
+            .filter(|&i| !layout.route_status_vec[i])
+            .collect();
+        let mut is_improved = false;
+        for failed_connection_idx in failed_connection_idx_vec {
+            let start_end_via = connection_via_vec[failed_connection_idx];
+            let ripped_vec = self.find_blocking_routes(start_end_via);
+            if ripped_vec.is_empty() {
+                continue;
+            }
+
+            let saved_routed_vec = self.routed_vec.clone();
+            let saved_route_status_vec = layout.route_status_vec.clone();
+            let saved_nets = nets.clone();
+            let n_completed_before = self.routed_vec.len();
+
+            for r in self.routed_vec.iter().filter(|r| ripped_vec.contains(&r.connection_idx)) {
+                nets.disconnect(r.net_group_id);
+            }
+            self.routed_vec.retain(|r| !ripped_vec.contains(&r.connection_idx));
+            self.rebuild(board, layout);
+            for connection_idx in std::iter::once(failed_connection_idx).chain(ripped_vec.iter().copied()) {
+                let is_routed = self.find_route(board, layout, nets, connection_idx, connection_via_vec[connection_idx]);
+                layout.route_status_vec[connection_idx] = is_routed;
+            }
+
+            if self.routed_vec.len() > n_completed_before {
+                is_improved = true;
+            } else {
+                self.routed_vec = saved_routed_vec;
+                layout.route_status_vec = saved_route_status_vec;
+                *nets = saved_nets;
+            }
+            self.rebuild(board, layout);
+        }
+        is_improved
+    }
+
+    // Find the routes that have any part within the bounding box of the connection.
+    // Routes in priority nets are always kept.
+    fn find_blocking_routes(&self, start_end_via: StartEndVia) -> Vec<usize> {
+        let x1 = start_end_via.start.x.min(start_end_via.end.x);
+        let x2 = start_end_via.start.x.max(start_end_via.end.x);
+        let y1 = start_end_via.start.y.min(start_end_via.end.y);
+        let y2 = start_end_via.start.y.max(start_end_via.end.y);
+        self.routed_vec
+            .iter()
+            .filter(|r| !self.net_attributes_vec[r.connection_idx].is_priority)
+            .filter(|r| {
+                r.route_step_vec
+                    .iter()
+                    .any(|s| s.via.x >= x1 && s.via.x <= x2 && s.via.y >= y1 && s.via.y <= y2)
+            })
+            .map(|r| r.connection_idx)
+            .collect()
+    }
+
+    // Recreate the wire layer blocking and wire jumps from the routes in routed_vec,
+    // and update the routes and route counts in the layout to match. The nets are
+    // updated separately, as routes are added and removed.
+    fn rebuild(&mut self, board: Board, layout: &mut Layout) {
+        let n_attempted_routes = layout.n_completed_routes + layout.n_failed_routes;
+        self.via_trace_vec = vec![WireLayerVia::new(); board.size()];
+        self.block_component_footprints(board, layout);
+        let routed_vec = std::mem::take(&mut self.routed_vec);
+        for r in &routed_vec {
+            self.add_route_to_wire_layer(board, &r.route_step_vec);
+        }
+        layout.route_vec = routed_vec.iter().map(|r| self.condense_route(r.route_step_vec.clone())).collect();
+        layout.route_cost_vec = routed_vec.iter().map(|r| r.cost_breakdown).collect();
+        layout.route_connection_idx_vec = routed_vec.iter().map(|r| r.connection_idx).collect();
+        layout.cost_breakdown = CostBreakdown::new();
+        for r in &routed_vec {
+            layout.cost_breakdown.add(&r.cost_breakdown);
+        }
+        layout.cost = layout.cost_breakdown.cost;
+        layout.n_completed_routes = routed_vec.len();
+        layout.n_failed_routes = n_attempted_routes - routed_vec.len();
+        self.routed_vec = routed_vec;
+    }
+
     // - Route always starts and ends on wire layer.
     // - Through to wire always starts a wire section.
     // - Through to strip always ends a wire section.
@@ -187,31 +368,26 @@ impl Router {
         route_section_vec
     }
 
-    // Transitions
-    // Cuts at:
-    // - used <-> other used
-    // - used <-> other pin
-    // Cuts NOT at:
-    // - unused <-> used
-    // - unused <-> pin
-    // - used <-> same pin
+    // Find the strip cuts required to keep the nets separated.
+    //
+    // Each strip is handled on its own. Going down the strip, a via is occupied if it
+    // is in a net or is an active component pin, and is otherwise free. Occupied vias
+    // are labeled with their net, and pins that are not in any net get a label of
+    // their own. See calc_strip_cuts() for how the cuts are placed.
     fn find_strip_cuts(&self, board: Board, layout: &mut Layout, nets: &mut Nets) -> Vec<Via> {
         let mut v = Vec::new();
-        for x in 0..self.board.w {
-            let mut is_used = false;
-            for y in 1..board.h {
-                let prev_via = Via::new(x, y - 1);
-                let cur_via = Via::new(x, y);
-                let is_connected = nets.is_connected(board, layout, cur_via, prev_via);
-                let is_in_other_net = nets.has_connection(board, layout, cur_via) && !is_connected;
-                let is_other_pin = self.is_any_pin(cur_via) && !is_connected;
-                if is_in_other_net || is_other_pin {
-                    if is_used {
-                        v.push(cur_via);
+        for x in 0..board.w {
+            let label_vec: Vec<Option<usize>> = (0..board.h)
+                .map(|y| {
+                    let via = Via::new(x, y);
+                    match nets.get_net_id(board, via) {
+                        Some(net_id) => Some(net_id),
+                        None if self.is_any_pin(via) => Some(board.size() + board.idx(via)),
+                        None => None,
                     }
-                    is_used = true;
-                }
-            }
+                })
+                .collect();
+            v.extend(calc_strip_cuts(&label_vec).into_iter().map(|y| Via::new(x, y)));
         }
         v
     }
@@ -257,6 +433,47 @@ impl Router {
         self.all_pin_set.contains(&via)
     }
 
+    pub fn get_cost_factors(&self, board: Board, via: Via) -> CostFactors {
+        let idx = board.idx(via);
+        let mut cost_factors = match self.cost_factors_vec.get(idx) {
+            Some(cost_factors) => *cost_factors,
+            None => CostFactors::new(),
+        };
+        if self.is_next_to_pair_vec.get(idx) == Some(&false) {
+            cost_factors.merge(&CostFactors {
+                strip_factor: PAIR_DETOUR_COST_FACTOR,
+                wire_factor: PAIR_DETOUR_COST_FACTOR,
+                via_factor: 1,
+            });
+        }
+        cost_factors
+    }
+
+    // True if any step may cost more than the costs in the settings.
+    pub fn has_cost_factors(&self) -> bool {
+        !self.cost_factors_vec.is_empty() || !self.is_next_to_pair_vec.is_empty()
+    }
+
+    // See is_next_to_pair_vec. Empty if the connection is not in a pair, or the other
+    // connection in the pair has not been routed.
+    fn gen_is_next_to_pair_vec(&self, board: Board, layout: &Layout, connection_idx: usize) -> Vec<bool> {
+        let Some(pair_connection_idx) = layout.circuit.find_pair_connection_idx(connection_idx) else {
+            return Vec::new();
+        };
+        let Some(pair_routed) = self.routed_vec.iter().find(|r| r.connection_idx == pair_connection_idx) else {
+            return Vec::new();
+        };
+        let mut is_next_to_pair_vec = vec![false; board.size()];
+        for step in &pair_routed.route_step_vec {
+            for y in step.via.y.saturating_sub(1)..=(step.via.y + 1).min(board.h - 1) {
+                for x in step.via.x.saturating_sub(1)..=(step.via.x + 1).min(board.w - 1) {
+                    is_next_to_pair_vec[board.idx(Via::new(x, y))] = true;
+                }
+            }
+        }
+        is_next_to_pair_vec
+    }
+
     pub fn wire_to_via_ref(&mut self, board: Board, via: Via) -> &mut ValidVia {
         let i = board.idx(via);
         &mut self.via_trace_vec[i].wire_to_via
@@ -328,3 +545,371 @@ impl Router {
         }
     }
 }
+
+// Calculate the minimal set of cuts for a single strip. label_vec has one entry per
+// via along the strip, holding the label of the occupied vias and None for the free
+// ones. A cut at y separates y - 1 from y. The returned cuts are sorted.
+//
+// Transitions between neighbouring occupied vias:
+// - Different labels (used <-> other used, used <-> other pin): one cut in the gap
+// between them.
+// - Same label (used <-> same net): no cut, the strip is part of the net.
+// Free vias (unused <-> used, unused <-> pin) never need a cut of their own, and
+// neither do the free vias before the first and after the last occupied via.
+//
+// This is minimal: The gaps between neighbouring occupied vias don't overlap, so no
+// cut can be in more than one gap, and a gap between different labels without a cut
+// would connect them. So at least one cut per such gap is needed, and one per gap
+// is sufficient.
+//
+// Any position in a gap is equally minimal and a cut never takes up a via, so all
+// the free vias are kept free wherever the cut goes. The cut is placed in the middle
+// of the gap, which splits the free vias evenly between the two sides and keeps the
+// stubs left on the nets short.
+fn calc_strip_cuts(label_vec: &[Option<usize>]) -> Vec<usize> {
+    let mut cut_vec = Vec::new();
+    let mut prev: Option<(usize, usize)> = None;
+    for (y, label) in label_vec.iter().enumerate() {
+        if let Some(label) = *label {
+            if let Some((prev_y, prev_label)) = prev {
+                if label != prev_label {
+                    cut_vec.push(prev_y + 1 + (y - prev_y - 1) / 2);
+                }
+            }
+            prev = Some((y, label));
+        }
+    }
+    cut_vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{Component, Connection, ConnectionGroup, ConnectionPoint, CostRegion, GroupKind};
+    use crate::via::OffsetVia;
+
+    const A: Option<usize> = Some(1);
+    const B: Option<usize> = Some(2);
+    const PIN: Option<usize> = Some(3);
+    const FREE: Option<usize> = None;
+
+    #[test]
+    fn empty_and_single_net_strips_have_no_cuts() {
+        assert_eq!(calc_strip_cuts(&[]), Vec::<usize>::new());
+        assert_eq!(calc_strip_cuts(&[FREE, FREE, FREE]), Vec::<usize>::new());
+        assert_eq!(calc_strip_cuts(&[FREE, A, FREE, A, A, FREE]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn used_next_to_other_used_is_cut_between_them() {
+        assert_eq!(calc_strip_cuts(&[A, B]), vec![1]);
+        assert_eq!(calc_strip_cuts(&[FREE, A, B, FREE]), vec![2]);
+    }
+
+    #[test]
+    fn used_next_to_other_pin_is_cut() {
+        assert_eq!(calc_strip_cuts(&[A, PIN]), vec![1]);
+        assert_eq!(calc_strip_cuts(&[PIN, FREE, A]), vec![1]);
+    }
+
+    #[test]
+    fn unused_next_to_used_or_pin_is_not_cut() {
+        assert_eq!(calc_strip_cuts(&[FREE, A, FREE]), Vec::<usize>::new());
+        assert_eq!(calc_strip_cuts(&[FREE, PIN, FREE]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn used_next_to_same_net_is_not_cut() {
+        assert_eq!(calc_strip_cuts(&[A, A, FREE, FREE, A]), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn one_cut_serves_a_gap_and_is_placed_in_the_middle() {
+        // Gap of 1: the cut at 1 puts y=1 with the higher side.
+        assert_eq!(calc_strip_cuts(&[A, FREE, B]), vec![1]);
+        // Gap of 4: two free vias on each side of the cut.
+        assert_eq!(calc_strip_cuts(&[A, FREE, FREE, FREE, FREE, B]), vec![3]);
+    }
+
+    #[test]
+    fn alternating_nets_need_one_cut_per_change() {
+        let label_vec = [A, FREE, B, B, FREE, A, PIN, FREE, FREE, A];
+        assert_eq!(calc_strip_cuts(&label_vec), vec![1, 4, 6, 8]);
+    }
+
+    // A board with single pin components at the given positions, named p0, p1, ...,
+    // and connections between them, given by position index.
+    fn pin_layout(pin_vec: &[(usize, usize)], connection_vec: &[(usize, usize)]) -> Layout {
+        let mut layout = Layout::new();
+        layout.board = Board::new(10, 10);
+        let circuit = &mut layout.circuit;
+        circuit
+            .package_to_pos_map
+            .insert("pin".to_string(), vec![OffsetVia::new(0, 0)]);
+        for (i, &(x, y)) in pin_vec.iter().enumerate() {
+            circuit
+                .component_name_to_component_map
+                .insert(format!("p{}", i), Component::new("pin".to_string(), Via::new(x, y)));
+        }
+        for &(start, end) in connection_vec {
+            circuit.connection_vec.push(Connection::new(
+                ConnectionPoint::new(format!("p{}", start), 0),
+                ConnectionPoint::new(format!("p{}", end), 0),
+            ));
+        }
+        layout
+    }
+
+    // The cost of the route between the pins, or None if it failed.
+    fn route_cost_with(start: (usize, usize), end: (usize, usize), net_attributes: NetAttributes) -> Option<usize> {
+        let mut layout = pin_layout(&[start, end], &[(0, 1)]);
+        layout
+            .circuit
+            .net_attributes_vec
+            .push((ConnectionPoint::new("p0".to_string(), 0), net_attributes));
+        let board = layout.board;
+        let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+        Router::new(board).route(board, &mut layout, &mut Nets::new(board), vec![0], &mut limit_routes);
+        (layout.n_completed_routes == 1).then_some(layout.cost)
+    }
+
+    fn is_routed_with(start: (usize, usize), end: (usize, usize), net_attributes: NetAttributes) -> bool {
+        route_cost_with(start, end, net_attributes).is_some()
+    }
+
+    #[test]
+    fn net_attributes_restrict_the_layers_and_the_strip_length() {
+        let none = NetAttributes::new();
+        let max_strip = |max_strip_len| NetAttributes {
+            max_strip_len: Some(max_strip_len),
+            ..none
+        };
+        let no_wire = NetAttributes {
+            is_wire_forbidden: true,
+            ..none
+        };
+        let no_strip = NetAttributes {
+            is_strip_forbidden: true,
+            ..none
+        };
+        // On the same strip.
+        assert!(is_routed_with((2, 1), (2, 6), none));
+        assert!(is_routed_with((2, 1), (2, 6), max_strip(5)));
+        assert!(!is_routed_with((2, 1), (2, 6), max_strip(4)));
+        assert!(is_routed_with((2, 1), (2, 6), no_wire));
+        assert!(is_routed_with((2, 1), (2, 6), no_strip));
+        // On the same row.
+        assert!(!is_routed_with((2, 3), (5, 3), no_wire));
+        assert!(is_routed_with((2, 3), (5, 3), no_strip));
+    }
+
+    // The cheapest route goes around the expensive wires in the rows next to the pins,
+    // which takes 4 strip steps. With at most 2, it must go through them.
+    #[test]
+    fn max_strip_len_finds_a_costlier_route_within_the_limit() {
+        let route_cost_with_max_strip = |max_strip_len| {
+            let mut layout = pin_layout(&[(2, 3), (5, 3)], &[(0, 1)]);
+            let cost_factors = CostFactors {
+                wire_factor: 20,
+                ..CostFactors::new()
+            };
+            layout.circuit.cost_region_vec = vec![CostRegion::new(
+                StartEndVia::new(Via::new(0, 2), Via::new(9, 4)),
+                cost_factors,
+            )];
+            let net_attributes = NetAttributes {
+                max_strip_len,
+                ..NetAttributes::new()
+            };
+            layout
+                .circuit
+                .net_attributes_vec
+                .push((ConnectionPoint::new("p0".to_string(), 0), net_attributes));
+            let board = layout.board;
+            let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+            Router::new(board).route(board, &mut layout, &mut Nets::new(board), vec![0], &mut limit_routes);
+            (layout.n_completed_routes == 1).then_some((layout.cost, layout.cost_breakdown.strip_len))
+        };
+        let (cost, strip_len) = route_cost_with_max_strip(None).unwrap();
+        assert_eq!(strip_len, 4);
+        let (limited_cost, limited_strip_len) = route_cost_with_max_strip(Some(2)).unwrap();
+        assert_eq!(limited_strip_len, 2);
+        assert!(limited_cost > cost);
+        assert_eq!(route_cost_with_max_strip(Some(1)), None);
+    }
+
+    #[test]
+    fn wires_cost_more_in_power_nets() {
+        let power = NetAttributes {
+            is_power: true,
+            ..NetAttributes::new()
+        };
+        let cost = route_cost_with((2, 3), (5, 3), NetAttributes::new()).unwrap();
+        let power_cost = route_cost_with((2, 3), (5, 3), power).unwrap();
+        assert!(power_cost > cost);
+        // A route along a strip costs the same.
+        assert_eq!(route_cost_with((2, 1), (2, 6), power), route_cost_with((2, 1), (2, 6), NetAttributes::new()));
+    }
+
+    #[test]
+    fn routes_avoid_cost_regions_when_they_can() {
+        let route_cost_in = |cost_region_vec: Vec<CostRegion>| {
+            let mut layout = pin_layout(&[(2, 3), (5, 3)], &[(0, 1)]);
+            layout.circuit.cost_region_vec = cost_region_vec;
+            let board = layout.board;
+            let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+            Router::new(board).route(board, &mut layout, &mut Nets::new(board), vec![0], &mut limit_routes);
+            assert_eq!(layout.n_completed_routes, 1);
+            layout.cost
+        };
+        let wire_region = |y1, y2| {
+            let cost_factors = CostFactors {
+                wire_factor: 5,
+                ..CostFactors::new()
+            };
+            CostRegion::new(StartEndVia::new(Via::new(0, y1), Via::new(9, y2)), cost_factors)
+        };
+        let cost = route_cost_in(Vec::new());
+        // The wire can go in the row above or below the pins.
+        assert_eq!(route_cost_in(vec![wire_region(0, 2)]), cost);
+        assert_eq!(route_cost_in(vec![wire_region(4, 9)]), cost);
+        assert!(route_cost_in(vec![wire_region(0, 2), wire_region(4, 9)]) > cost);
+    }
+
+    // Route two connections, from (x1,1) to (x1,y1) and from (x2,1) to (x2,y2), in the
+    // group, in that order.
+    fn route_group(kind: GroupKind, (x1, y1): (usize, usize), (x2, y2): (usize, usize)) -> Layout {
+        let mut layout = pin_layout(&[(x1, 1), (x1, y1), (x2, 1), (x2, y2)], &[(0, 1), (2, 3)]);
+        layout
+            .circuit
+            .connection_group_vec
+            .push(ConnectionGroup::new("g".to_string(), kind, vec![0, 1]));
+        let board = layout.board;
+        let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+        Router::new(board).route(board, &mut layout, &mut Nets::new(board), vec![0, 1], &mut limit_routes);
+        assert_eq!(layout.n_completed_routes, 2);
+        layout
+    }
+
+    #[test]
+    fn pair_routes_cost_more_away_from_each_other() {
+        let cost_without_pair = route_group(GroupKind::MatchedLength, (2, 8), (6, 8)).cost;
+        assert!(route_group(GroupKind::Pair, (2, 8), (6, 8)).cost > cost_without_pair);
+        let cost_without_pair = route_group(GroupKind::MatchedLength, (2, 8), (3, 8)).cost;
+        assert_eq!(route_group(GroupKind::Pair, (2, 8), (3, 8)).cost, cost_without_pair);
+    }
+
+    #[test]
+    fn length_mismatch_is_added_to_the_cost_and_reported() {
+        let layout = route_group(GroupKind::MatchedLength, (2, 8), (6, 5));
+        assert_eq!(layout.cost_breakdown.len_mismatch, 3);
+        let route_cost: usize = layout.route_cost_vec.iter().map(|c| c.cost).sum();
+        assert_eq!(layout.cost, route_cost + 3 * layout.settings.length_mismatch_cost);
+        assert_eq!(layout.group_info_vec(), vec!["Group g (match): lengths 7, 4, difference 3"]);
+    }
+
+    // The cost that is shown for power nets, cost regions and pairs.
+    fn extra_cost(layout: &Layout) -> usize {
+        let info_vec = layout.cost_breakdown.info_vec(&layout.settings);
+        let line = info_vec.iter().find(|line| line.starts_with("Extra cost")).unwrap();
+        line.rsplit(' ').next().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn cuts_are_added_to_the_cost_and_reported() {
+        // Two nets on the same strip, which must be cut apart.
+        let mut layout = pin_layout(&[(2, 1), (2, 3), (2, 5), (2, 7)], &[(0, 1), (2, 3)]);
+        let board = layout.board;
+        let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+        Router::new(board).route(board, &mut layout, &mut Nets::new(board), vec![0, 1], &mut limit_routes);
+        assert_eq!(layout.n_completed_routes, 2);
+        let n_cuts = layout.strip_cut_vec.len();
+        assert!(n_cuts > 0);
+        assert_eq!(layout.cost_breakdown.n_cuts, n_cuts);
+        let route_cost: usize = layout.route_cost_vec.iter().map(|c| c.cost).sum();
+        assert_eq!(layout.cost, route_cost + n_cuts * layout.settings.cut_cost);
+        assert_eq!(layout.cost_breakdown.cost, layout.cost);
+        assert_eq!(extra_cost(&layout), 0);
+    }
+
+    #[test]
+    fn extra_cost_for_pairs_is_reported() {
+        let layout = route_group(GroupKind::Pair, (2, 8), (6, 8));
+        let cost_without_pair = route_group(GroupKind::MatchedLength, (2, 8), (6, 8)).cost;
+        assert_eq!(extra_cost(&layout), layout.cost - cost_without_pair);
+    }
+
+    fn sorted_net_vec(nets: &Nets) -> Vec<Vec<Via>> {
+        let mut net_vec = nets.get_net_vec();
+        for member_vec in &mut net_vec {
+            member_vec.sort_by_key(|via| (via.y, via.x));
+        }
+        net_vec.sort_by_key(|member_vec| (member_vec[0].y, member_vec[0].x));
+        net_vec
+    }
+
+    // On the small board, the route for the first connection takes the way that the
+    // second one needs. After the first route is ripped up, both can be routed.
+    #[test]
+    fn rip_up_completes_a_connection_blocked_by_an_earlier_route() {
+        let route_with_passes = |rip_up_passes| {
+            let mut layout = pin_layout(&[(3, 3), (1, 2), (2, 2), (1, 0)], &[(0, 1), (2, 3)]);
+            layout.board = Board::new(5, 5);
+            layout.settings.rip_up_passes = rip_up_passes;
+            let board = layout.board;
+            let mut router = Router::new(board);
+            let mut nets = Nets::new(board);
+            let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+            router.route(board, &mut layout, &mut nets, vec![0, 1], &mut limit_routes);
+            (layout, router, nets)
+        };
+        let (layout, _, _) = route_with_passes(0);
+        assert_eq!(layout.route_status_vec, vec![true, false]);
+        assert_eq!((layout.n_completed_routes, layout.n_failed_routes), (1, 1));
+
+        let (mut layout, router, nets) = route_with_passes(1);
+        assert_eq!(layout.route_status_vec, vec![true, true]);
+        assert_eq!((layout.n_completed_routes, layout.n_failed_routes), (2, 0));
+        assert_eq!(router.routed_vec.len(), 2);
+        assert_eq!(layout.route_vec.len(), 2);
+        let mut connection_idx_vec = layout.route_connection_idx_vec.clone();
+        connection_idx_vec.sort();
+        assert_eq!(connection_idx_vec, vec![0, 1]);
+        for (route_idx, r) in router.routed_vec.iter().enumerate() {
+            assert_eq!(layout.route_connection_idx_vec[route_idx], r.connection_idx);
+            assert_eq!(layout.route_cost_vec[route_idx], r.cost_breakdown);
+            assert_eq!(layout.route_vec[route_idx], router.condense_route(r.route_step_vec.clone()));
+        }
+        let route_cost: usize = layout.route_cost_vec.iter().map(|c| c.cost).sum();
+        assert_eq!(layout.cost, route_cost + layout.strip_cut_vec.len() * layout.settings.cut_cost);
+
+        // The nets are the same as when built from scratch from the routes that are left.
+        let board = layout.board;
+        let mut rebuilt_nets = Nets::new(board);
+        for c in layout.circuit.gen_connection_via_vec() {
+            rebuilt_nets.connect(board, &mut layout, c.start, c.end);
+        }
+        for r in &router.routed_vec {
+            rebuilt_nets.connect_route(board, &mut layout, &r.route_step_vec);
+        }
+        assert_eq!(sorted_net_vec(&nets), sorted_net_vec(&rebuilt_nets));
+    }
+
+    #[test]
+    fn net_attributes_apply_to_the_whole_net_and_priority_nets_go_first() {
+        let mut layout = pin_layout(&[(1, 1), (3, 1), (5, 1), (7, 1), (9, 1)], &[(0, 1), (3, 4), (1, 2)]);
+        let priority = NetAttributes {
+            is_priority: true,
+            ..NetAttributes::new()
+        };
+        layout
+            .circuit
+            .net_attributes_vec
+            .push((ConnectionPoint::new("p2".to_string(), 0), priority));
+        let net_attributes_vec = layout.circuit.gen_connection_net_attributes_vec();
+        assert_eq!(net_attributes_vec, vec![priority, NetAttributes::new(), priority]);
+        let mut router = Router::new(layout.board);
+        router.net_attributes_vec = net_attributes_vec;
+        assert_eq!(router.move_priority_connections_first(vec![1, 2, 0]), vec![2, 0, 1]);
+    }
+}
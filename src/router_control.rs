@@ -1,7 +1,12 @@
-use crate::ga_interface::GeneticAlgorithm;
+use crate::checkpoint::{self, LoadedCheckpoint};
+use crate::ga_stats::GenerationStats;
 use crate::layout::Layout;
+use crate::ordering_search::{create_ordering_search, OrderingSearch};
+use crate::pareto::{LayoutObjectives, ParetoArchive, ParetoLayout};
+use crate::settings::{GaSettings, OrderingSearchStrategy};
 // use crate::thread_stop::ThreadStop;
-use std::sync::atomic::AtomicUsize;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 // Condvar - block and wait:  https://doc.rust-lang.org/std/sync/struct.Condvar.html
 use crate::router_thread::RouterThread;
@@ -15,18 +20,32 @@ pub(crate) struct RouterControl {
     // router_stop_signal: Arc<Mutex<ThreadStop>>,
     router_stop_signal: Arc<(Mutex<bool>, Condvar)>,
 
-    // This is where the single instance of the GA is stored. It is used by all
-    // router threads.
-    genetic_algorithm: Arc<Mutex<GeneticAlgorithm>>,
+    // This is where the single instance of the ordering search is stored. It is used by
+    // all router threads. Threads that are waiting for an ordering wait on the Condvar.
+    ordering_search: Arc<(Mutex<Box<dyn OrderingSearch>>, Condvar)>,
+    // Incremented each time the ordering search is replaced, so that the router threads
+    // know not to release orderings from the old search to the new one.
+    ordering_search_id: Arc<AtomicUsize>,
+
+    // The layouts that are best in some trade-off between the objectives. See
+    // ParetoArchive.
+    pareto_archive: Arc<Mutex<ParetoArchive>>,
 
     router_thread_vec: Vec<RouterThread>,
 
     counter: Arc<AtomicUsize>,
 
     limit_routes: Arc<AtomicUsize>,
+
+    // Use a single router thread, so that the orderings are checked in the same
+    // sequence on every run with the same seed.
+    is_deterministic: bool,
+
+    seed: u64,
 }
 
 impl RouterControl {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         // router_stop_signal: Arc<Mutex<ThreadStop>>,
         input_layout: Arc<Mutex<Layout>>,
@@ -34,44 +53,42 @@ impl RouterControl {
         best_layout: Arc<Mutex<Layout>>,
         counter: Arc<AtomicUsize>,
         limit_routes: Arc<AtomicUsize>,
+        ordering_search_strategy: OrderingSearchStrategy,
+        ga_settings: &GaSettings,
+        seed: u64,
+        is_deterministic: bool,
     ) -> Self {
-        Self {
+        let mut router_control = Self {
             input_layout,
             current_layout,
             best_layout,
             router_stop_signal: Arc::new((Mutex::new(false), Condvar::new())),
-            genetic_algorithm: Arc::new(Mutex::new(GeneticAlgorithm::new(1000, 0.7, 0.01))),
+            ordering_search: Arc::new((
+                Mutex::new(create_ordering_search(ordering_search_strategy, ga_settings, seed)),
+                Condvar::new(),
+            )),
+            ordering_search_id: Arc::new(AtomicUsize::new(0)),
+            pareto_archive: Arc::new(Mutex::new(ParetoArchive::new())),
             router_thread_vec: Vec::new(),
             counter,
             limit_routes,
-        }
+            is_deterministic,
+            seed,
+        };
+        // Reset here instead of when starting, so that a checkpoint can be resumed
+        // before the search starts.
+        router_control.reset_ordering_search();
+        router_control
     }
 
     pub fn start(&mut self) {
-        self.genetic_algorithm
-            .lock()
-            .unwrap()
-            .reset(self.input_layout.lock().unwrap().circuit.connection_vec.len());
-
-        // If DEBUG build, we start only one router thread.
+        // If DEBUG build, or in deterministic mode, we start only one router thread.
         // If RELEASE build, we start as many router threads as there are CPUs.
         //
-        let n_threads = if cfg!(debug_assertions) { 1 } else { num_cpus::get() };
+        let n_threads = if cfg!(debug_assertions) || self.is_deterministic { 1 } else { num_cpus::get() };
         for i in 0..n_threads {
-            println!("Starting router thread i: {}", i);
-            let router_thread = Arc::new(Mutex::new(RouterThread::new(
-                // Arc::clone() is a method on the Arc type that returns a new Arc
-                // that points to the same data as the original Arc. It does not clone
-                // the underlying data; it only increments the reference count.
-                Arc::clone(&self.input_layout),
-                Arc::clone(&self.current_layout),
-                Arc::clone(&self.best_layout),
-                Arc::clone(&self.router_stop_signal),
-                Arc::clone(&self.genetic_algorithm),
-                Arc::clone(&self.counter),
-                Arc::clone(&self.limit_routes),
-                i,
-            )));
+            eprintln!("Starting router thread i: {}", i);
+            let router_thread = Arc::new(Mutex::new(self.create_router_thread(i)));
             // let router_thread_clone = Arc::clone(&router_thread);
             thread::spawn(move || {
                 let mut router_thread = router_thread.lock().unwrap();
@@ -83,6 +100,142 @@ impl RouterControl {
         }
     }
 
+    // Check the given number of orderings in the calling thread, without starting any
+    // router threads. The result is in the best layout. This is always deterministic.
+    pub fn check_orderings(&mut self, n_checks: usize) {
+        let mut router_thread = self.create_router_thread(0);
+        for _ in 0..n_checks {
+            if !router_thread.check_ordering() {
+                break;
+            }
+        }
+    }
+
+    pub fn set_ga_settings(&mut self, ga_settings: &GaSettings) {
+        self.ordering_search.0.lock().unwrap().set_settings(ga_settings);
+    }
+
+    pub fn get_ordering_search_strategy(&self) -> OrderingSearchStrategy {
+        self.ordering_search.0.lock().unwrap().get_strategy()
+    }
+
+    // Switch to another ordering search while the search is running. The new search
+    // starts from scratch. The best layout is kept, and orderings that were handed out
+    // by the old search are routed, but their results only count for the best layout.
+    pub fn set_ordering_search_strategy(&mut self, strategy: OrderingSearchStrategy, ga_settings: &GaSettings) {
+        let mut ordering_search = self.ordering_search.0.lock().unwrap();
+        if ordering_search.get_strategy() == strategy {
+            return;
+        }
+        *ordering_search = create_ordering_search(strategy, ga_settings, self.seed);
+        ordering_search.reset(&self.input_layout.lock().unwrap().circuit);
+        self.ordering_search_id.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn clear_fitness_cache(&mut self) {
+        self.ordering_search.0.lock().unwrap().clear_fitness_cache();
+    }
+
+    pub fn get_n_fitness_cache_hits(&self) -> usize {
+        self.ordering_search.0.lock().unwrap().get_n_fitness_cache_hits()
+    }
+
+    pub fn get_n_generations(&self) -> usize {
+        self.ordering_search.0.lock().unwrap().get_n_generations()
+    }
+
+    pub fn get_stats_vec(&self) -> Vec<GenerationStats> {
+        self.ordering_search.0.lock().unwrap().get_stats_vec()
+    }
+
+    pub fn get_stats_csv(&self) -> String {
+        self.ordering_search.0.lock().unwrap().get_stats_csv()
+    }
+
+    pub fn get_pareto_objectives_vec(&self) -> Vec<(usize, LayoutObjectives)> {
+        self.pareto_archive.lock().unwrap().get_objectives_vec()
+    }
+
+    // None if the layout has been dropped from the archive.
+    pub fn get_pareto_layout(&self, id: usize) -> Option<Layout> {
+        self.pareto_archive.lock().unwrap().get_layout(id)
+    }
+
+    pub fn get_pareto_csv(&self) -> String {
+        self.pareto_archive.lock().unwrap().to_csv()
+    }
+
+    // Write the objectives of the layouts in the archive to front.csv in the
+    // directory, and each layout to pareto-<id>.layout. The directory is created if
+    // needed.
+    pub fn export_pareto_front(&self, dir_path: &Path) -> Result<(), String> {
+        std::fs::create_dir_all(dir_path).map_err(|e| format!("{}: {}", dir_path.display(), e))?;
+        let pareto_archive = self.pareto_archive.lock().unwrap();
+        let csv_path = dir_path.join("front.csv");
+        std::fs::write(&csv_path, pareto_archive.to_csv()).map_err(|e| format!("{}: {}", csv_path.display(), e))?;
+        for ParetoLayout { id, layout, .. } in pareto_archive.get_pareto_layout_vec() {
+            checkpoint::save_layout(&dir_path.join(format!("pareto-{}.layout", id)), layout)?;
+        }
+        Ok(())
+    }
+
+    // Can be called while the router threads are running.
+    pub fn save_checkpoint(&self, path: &Path) -> Result<(), String> {
+        let input_layout = self.input_layout.lock().unwrap().clone();
+        let best_layout = self.best_layout.lock().unwrap().clone();
+        let ordering_search = self.ordering_search.0.lock().unwrap();
+        checkpoint::save_checkpoint(path, &input_layout, ordering_search.as_ref(), &best_layout)
+    }
+
+    // Must be called before the search is started.
+    pub fn load_checkpoint(&mut self, path: &Path) -> Result<LoadedCheckpoint, String> {
+        let input_layout = self.input_layout.lock().unwrap().clone();
+        let mut best_layout = self.best_layout.lock().unwrap().clone();
+        let loaded_checkpoint = checkpoint::load_checkpoint(
+            path,
+            &input_layout,
+            self.ordering_search.0.lock().unwrap().as_mut(),
+            &mut best_layout,
+        )?;
+        *self.best_layout.lock().unwrap() = best_layout;
+        Ok(loaded_checkpoint)
+    }
+
+    // Switch to an edited version of the circuit while the search is running. The
+    // ordering search keeps going from what it has learned (the GA keeps its
+    // population). The search lock is held while the input layout is replaced, so that
+    // the router threads always get an ordering and an input layout that belong
+    // together. Results for orderings that were handed out before the switch are dropped
+    // by the router threads.
+    pub fn update_circuit(&mut self, layout: Layout) {
+        let mut ordering_search = self.ordering_search.0.lock().unwrap();
+        ordering_search.warm_start(&layout.circuit);
+        self.pareto_archive.lock().unwrap().clear();
+        *self.input_layout.lock().unwrap() = layout;
+    }
+
+    fn reset_ordering_search(&mut self) {
+        self.ordering_search.0.lock().unwrap().reset(&self.input_layout.lock().unwrap().circuit);
+    }
+
+    fn create_router_thread(&self, thread_idx: usize) -> RouterThread {
+        RouterThread::new(
+            // Arc::clone() is a method on the Arc type that returns a new Arc
+            // that points to the same data as the original Arc. It does not clone
+            // the underlying data; it only increments the reference count.
+            Arc::clone(&self.input_layout),
+            Arc::clone(&self.current_layout),
+            Arc::clone(&self.best_layout),
+            Arc::clone(&self.router_stop_signal),
+            Arc::clone(&self.ordering_search),
+            Arc::clone(&self.ordering_search_id),
+            Arc::clone(&self.pareto_archive),
+            Arc::clone(&self.counter),
+            Arc::clone(&self.limit_routes),
+            thread_idx,
+        )
+    }
+
     // pub fn start(&self, shared_self: Arc<Mutex<Self>>) {
     //     let thread_self = Arc::clone(&shared_self);
     //     thread::spawn(move || {
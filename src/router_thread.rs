@@ -1,31 +1,38 @@
-use crate::ga_interface::GeneticAlgorithm;
 use crate::layout::Layout;
+use crate::ordering_search::OrderingSearch;
+use crate::pareto::ParetoArchive;
 // use crate::thread_stop::ThreadStop;
 use crate::via::Via;
 use crate::{nets, router, settings};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::Duration;
 
 pub(crate) struct RouterThread {
     input_layout: Arc<Mutex<Layout>>,
     current_layout: Arc<Mutex<Layout>>,
     best_layout: Arc<Mutex<Layout>>,
     router_stop_signal: Arc<(Mutex<bool>, Condvar)>,
-    genetic_algorithm: Arc<Mutex<GeneticAlgorithm>>,
+    ordering_search: Arc<(Mutex<Box<dyn OrderingSearch>>, Condvar)>,
+    ordering_search_id: Arc<AtomicUsize>,
+    pareto_archive: Arc<Mutex<ParetoArchive>>,
     counter: Arc<AtomicUsize>,
     limit_routes: Arc<AtomicUsize>,
     thread_idx: usize,
 }
 
 impl RouterThread {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         // router_stop_signal: Arc<Mutex<ThreadStop>>,
         input_layout: Arc<Mutex<Layout>>,
         current_layout: Arc<Mutex<Layout>>,
         best_layout: Arc<Mutex<Layout>>,
         router_stop_signal: Arc<(Mutex<bool>, Condvar)>,
-        genetic_algorithm: Arc<Mutex<GeneticAlgorithm>>,
+        ordering_search: Arc<(Mutex<Box<dyn OrderingSearch>>, Condvar)>,
+        ordering_search_id: Arc<AtomicUsize>,
+        pareto_archive: Arc<Mutex<ParetoArchive>>,
         counter: Arc<AtomicUsize>,
         limit_routes: Arc<AtomicUsize>,
         thread_idx: usize,
@@ -35,7 +42,9 @@ impl RouterThread {
             current_layout,
             best_layout,
             router_stop_signal,
-            genetic_algorithm,
+            ordering_search,
+            ordering_search_id,
+            pareto_archive,
             counter,
             limit_routes,
             thread_idx,
@@ -49,10 +58,8 @@ impl RouterThread {
 
         // println!("run() thread_idx={}", self.thread_idx);
 
-        // println!("waittttt thread_idx={}", self.thread_idx);
-        //
-        // while !*stop {
-        let (lock, cvar) = &*self.router_stop_signal;
+        let router_stop_signal = Arc::clone(&self.router_stop_signal);
+        let (lock, cvar) = &*router_stop_signal;
         loop {
             // We check the stop signal in a separate scope to avoid holding the lock
             // permanently in the loop.
@@ -63,96 +70,101 @@ impl RouterThread {
                     break;
                 }
             }
-            // println!("while thread_idx={}", self.thread_idx);
-            // while !self.router_stop_signal.lock().unwrap().is_stopped() {
-            let mut ordering_idx;
-            loop {
-                ordering_idx = self.genetic_algorithm.lock().unwrap().reserve_ordering();
-                if ordering_idx != usize::MAX {
-                    break;
-                } else {
-                    // println!("thread_idx={} waiting", self.thread_idx);
-                    thread::sleep(std::time::Duration::from_millis(10));
-                }
+            // println!("thread_idx={} checking", self.thread_idx);
+            self.check_ordering();
+        }
+    }
+
+    // Get the next ordering from the ordering search, route it, and pass the result
+    // back to the search.
+    // The layout becomes the new best layout if it's better than the current one.
+    // Returns false if the search had no ordering available within the wait timeout.
+    //
+    // This is all the work done by a router thread, so calling this repeatedly from a
+    // single thread gives the same sequence of layouts on every run with the same
+    // seed.
+    pub fn check_ordering(&mut self) -> bool {
+        let (ordering_search_id, ordering_idx, ordering, mut thread_layout) = match self.wait_for_ordering() {
+            Some(reserved) => reserved,
+            None => return false,
+        };
+        // println!("ordering={:?}", ordering);
+
+        let mut router = router::Router::new(thread_layout.board);
+        let mut nets = nets::Nets::new(thread_layout.board);
+
+        let connection_idx_vec = ordering;
+
+        router.route(
+            thread_layout.board,
+            &mut thread_layout,
+            &mut nets,
+            connection_idx_vec,
+            &mut self.limit_routes,
+        );
+
+        nets.copy_to_layout(&mut thread_layout);
+
+        // If the circuit was changed while routing, the ordering belongs to the GA
+        // population from before the change, and the layout is for the old circuit,
+        // so the result is dropped. If the ordering search was replaced, the ordering
+        // belongs to the old search, but the layout can still be the new best layout.
+        // The Pareto archive is updated under the search lock, so that it's never
+        // cleared for a new circuit between the check and the insert.
+        {
+            let (lock, cvar) = &*self.ordering_search;
+            let mut ordering_search = lock.lock().unwrap();
+            if !thread_layout.is_based_on(&self.input_layout.lock().unwrap()) {
+                return true;
+            }
+            if ordering_search_id == self.ordering_search_id.load(Ordering::SeqCst) {
+                ordering_search.release_ordering(ordering_idx, thread_layout.n_completed_routes, thread_layout.cost);
             }
-            // let ordering = self.genetic_algorithm.lock().unwrap().get_ordering(ordering_idx);
-            // println!("ordering={:?}", ordering);
-
-
-            let ordering = (0..self.input_layout.lock().unwrap().circuit.connection_vec.len()).collect::<Vec<usize>>();
-            // println!("ordering={:?}", ordering);
-
-            // {
-            //     let mut x = self.input_layout.lock().unwrap();
-            //     println!("x.layout_info_vec.len() = {}", x.layout_info_vec.len());
-            //     println!("x.route_status_vec.len() = {}", x.route_status_vec.len());
-            //     println!("x.set_idx_vec.len() = {}", x.set_idx_vec.len());
-            //     println!("x.strip_cut_vec.len() = {}", x.strip_cut_vec.len());
-            //     println!("x.via_set_vec.len() = {}", x.via_set_vec.len());
-            //     println!("x.route_vec.len() = {}", x.route_vec.len());
-            //     println!("x.n_completed_routes = {}", x.n_completed_routes);
-            //     println!("x.n_failed_routes = {}", x.n_failed_routes);
-            //     println!("x.cost = {}", x.cost);
-            // }
-
-            let mut thread_layout = self.input_layout.lock().unwrap().thread_safe_copy();
-
-            let mut router = router::Router::new(thread_layout.board);
-            let mut nets = nets::Nets::new(thread_layout.board);
-            let settings = settings::Settings::new();
-
-            let connection_idx_vec = ordering;
-
-
-            router.route(
-                thread_layout.board,
-                &mut thread_layout,
-                &mut nets,
-                connection_idx_vec,
-                &mut self.limit_routes,
-            );
-
-            // thread_layout.via_set_vec = nets.via_set_vec;
-            // thread_layout.set_idx_vec = nets.set_idx_vec;
-
-            self.genetic_algorithm.lock().unwrap().release_ordering(
-                ordering_idx,
-                thread_layout.n_completed_routes,
-                thread_layout.cost,
-            );
-
-            /////////////////////////
-
-            // let mut input_layout_guard = self.input_layout.lock().unwrap();
-
-            // {
-                let mut best_layout_guard = self.best_layout.lock().unwrap();
-            //
-            //     let has_more_completed_routes = thread_layout.n_completed_routes > best_layout_guard.n_completed_routes;
-            //     let has_equal_routes_and_better_score = thread_layout.n_completed_routes == best_layout_guard.n_completed_routes && thread_layout.cost < best_layout_guard.cost;
-            //
-            //     // Assuming `is_based_on` is a method in the `Layout` struct that takes a reference to another `Layout`
-            //     // let is_based_on_other_layout = !best_layout_guard.is_based_on(&thread_layout);
-            //     let is_based_on_other_layout = false;
-            //
-            //     if has_more_completed_routes || has_equal_routes_and_better_score || is_based_on_other_layout {
-            //         *best_layout_guard = thread_layout.clone();
-            //     }
-            // }
-
-            println!("completed_routes={}", thread_layout.n_completed_routes);
-
-            // let mut best_layout_guard = self.best_layout.lock().unwrap();
-            *best_layout_guard = thread_layout.clone();
-
-
-            ///////////////////////
-
-            // println!("thread_idx={}", self.thread_idx);
-            self.counter.fetch_add(1, Ordering::SeqCst);
-            // println!("x={}", x);
-            // thread::sleep(std::time::Duration::from_millis(1000));
+            self.pareto_archive.lock().unwrap().insert(&thread_layout);
+            drop(ordering_search);
+            cvar.notify_all();
+        }
+
+        {
+            let mut best_layout_guard = self.best_layout.lock().unwrap();
+
+            let has_more_completed_routes = thread_layout.n_completed_routes > best_layout_guard.n_completed_routes;
+            let has_equal_routes_and_better_score = thread_layout.n_completed_routes
+                == best_layout_guard.n_completed_routes
+                && thread_layout.cost < best_layout_guard.cost;
+            let is_based_on_other_layout = !best_layout_guard.is_based_on(&thread_layout);
+
+            if has_more_completed_routes || has_equal_routes_and_better_score || is_based_on_other_layout {
+                *best_layout_guard = thread_layout;
+            }
+        }
+
+        // println!("thread_idx={}", self.thread_idx);
+        self.counter.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+
+    // Reserve an ordering from the ordering search, and copy the input layout to route
+    // it on. If no ordering is available, wait until another thread releases one. Gives
+    // up after a timeout, so that the caller can check the stop signal. Also returns the
+    // ID of the search that the ordering came from.
+    fn wait_for_ordering(&self) -> Option<(usize, usize, Vec<usize>, Layout)> {
+        let (lock, cvar) = &*self.ordering_search;
+        let mut ordering_search = lock.lock().unwrap();
+        let mut ordering_idx = ordering_search.reserve_ordering();
+        if ordering_idx == usize::MAX {
+            ordering_search = cvar
+                .wait_timeout(ordering_search, Duration::from_millis(100))
+                .unwrap()
+                .0;
+            ordering_idx = ordering_search.reserve_ordering();
+        }
+        if ordering_idx == usize::MAX {
+            return None;
         }
+        let ordering_search_id = self.ordering_search_id.load(Ordering::SeqCst);
+        let thread_layout = self.input_layout.lock().unwrap().thread_safe_copy();
+        Some((ordering_search_id, ordering_idx, ordering_search.get_ordering(ordering_idx), thread_layout))
     }
 }
 
@@ -10,6 +10,26 @@ const DEFAULT_WIRE_COST: usize = 10;
 const DEFAULT_STRIP_COST: usize = 10;
 const DEFAULT_VIA_COST: usize = 1;
 const DEFAULT_CUT_COST: usize = 100;
+const DEFAULT_AREA_COST: usize = 0;
+const DEFAULT_LENGTH_MISMATCH_COST: usize = 10;
+const DEFAULT_RIP_UP_PASSES: usize = 1;
+
+const DEFAULT_N_ORGANISMS_IN_POPULATION: usize = 1000;
+const DEFAULT_CROSSOVER_RATE: f64 = 0.7;
+const DEFAULT_MUTATION_RATE: f64 = 0.01;
+const DEFAULT_N_TOURNAMENT_CANDIDATES: usize = 2;
+const DEFAULT_N_ELITES: usize = 2;
+const DEFAULT_FITNESS_CACHE_SIZE: usize = 10000;
+
+// The search used for finding the lowest cost route for a single connection. Both
+// return routes with the same cost, so the choice only affects routing speed (and
+// which of several equal cost routes is picked). UCS is kept so that the two can be
+// benchmarked against each other.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchAlgorithm {
+    UniformCost,
+    AStar,
+}
 
 #[derive(Clone)]
 pub struct Settings {
@@ -17,6 +37,16 @@ pub struct Settings {
     pub strip_cost: usize,
     pub via_cost: usize,
     pub cut_cost: usize,
+    // Cost per via in the rectangle used by the layout, to prefer compact layouts. See
+    // Layout::calc_used_area(). 0 disables it.
+    pub area_cost: usize,
+    // Cost per step of difference between the longest and the shortest route in each
+    // connection group. See ConnectionGroup.
+    pub length_mismatch_cost: usize,
+    pub search_algorithm: SearchAlgorithm,
+    // Max number of rip-up and reroute passes over the failed connections after the
+    // first routing pass. 0 disables rip-up.
+    pub rip_up_passes: usize,
 }
 
 impl Settings {
@@ -26,6 +56,147 @@ impl Settings {
             strip_cost: DEFAULT_STRIP_COST,
             via_cost: DEFAULT_VIA_COST,
             cut_cost: DEFAULT_CUT_COST,
+            area_cost: DEFAULT_AREA_COST,
+            length_mismatch_cost: DEFAULT_LENGTH_MISMATCH_COST,
+            search_algorithm: SearchAlgorithm::AStar,
+            rip_up_passes: DEFAULT_RIP_UP_PASSES,
+        }
+    }
+}
+
+// How the connection orderings are searched for. See OrderingSearch.
+//
+// GeneticAlgorithm: The Topo-GA. See GeneticAlgorithm and GaSettings.
+// RandomRestart: Hill climbing by swapping and moving connections in the ordering,
+// starting over from a random ordering when stuck. See LocalSearch.
+// SimulatedAnnealing: Like RandomRestart, but also accepts worse orderings, less often
+// as the search cools down. See LocalSearch.
+// HardestFirst: Route the longest and most obstructed connections first, with some
+// noise added after the first ordering. See HardestFirst.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OrderingSearchStrategy {
+    GeneticAlgorithm,
+    RandomRestart,
+    SimulatedAnnealing,
+    HardestFirst,
+}
+
+impl OrderingSearchStrategy {
+    pub const ALL: [Self; 4] = [
+        Self::GeneticAlgorithm,
+        Self::RandomRestart,
+        Self::SimulatedAnnealing,
+        Self::HardestFirst,
+    ];
+
+    // The name used on the command line and in checkpoints.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::GeneticAlgorithm => "ga",
+            Self::RandomRestart => "random-restart",
+            Self::SimulatedAnnealing => "annealing",
+            Self::HardestFirst => "hardest-first",
+        }
+    }
+}
+
+// How the GA creates new organisms.
+//
+// Generational: The whole population is replaced at once, when all the organisms in
+// the current generation have been routed. Towards the end of each generation,
+// threads have to wait for the slowest ones to finish.
+//
+// SteadyState: Each time an organism has been routed, a single new organism is bred
+// from the population and handed out. When it has been routed, it replaces the least
+// fit organism in the population, if it's fitter. No thread ever waits.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GaStrategy {
+    Generational,
+    SteadyState,
+}
+
+// How two parent organisms are combined. Each gene is the index of the connection
+// that the connection for the gene depends on, and the ordering is a topological sort
+// of the dependencies.
+//
+// SinglePoint: Swap all the genes after a random point.
+// Uniform: Swap each gene with a probability of 0.5.
+// DependencyPreserving: Swap the dependency chain of a random gene as a whole, so that
+// the part of the ordering that the chain encodes is passed on intact.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CrossoverOperator {
+    SinglePoint,
+    Uniform,
+    DependencyPreserving,
+}
+
+// How an organism is mutated.
+//
+// Dependency: Point a random gene to a new random dependency.
+// Swap: Swap the dependencies of two random genes.
+// Insert: Move a random gene to directly after another random gene. The genes that
+// depended on the moved gene get its old dependency, closing the gap it leaves.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MutationOperator {
+    Dependency,
+    Swap,
+    Insert,
+}
+
+// Settings for the genetic algorithm that searches for the connection ordering that
+// gives the best layout. Changes take effect from the next generation.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GaSettings {
+    pub strategy: GaStrategy,
+    pub n_organisms_in_population: usize,
+    pub crossover_rate: f64,
+    pub mutation_rate: f64,
+    pub crossover_operator: CrossoverOperator,
+    pub mutation_operator: MutationOperator,
+    // Raise the mutation rate while the best fitness is not improving, to get out of
+    // local optimums.
+    pub is_adaptive_mutation: bool,
+    // Number of organisms that compete in each tournament when selecting parents.
+    // Higher values select more strongly for fitness.
+    pub n_tournament_candidates: usize,
+    // Number of the best organisms that are copied unchanged into the next
+    // generation, so that the best ordering found so far is never lost.
+    pub n_elites: usize,
+    // Max number of orderings for which the resulting fitness is remembered, so that
+    // organisms that give the same ordering as an earlier one don't have to be routed
+    // again. 0 disables the cache.
+    pub fitness_cache_size: usize,
+}
+
+impl GaSettings {
+    pub fn new() -> Self {
+        Self {
+            strategy: GaStrategy::Generational,
+            n_organisms_in_population: DEFAULT_N_ORGANISMS_IN_POPULATION,
+            crossover_rate: DEFAULT_CROSSOVER_RATE,
+            mutation_rate: DEFAULT_MUTATION_RATE,
+            crossover_operator: CrossoverOperator::SinglePoint,
+            mutation_operator: MutationOperator::Dependency,
+            is_adaptive_mutation: false,
+            n_tournament_candidates: DEFAULT_N_TOURNAMENT_CANDIDATES,
+            n_elites: DEFAULT_N_ELITES,
+            fitness_cache_size: DEFAULT_FITNESS_CACHE_SIZE,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.n_organisms_in_population < 2 {
+            return Err("The population must have at least 2 organisms".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.crossover_rate) || !(0.0..=1.0).contains(&self.mutation_rate) {
+            return Err("The crossover and mutation rates must be between 0 and 1".to_string());
+        }
+        if self.n_tournament_candidates < 1 || self.n_tournament_candidates > self.n_organisms_in_population {
+            return Err("The tournament must have between 1 and population size candidates".to_string());
+        }
+        if self.n_elites > self.n_organisms_in_population {
+            return Err("There can't be more elites than organisms in the population".to_string());
         }
+        Ok(())
     }
 }
@@ -0,0 +1,194 @@
+use crate::board::Board;
+use crate::layout::Layout;
+use crate::router_control::RouterControl;
+use crate::settings::{GaSettings, OrderingSearchStrategy};
+use crate::via::{OffsetVia, StartEndVia, Via};
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
+
+// Find the smallest board that the circuit can still be routed completely on.
+//
+// The search slows down as the board gets larger, so the board should not be larger
+// than necessary, but it's hard to tell by hand how small it can be. The board is
+// shrunk by removing rows and columns at its edges. When a row or column is removed
+// at the top or left, the components are moved along with the rest of the board.
+//
+// Each candidate board is tried by checking n_checks orderings with the ordering
+// search, and is accepted if the best layout found completes all the routes. Since
+// the search is cut short, a board can be rejected even if a complete layout exists
+// for it, so more checks can give a smaller board.
+//
+// First, the board is cropped to the rectangle used by the complete layout on the
+// original board. All the routes of that layout fit in the rectangle, so this
+// usually succeeds. Then a single row or column is removed at each edge in turn, for
+// as long as any of them can be removed.
+
+pub struct ShrinkResult {
+    // The complete layout on the smallest board.
+    pub layout: Layout,
+    // How far the components were moved.
+    pub offset: OffsetVia,
+}
+
+pub struct BoardShrinker {
+    ordering_search_strategy: OrderingSearchStrategy,
+    ga_settings: GaSettings,
+    seed: u64,
+    n_checks: usize,
+}
+
+impl BoardShrinker {
+    pub fn new(
+        ordering_search_strategy: OrderingSearchStrategy,
+        ga_settings: &GaSettings,
+        seed: u64,
+        n_checks: usize,
+    ) -> Self {
+        Self {
+            ordering_search_strategy,
+            ga_settings: ga_settings.clone(),
+            seed,
+            n_checks,
+        }
+    }
+
+    pub fn shrink(&self, input_layout: &Layout) -> Result<ShrinkResult, String> {
+        let mut best_layout = self.route(input_layout);
+        if !is_complete(&best_layout) {
+            return Err(format!(
+                "The circuit could not be routed completely on the {},{} board in {} checks",
+                input_layout.board.w, input_layout.board.h, self.n_checks
+            ));
+        }
+        let mut offset = OffsetVia::new(0, 0);
+        if let Some(used_rect) = best_layout.calc_used_rect() {
+            if let Some(layout) = self.try_crop(&best_layout, &used_rect) {
+                offset -= rect_offset(&used_rect);
+                best_layout = layout;
+            }
+        }
+        loop {
+            let (w, h) = (best_layout.board.w, best_layout.board.h);
+            let full_rect = StartEndVia::new(Via::new(0, 0), Via::new(w - 1, h - 1));
+            let mut rect_vec = Vec::new();
+            if w > 1 {
+                rect_vec.push(StartEndVia::new(full_rect.start, Via::new(w - 2, h - 1)));
+                rect_vec.push(StartEndVia::new(Via::new(1, 0), full_rect.end));
+            }
+            if h > 1 {
+                rect_vec.push(StartEndVia::new(full_rect.start, Via::new(w - 1, h - 2)));
+                rect_vec.push(StartEndVia::new(Via::new(0, 1), full_rect.end));
+            }
+            let mut is_shrunk = false;
+            for rect in &rect_vec {
+                if let Some(layout) = self.try_crop(&best_layout, rect) {
+                    offset -= rect_offset(rect);
+                    best_layout = layout;
+                    is_shrunk = true;
+                }
+            }
+            if !is_shrunk {
+                break;
+            }
+        }
+        Ok(ShrinkResult {
+            layout: best_layout,
+            offset,
+        })
+    }
+
+    // Route the circuit cropped to the rectangle, and return the layout if it's
+    // complete.
+    fn try_crop(&self, layout: &Layout, rect: &StartEndVia) -> Option<Layout> {
+        let cropped_layout = crop(layout, rect)?;
+        let routed_layout = self.route(&cropped_layout);
+        eprintln!(
+            "Board {},{}: {} completed, {} failed",
+            cropped_layout.board.w,
+            cropped_layout.board.h,
+            routed_layout.n_completed_routes,
+            routed_layout.n_failed_routes
+        );
+        is_complete(&routed_layout).then_some(routed_layout)
+    }
+
+    fn route(&self, input_layout: &Layout) -> Layout {
+        let best_layout = Arc::new(Mutex::new(Layout::new()));
+        let mut router_control = RouterControl::new(
+            Arc::new(Mutex::new(input_layout.clone())),
+            Arc::new(Mutex::new(Layout::new())),
+            Arc::clone(&best_layout),
+            Arc::new(AtomicUsize::new(0)),
+            Arc::new(AtomicUsize::new(usize::MAX)),
+            self.ordering_search_strategy,
+            &self.ga_settings,
+            self.seed,
+            true,
+        );
+        router_control.check_orderings(self.n_checks);
+        let best_layout = best_layout.lock().unwrap().clone();
+        best_layout
+    }
+}
+
+fn is_complete(layout: &Layout) -> bool {
+    layout.n_failed_routes == 0 && layout.n_completed_routes == layout.circuit.connection_vec.len()
+}
+
+fn rect_offset(rect: &StartEndVia) -> OffsetVia {
+    OffsetVia::new(rect.start.x as isize, rect.start.y as isize)
+}
+
+// The unrouted circuit on a board cropped to the rectangle, with the components moved
+// along. None if any of the pins are outside of the rectangle.
+fn crop(layout: &Layout, rect: &StartEndVia) -> Option<Layout> {
+    for component_name in layout.circuit.component_name_to_component_map.keys() {
+        for pin in layout.circuit.calc_component_pins(component_name) {
+            if !(rect.start.x..=rect.end.x).contains(&pin.x) || !(rect.start.y..=rect.end.y).contains(&pin.y) {
+                return None;
+            }
+        }
+    }
+    let mut cropped_layout = Layout::new();
+    cropped_layout.circuit = layout.circuit.clone();
+    cropped_layout.circuit.move_components(&-rect_offset(rect));
+    cropped_layout.settings = layout.settings.clone();
+    cropped_layout.board = Board::new(rect.end.x - rect.start.x + 1, rect.end.y - rect.start.y + 1);
+    Some(cropped_layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{Component, Connection, ConnectionPoint};
+
+    #[test]
+    fn board_is_shrunk_to_the_pins_and_the_components_are_moved() {
+        let mut layout = Layout::new();
+        layout.board = Board::new(10, 10);
+        let circuit = &mut layout.circuit;
+        circuit
+            .package_to_pos_map
+            .insert("pin".to_string(), vec![OffsetVia::new(0, 0)]);
+        for (name, x) in [("a", 2), ("b", 5)] {
+            circuit
+                .component_name_to_component_map
+                .insert(name.to_string(), Component::new("pin".to_string(), Via::new(x, 3)));
+        }
+        circuit.connection_vec = vec![Connection::new(
+            ConnectionPoint::new("a".to_string(), 0),
+            ConnectionPoint::new("b".to_string(), 0),
+        )];
+        let shrinker = BoardShrinker::new(OrderingSearchStrategy::HardestFirst, &GaSettings::new(), 1, 1);
+        let result = shrinker.shrink(&layout).unwrap();
+        // The route goes through the wire layer in the row above the pins, so that row
+        // is kept.
+        assert_eq!((result.layout.board.w, result.layout.board.h), (4, 2));
+        assert_eq!(result.offset, OffsetVia::new(-2, -2));
+        assert!(is_complete(&result.layout));
+        assert_eq!(
+            result.layout.circuit.calc_component_pins(&"a".to_string()),
+            vec![Via::new(0, 1)]
+        );
+    }
+}
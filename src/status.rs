@@ -1,6 +1,10 @@
 // use lazy_static::lazy_static;
 use std::sync::Mutex;
 
+use crate::ga_stats::GenerationStats;
+use crate::pareto::LayoutObjectives;
+use crate::settings::{GaSettings, OrderingSearchStrategy};
+
 // lazy_static! {
 //     pub static ref STATUS_MUTEX: Mutex<()> = Mutex::new(());
 // }
@@ -9,11 +13,33 @@ pub struct Status {
     pub ms_per_frame: f32,
     pub checked_total: usize,
     pub checked_per_second: f32,
+    pub n_generations: usize,
+
+    // Refreshed periodically from the GA, for plotting the search progress.
+    pub generation_stats_vec: Vec<GenerationStats>,
+
+    // Refreshed with the statistics. The ID and objectives of each layout in the
+    // Pareto archive.
+    pub pareto_objectives_vec: Vec<(usize, LayoutObjectives)>,
+    // The Pareto layout shown instead of the best layout, if any.
+    pub selected_pareto_layout_id: Option<usize>,
+    pub is_pareto_export_requested: bool,
+    // Write the build sheet for the layout that is shown. See BuildSheet.
+    pub is_build_sheet_export_requested: bool,
+    // Write the printable assembly sheets for the layout that is shown. See
+    // AssemblyPdf.
+    pub is_pdf_export_requested: bool,
 
     pub wire_cost: i32,
     pub strip_cost: i32,
     pub via_cost: i32,
     pub cut_cost: i32,
+    // Applied to the input layout. See Settings::area_cost.
+    pub area_cost: usize,
+    // Applied to the input layout. See Settings::length_mismatch_cost.
+    pub length_mismatch_cost: usize,
+    // Applied to the input layout. See Settings::rip_up_passes.
+    pub rip_up_passes: usize,
 
     pub zoom: f32,
 
@@ -25,22 +51,44 @@ pub struct Status {
     pub best_layout_failed_routes: usize,
     pub best_layout_cost: usize,
 
+    pub best_layout_opens: usize,
+    pub best_layout_shorts: usize,
+    pub best_layout_shared_strips: usize,
+    // The name of each connection group, and the difference between the longest and
+    // the shortest route in it. None if the group is not completely routed.
+    pub best_layout_group_len_diff_vec: Vec<(String, Option<usize>)>,
+
     pub show_rats_nest: bool,
     pub show_only_failed: bool,
     pub show_current_layout: bool,
+    pub show_cost_regions: bool,
     pub pause_router: bool,
+    pub use_a_star: bool,
+    pub ordering_search_strategy: OrderingSearchStrategy,
+
+    pub ga_settings: GaSettings,
 }
 
 impl Status {
-    pub fn new() -> Self {
+    pub fn new(ga_settings: GaSettings, ordering_search_strategy: OrderingSearchStrategy) -> Self {
         Self {
             ms_per_frame: 0.0,
             checked_total: 0,
             checked_per_second: 0.0,
+            n_generations: 0,
+            generation_stats_vec: Vec::new(),
+            pareto_objectives_vec: Vec::new(),
+            selected_pareto_layout_id: None,
+            is_pareto_export_requested: false,
+            is_build_sheet_export_requested: false,
+            is_pdf_export_requested: false,
             wire_cost: 10,
             strip_cost: 10,
             via_cost: 1,
             cut_cost: 100,
+            area_cost: 0,
+            length_mismatch_cost: 10,
+            rip_up_passes: 1,
             zoom: 15.0,
             current_layout_completed_routes: 0,
             current_layout_failed_routes: 0,
@@ -48,10 +96,18 @@ impl Status {
             best_layout_completed_routes: 0,
             best_layout_failed_routes: 0,
             best_layout_cost: 0,
+            best_layout_opens: 0,
+            best_layout_shorts: 0,
+            best_layout_shared_strips: 0,
+            best_layout_group_len_diff_vec: Vec::new(),
             show_rats_nest: false,
             show_only_failed: false,
             show_current_layout: false,
+            show_cost_regions: true,
             pause_router: false,
+            use_a_star: true,
+            ordering_search_strategy,
+            ga_settings,
         }
     }
 }
@@ -1,35 +1,242 @@
-use crate::layout::{Layout, RouteStepVec};
+use crate::layout::{CostBreakdown, Layout, RouteStepVec};
 use crate::nets::Nets;
 use crate::router::Router;
-use crate::via::{via_to_str, CostVia, LayerCostVia, LayerVia, StartEndVia, ValidVia, Via};
+use crate::via::{via_to_str, LayerVia, StartEndVia, ValidVia, Via};
 use std::sync::Mutex;
 
 use crate::board::Board;
-use crate::settings::Settings;
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashSet};
+use crate::circuit::{CostFactors, NetAttributes, POWER_NET_WIRE_COST_FACTOR};
+use crate::settings::{SearchAlgorithm, Settings};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+type FrontierPri = BinaryHeap<Reverse<FrontierEntry>>;
+
+// The search is run once for each connection in each layout, so its buffers are
+// reused instead of being allocated for each route. The per node state is kept in
+// dense arrays indexed by node_idx(), and each slot is stamped with the generation of
+// the search that last wrote it. A slot with an old stamp is unvisited, so starting a
+// new search is O(1) regardless of the board size.
+//
+// For nets with a maximum strip length, the number of strip steps taken is part of the
+// node, so that a route with fewer strip steps is still found when a cheaper route to
+// the same via has used too many. A node is skipped if the via has already been
+// explored with the same or fewer strip steps, since that got there at a lower cost.
+
+// The costs of the steps for the route being searched for, and which layers it may use.
+// They follow from the settings and the attributes of the net the connection is in.
+// See NetAttributes.
+#[derive(Clone, Copy)]
+struct StepCosts {
+    wire_cost: usize,
+    strip_cost: usize,
+    via_cost: usize,
+    is_wire_allowed: bool,
+    is_strip_allowed: bool,
+}
+
+impl StepCosts {
+    fn new(settings: &Settings, net_attributes: &NetAttributes) -> Self {
+        let wire_cost_factor = if net_attributes.is_power { POWER_NET_WIRE_COST_FACTOR } else { 1 };
+        Self {
+            wire_cost: settings.wire_cost * wire_cost_factor,
+            strip_cost: settings.strip_cost,
+            via_cost: settings.via_cost,
+            is_wire_allowed: !net_attributes.is_wire_forbidden,
+            is_strip_allowed: !net_attributes.is_strip_forbidden,
+        }
+    }
 
-type FrontierPri = BinaryHeap<Reverse<LayerCostVia>>;
-type FrontierSet = HashSet<LayerVia>;
-type ExploredSet = HashSet<LayerVia>;
+    // The cost of a step into a via, scaled by the factors of the cost regions that
+    // the via is in.
+    fn calc_step_cost(&self, step_kind: StepKind, cost_factors: &CostFactors) -> usize {
+        match step_kind {
+            StepKind::Wire => self.wire_cost * cost_factors.wire_factor,
+            StepKind::Strip => self.strip_cost * cost_factors.strip_factor,
+            StepKind::Via => self.via_cost * cost_factors.via_factor,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum StepKind {
+    Wire,
+    Strip,
+    Via,
+}
+
+// A via on one of the layers, and the number of strip steps taken to get there. The
+// strip steps are only counted for nets with a maximum strip length, and are otherwise
+// always 0.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SearchNode {
+    layer_via: LayerVia,
+    strip_len: usize,
+}
+
+impl SearchNode {
+    fn new(layer_via: LayerVia, strip_len: usize) -> Self {
+        Self { layer_via, strip_len }
+    }
+}
+
+// Ordered by the priority, and by the via for a stable order between entries of equal
+// priority.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FrontierEntry {
+    priority: usize,
+    node: SearchNode,
+}
+
+impl FrontierEntry {
+    fn key(&self) -> (usize, usize, usize, bool, usize) {
+        let layer_via = &self.node.layer_via;
+        (
+            self.priority,
+            layer_via.via.x,
+            layer_via.via.y,
+            layer_via.is_wire_layer,
+            self.node.strip_len,
+        )
+    }
+}
+
+impl Ord for FrontierEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for FrontierEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum NodeState {
+    Unvisited,
+    Frontier,
+    Explored,
+}
 
 pub struct UniformCostSearch {
-    via_cost_vec: Vec<CostVia>,
+    generation: u32,
+    generation_vec: Vec<u32>,
+    cost_vec: Vec<usize>,
+    state_vec: Vec<NodeState>,
+    // Index of the node from which each node was reached at the lowest cost, and the
+    // number of layer changes on the way there. The layer changes are used for
+    // breaking ties between routes of equal cost.
+    parent_vec: Vec<usize>,
+    layer_changes_vec: Vec<usize>,
     frontier_pri: FrontierPri,
-    frontier_set: FrontierSet,
-    explored_set: ExploredSet,
+    // A* state. When use_heuristic is false, the estimate is always 0 and the search
+    // is plain UCS.
+    use_heuristic: bool,
+    end_via: Via,
+    step_costs: StepCosts,
+    // True if the router scales any step costs, for cost regions or pairs.
+    has_cost_factors: bool,
+    // The maximum strip length of the net, and the number of strip lengths that are
+    // tracked for each via, which is 1 if there is no maximum.
+    max_strip_len: Option<usize>,
+    n_strip_lens: usize,
+    // Where the search reached the end via.
+    end_node: SearchNode,
 }
 
 impl UniformCostSearch {
     pub fn new(board: Board) -> Self {
+        // Two nodes per via, one on each layer.
+        let n_nodes = board.size() * 2;
         Self {
-            via_cost_vec: vec![CostVia::new(); board.size()],
+            generation: 0,
+            generation_vec: vec![0; n_nodes],
+            cost_vec: vec![usize::MAX; n_nodes],
+            state_vec: vec![NodeState::Unvisited; n_nodes],
+            parent_vec: vec![usize::MAX; n_nodes],
+            layer_changes_vec: vec![0; n_nodes],
             frontier_pri: BinaryHeap::new(),
-            frontier_set: HashSet::new(),
-            explored_set: HashSet::new(),
+            use_heuristic: false,
+            end_via: Via::new(0, 0),
+            step_costs: StepCosts::new(&Settings::new(), &NetAttributes::new()),
+            has_cost_factors: false,
+            max_strip_len: None,
+            n_strip_lens: 1,
+            end_node: SearchNode::new(LayerVia::new(), 0),
+        }
+    }
+
+    // Invalidate the state from the previous search. The buffers are only
+    // reallocated if they are too small for the board and the number of strip lengths,
+    // so that they are not reallocated for each route when nets with and without a
+    // maximum strip length take turns.
+    fn reset(&mut self, board: Board, max_strip_len: Option<usize>) {
+        self.max_strip_len = max_strip_len;
+        self.n_strip_lens = max_strip_len.map_or(1, |max_strip_len| max_strip_len + 1);
+        let n_nodes = board.size() * 2 * self.n_strip_lens;
+        if self.generation_vec.len() < n_nodes {
+            self.generation_vec = vec![0; n_nodes];
+            self.cost_vec = vec![usize::MAX; n_nodes];
+            self.state_vec = vec![NodeState::Unvisited; n_nodes];
+            self.parent_vec = vec![usize::MAX; n_nodes];
+            self.layer_changes_vec = vec![0; n_nodes];
+            self.generation = 0;
+        }
+        if self.generation == u32::MAX {
+            self.generation_vec.fill(0);
+            self.generation = 0;
+        }
+        self.generation += 1;
+        self.frontier_pri.clear();
+    }
+
+    fn node_idx(&self, board: Board, node: SearchNode) -> usize {
+        (board.idx(node.layer_via.via) * 2 + node.layer_via.is_wire_layer as usize) * self.n_strip_lens + node.strip_len
+    }
+
+    fn node_from_idx(&self, board: Board, i: usize) -> SearchNode {
+        let layer_via_idx = i / self.n_strip_lens;
+        let via_idx = layer_via_idx / 2;
+        SearchNode::new(
+            LayerVia::from_via(Via::new(via_idx % board.w, via_idx / board.w), layer_via_idx % 2 == 1),
+            i % self.n_strip_lens,
+        )
+    }
+
+    // Make sure the slot belongs to the current search before it's written to.
+    fn claim_node(&mut self, i: usize) {
+        if self.generation_vec[i] != self.generation {
+            self.generation_vec[i] = self.generation;
+            self.cost_vec[i] = usize::MAX;
+            self.state_vec[i] = NodeState::Unvisited;
+            self.parent_vec[i] = usize::MAX;
+            self.layer_changes_vec[i] = 0;
+        }
+    }
+
+    fn get_state(&self, board: Board, node: SearchNode) -> NodeState {
+        let i = self.node_idx(board, node);
+        if self.generation_vec[i] == self.generation {
+            self.state_vec[i]
+        } else {
+            NodeState::Unvisited
         }
     }
 
+    fn set_state(&mut self, board: Board, node: SearchNode, state: NodeState) {
+        let i = self.node_idx(board, node);
+        self.claim_node(i);
+        self.state_vec[i] = state;
+    }
+
+    // The via has been explored with the same or fewer strip steps.
+    fn is_dominated(&self, board: Board, node: SearchNode) -> bool {
+        (0..=node.strip_len)
+            .any(|strip_len| self.get_state(board, SearchNode::new(node.layer_via, strip_len)) == NodeState::Explored)
+    }
+
     pub fn find_lowest_cost_route(
         &mut self,
         board: Board,
@@ -38,19 +245,18 @@ impl UniformCostSearch {
         router: &mut Router,
         // settings: &mut Settings,
         start_end_via: StartEndVia,
+        net_attributes: &NetAttributes,
     ) -> RouteStepVec {
         let end = start_end_via.end.clone_owned();
+        self.reset(board, net_attributes.max_strip_len);
+        self.step_costs = StepCosts::new(&layout.settings, net_attributes);
+        self.has_cost_factors = router.has_cost_factors();
         let found_route = self.find_costs(board, layout, nets, router, start_end_via);
 
         // self.dump_costs(board);
 
         return if found_route {
-            self.backtrace_lowest_cost_route(
-                board,
-                layout,
-                router,
-                start_end_via
-            )
+            self.backtrace_lowest_cost_route(board, layout, start_end_via)
         } else {
             RouteStepVec::new()
         };
@@ -74,6 +280,10 @@ impl UniformCostSearch {
     //           frontier.add(n)
     //         'else if' n is in frontier with higher cost
     //           replace existing node with n
+    //
+    // With A*, the frontier is ordered by cost + estimated remaining cost instead of
+    // by cost alone. The costs stored in cost_vec are still the actual costs from
+    // the start, so the backtrace works the same for both searches.
 
     fn find_costs(
         &mut self,
@@ -91,52 +301,73 @@ impl UniformCostSearch {
             via: start_end_via.end,
             is_wire_layer: false,
         };
-        self.set_cost(board, start, 0);
-        self.frontier_pri.push(Reverse(LayerCostVia {
-            layer_via: start,
-            cost: 0,
+        self.use_heuristic = layout.settings.search_algorithm == SearchAlgorithm::AStar;
+        self.end_via = end.via;
+        let start_node = SearchNode::new(start, 0);
+        self.set_cost(board, start_node, 0);
+        self.frontier_pri.push(Reverse(FrontierEntry {
+            priority: self.estimate_remaining_cost(start),
+            node: start_node,
         }));
-        self.frontier_set.insert(LayerVia {
-            via: start.via,
-            is_wire_layer: false,
-        });
+        self.set_state(board, start_node, NodeState::Frontier);
         // #[rustfmt::skip]
         while !self.frontier_pri.is_empty() {
-            let node = self.frontier_pri.pop().unwrap();
+            let node = self.frontier_pri.pop().unwrap().0.node;
 
             // LOTS OF COST TWEAKS HERE
 
-            let layer_node = LayerVia::from_layer_cost_via(&node);
+            let layer_node = node.layer_via;
             // println!("layer_node: {:?}", layer_node);
-            assert!(self.frontier_set.remove(&layer_node));
+
+            // Entries are never removed from the priority queue when a node is reached
+            // at a lower cost. Instead, a new entry with the lower cost is pushed, so
+            // the queue may hold several entries for a node. The cheapest one is popped
+            // first, and explores the node. Any that remain are stale and are skipped,
+            // as are nodes for vias that were explored with fewer strip steps.
+            if self.is_dominated(board, node) {
+                continue;
+            }
 
             if layer_node.is_target(end.via) {
+                self.end_node = node;
                 return true;
             }
 
-            self.explored_set.insert(layer_node);
+            self.set_state(board, node, NodeState::Explored);
 
+            let step_costs = self.step_costs;
             if layer_node.is_wire_layer {
                 if layer_node.via.x > 0 {
-                    self.explore_neighbour(board, layout, nets, router, layer_node, self.step_left(layer_node), start_end_via, layout.settings.wire_cost);
+                    self.explore_neighbour(board, layout, nets, router, node, self.step_left(layer_node), start_end_via, StepKind::Wire);
                 }
                 if layer_node.via.x < board.w - 1 {
-                    self.explore_neighbour(board, layout, nets, router, layer_node, self.step_right(layer_node), start_end_via, layout.settings.wire_cost);
+                    self.explore_neighbour(board, layout, nets, router, node, self.step_right(layer_node), start_end_via, StepKind::Wire);
                 }
-                self.explore_neighbour(board, layout, nets, router, layer_node, self.step_to_strip(layer_node), start_end_via, layout.settings.via_cost);
+                self.explore_neighbour(board, layout, nets, router, node, self.step_to_strip(layer_node), start_end_via, StepKind::Via);
             } else {
-                if layer_node.via.y > 0 {
-                    self.explore_neighbour(board, layout, nets, router, layer_node, self.step_up(layer_node), start_end_via, layout.settings.strip_cost);
-                }
-                if layer_node.via.y < board.h - 1 {
-                    self.explore_neighbour(board, layout, nets, router, layer_node, self.step_down(layer_node), start_end_via, layout.settings.strip_cost);
+                // The strips that the pins are on can always be used, since the wire
+                // layer is blocked at the pins.
+                let is_strip_allowed = step_costs.is_strip_allowed
+                    || layer_node.via.x == start_end_via.start.x
+                    || layer_node.via.x == start_end_via.end.x;
+                if is_strip_allowed {
+                    if layer_node.via.y > 0 {
+                        self.explore_neighbour(board, layout, nets, router, node, self.step_up(layer_node), start_end_via, StepKind::Strip);
+                    }
+                    if layer_node.via.y < board.h - 1 {
+                        self.explore_neighbour(board, layout, nets, router, node, self.step_down(layer_node), start_end_via, StepKind::Strip);
+                    }
                 }
-                self.explore_neighbour(board, layout, nets, router, layer_node, self.step_to_wire(layer_node), start_end_via, layout.settings.via_cost);
-
-                // Wire jumps
-                let wire_to_via = router.wire_to_via_ref(board, layer_node.via);
-                if wire_to_via.is_valid {
-                    self.explore_frontier(board, layout, layer_node, LayerVia { via: wire_to_via.via, is_wire_layer: false }, layout.settings.wire_cost);
+                if step_costs.is_wire_allowed {
+                    self.explore_neighbour(board, layout, nets, router, node, self.step_to_wire(layer_node), start_end_via, StepKind::Via);
+
+                    // Wire jumps. The wire already exists, so the cost regions it
+                    // crosses don't apply.
+                    let wire_to_via = router.wire_to_via_ref(board, layer_node.via);
+                    if wire_to_via.is_valid {
+                        let next_node = SearchNode::new(LayerVia { via: wire_to_via.via, is_wire_layer: false }, node.strip_len);
+                        self.explore_frontier(board, layout, node, next_node, step_costs.wire_cost);
+                    }
                 }
             }
         }
@@ -149,13 +380,20 @@ impl UniformCostSearch {
         layout: &mut Layout,
         nets: &mut Nets,
         router: &mut Router,
-        cur_node: LayerVia,
-        next_node: LayerVia,
+        cur_node: SearchNode,
+        next_layer_via: LayerVia,
         start_end_via: StartEndVia,
-        step_cost: usize,
+        step_kind: StepKind,
     ) {
-        if router.is_available(board, layout, nets, next_node, start_end_via.start) {
-            self.explore_frontier(board, layout, cur_node, next_node, step_cost);
+        // Steps along a strip that would make it longer than the maximum are not taken.
+        let next_strip_len = match (step_kind, self.max_strip_len) {
+            (StepKind::Strip, Some(max_strip_len)) if cur_node.strip_len == max_strip_len => return,
+            (StepKind::Strip, Some(_)) => cur_node.strip_len + 1,
+            _ => cur_node.strip_len,
+        };
+        if router.is_available(board, layout, nets, next_layer_via, start_end_via.start) {
+            let step_cost = self.step_costs.calc_step_cost(step_kind, &router.get_cost_factors(board, next_layer_via.via));
+            self.explore_frontier(board, layout, cur_node, SearchNode::new(next_layer_via, next_strip_len), step_cost);
         }
     }
 
@@ -163,282 +401,232 @@ impl UniformCostSearch {
         &mut self,
         board: Board,
         layout: &mut Layout,
-        cur_node: LayerVia,
-        next_node: LayerVia,
+        cur_node: SearchNode,
+        next_node: SearchNode,
         step_cost: usize,
     ) {
         // println!("--");
         // println!("cur_node={:?}", cur_node);
         // println!("next_node={:?}", next_node);
 
-        if self.explored_set.contains(&next_node) {
+        if self.is_dominated(board, next_node) {
             return;
         }
+        let next_node_state = self.get_state(board, next_node);
 
         let next_node_cost = self.get_cost(board, cur_node) + step_cost;
+        let next_node_layer_changes = self.get_layer_changes(board, cur_node)
+            + self.count_layer_changes(cur_node.layer_via, next_node.layer_via);
 
-        if !self.frontier_set.contains(&next_node) {
-            self.frontier_set.insert(next_node);
+        if next_node_state == NodeState::Unvisited {
+            self.set_state(board, next_node, NodeState::Frontier);
             // assert_eq!(self.get_cost(board, next_node), 0);
             // println!("next_node={:?} get_cost()={}", next_node, self.get_cost(board, next_node));
             self.set_cost(board, next_node, next_node_cost);
+            self.set_parent(board, next_node, cur_node, next_node_layer_changes);
             // println!("SET next_node={} next_node_cost={}", next_node, next_node_cost);
-            self.frontier_pri.push(Reverse(LayerCostVia::from_layer_via(next_node, next_node_cost)));
+            let next_node_priority = next_node_cost + self.estimate_remaining_cost(next_node.layer_via);
+            self.frontier_pri.push(Reverse(FrontierEntry {
+                priority: next_node_priority,
+                node: next_node,
+            }));
         } else {
-            // If frontier_set contains next_node, we may already have visited
-            // next_node, via another route. If so, the route we're reaching it by now
-            // may have a lower cost, in which case we update it with the lower cost. If
-            // we haven't visited next_node before, it will be set to usize::MAX, so it
-            // will always be updated with the lower cost.
+            // If next_node is in the frontier, we have already reached it via another
+            // route. The route we're reaching it by now may have a lower cost, in which
+            // case we update it with the lower cost, and push it to the priority queue
+            // again, so that it's explored in the right order. The old entry becomes
+            // stale and is skipped when it's popped.
+            //
+            // Between routes of equal cost, the one with fewer layer changes wins, so
+            // that the result doesn't depend on the order in which the neighbours
+            // happen to be explored.
             let frontier_cost = self.get_cost(board, next_node);
             // println!("GET next_node={} frontier_cost={}", next_node, frontier_cost);
+            let is_fewer_layer_changes = next_node_layer_changes < self.get_layer_changes(board, next_node);
             if frontier_cost > next_node_cost {
                 self.set_cost(board, next_node, next_node_cost);
+                self.set_parent(board, next_node, cur_node, next_node_layer_changes);
+                let next_node_priority = next_node_cost + self.estimate_remaining_cost(next_node.layer_via);
+                self.frontier_pri.push(Reverse(FrontierEntry {
+                    priority: next_node_priority,
+                    node: next_node,
+                }));
+            } else if frontier_cost == next_node_cost && is_fewer_layer_changes {
+                self.set_parent(board, next_node, cur_node, next_node_layer_changes);
             }
         }
     }
 
-    // fn backtrace_lowest_cost_route(
-    //     &mut self,
-    //     board: Board,
-    //     layout: &mut Layout,
-    //     router: &mut Router,
-    //     start_end_via: StartEndVia,
-    // ) -> RouteStepVec {
-    //     // println!("start_end_via: {:?} {:?}", via_to_str(&start_end_via.start), via_to_str(&start_end_via.end));
-    //
-    //     let mut route_cost = 0;
-    //     let start = LayerVia::from_via(start_end_via.start, false);
-    //     let end = LayerVia::from_via(start_end_via.end, false);
-    //     let mut route_step_vec = Vec::new();
-    //
-    //     // We start at the end and work backwards to the start.
-    //     let mut cur_node = end.clone();
-    //
-    //     route_step_vec.push(cur_node.clone());
-    //
-    //     let mut check_stuck_cnt = 0;
-    //
-    //     while cur_node.via != start.via || cur_node.is_wire_layer != start.is_wire_layer {
-    //         check_stuck_cnt += 1;
-    //         // If this assert fails, the backtrace has become stuck in an infinite loop.
-    //         // This indicates a bug in find_cost(). The dump_costs() method will
-    //         // probably show that the current location is one which does not have any
-    //         // lower cost neighbours.
-    //         assert!(check_stuck_cnt < board.w * board.h);
-    //
-    //         let mut next_node = cur_node.clone();
-    //         // println!("c: {:?}", c);;
-    //
-    //         if cur_node.is_wire_layer {
-    //             if cur_node.via.x > 0 {
-    //                 let n_left = self.step_left(cur_node);
-    //                 if self.get_cost(board, n_left) < self.get_cost(board, next_node) {
-    //                     next_node = n_left;
-    //                 }
-    //             }
-    //             if cur_node.via.x < board.w - 1 {
-    //                 let n_right = self.step_right(cur_node);
-    //                 if self.get_cost(board, n_right) < self.get_cost(board, next_node) {
-    //                     next_node = n_right;
-    //                 }
-    //             }
-    //             let n_strip = self.step_to_strip(cur_node);
-    //             if self.get_cost(board, n_strip) < self.get_cost(board, next_node) {
-    //                 next_node = n_strip;
-    //             }
-    //         } else {
-    //             if cur_node.via.y > 0 {
-    //                 let n_up = self.step_up(cur_node);
-    //                 if self.get_cost(board, n_up) < self.get_cost(board, next_node) {
-    //                     next_node = n_up;
-    //                 }
-    //             }
-    //             if cur_node.via.y < board.h - 1 {
-    //                 let n_down = self.step_down(cur_node);
-    //                 if self.get_cost(board, n_down) < self.get_cost(board, next_node) {
-    //                     next_node = n_down;
-    //                 }
-    //             }
-    //             let n_wire = self.step_to_wire(cur_node);
-    //             if self.get_cost(board, n_wire) < self.get_cost(board, next_node) {
-    //                 next_node = n_wire;
-    //             }
+    // A step between the layers is one layer change. A wire jump goes from the strip
+    // layer, through the wire layer and back, so it's two.
+    fn count_layer_changes(&self, cur_node: LayerVia, next_node: LayerVia) -> usize {
+        if cur_node.is_wire_layer != next_node.is_wire_layer {
+            1
+        } else if !cur_node.is_wire_layer && cur_node.via.x != next_node.via.x {
+            2
+        } else {
+            0
+        }
+    }
+
+    // Estimate of the cost remaining from layer_via to the end via, which is always on
+    // the strip layer. This is what turns UCS into A*. The estimate is admissible
+    // (never higher than the actual remaining cost) and consistent (never drops by
+    // more than the cost of the step taken), so a node has its final cost when it's
+    // popped from the frontier, and A* finds routes with the same cost as UCS.
     //
-    //             let wire_to_via = router.wire_to_via_ref(board, cur_node.via);
-    //             if wire_to_via.is_valid {
-    //                 let mut n_wire_jump = LayerVia::from_via(wire_to_via.via, false);
-    //                 if self.get_cost(board, n_wire_jump) < self.get_cost(board, next_node) {
-    //                     route_step_vec.push(LayerVia::from_via(cur_node.via, true));
-    //                     let x1 = cur_node.via.x;
-    //                     let x2 = n_wire_jump.via.x;
-    //                     if (x1 < x2) {
-    //                         for x in (x1..x2) {
-    //                             route_step_vec.push(LayerVia::from_via(Via::new(x, cur_node.via.y), true));
-    //                         }
-    //                     } else {
-    //                         for x in (x2..x1) {
-    //                             route_step_vec.push(LayerVia::from_via(Via::new(x, cur_node.via.y), true));
-    //                         }
-    //                     }
-    //                     // The above is my replacement for this code that Copilot apparently screwed up on. It's not possible to step by -1.
-    //                     // let step = if x1 > x2 { -1 } else { 1 };
-    //                     // for x in (x1..x2).step_by(step) {
-    //                     //     route_step_vec.push(LayerVia::from_via(Via::new(x, c.via.y), true));
-    //                     // }
-    //                     if x1 != x2 {
-    //                         route_step_vec.push(LayerVia::from_via(Via::new(x2, cur_node.via.y), true));
-    //                     }
-    //                     next_node = n_wire_jump;
-    //                 }
-    //             }
-    //         }
-    //         route_cost += self.get_cost(board, cur_node) - self.get_cost(board, next_node);
-    //         cur_node = next_node;
-    //         route_step_vec.push(cur_node.clone());
-    //     }
-    //     layout.cost += route_cost;
-    //     route_step_vec.reverse();
+    // - Only strip steps move between rows, so each row between here and the end
+    // costs at least strip_cost. Wire layer steps and wire jumps are horizontal.
+    // - Any horizontal distance can be covered by a single wire jump along an existing
+    // wire, which costs one wire_cost regardless of its length. A new wire costs at
+    // least one wire_cost plus two vias, so one wire_cost is all we can count.
+    // - From the wire layer, we must go through a via to get back to the strip layer.
+    // - Cost regions only ever raise the cost of a step, so the estimate ignores them.
+    // - Net shortcuts don't lower the estimate. Vias in our net are available to the
+    // search, but stepping through them still has the regular costs, and the search
+    // only stops at the actual end via.
+    fn estimate_remaining_cost(&self, layer_via: LayerVia) -> usize {
+        if !self.use_heuristic {
+            return 0;
+        }
+        let step_costs = &self.step_costs;
+        let mut cost = layer_via.via.y.abs_diff(self.end_via.y) * step_costs.strip_cost;
+        if layer_via.via.x != self.end_via.x {
+            cost += step_costs.wire_cost;
+        }
+        if layer_via.is_wire_layer {
+            cost += step_costs.via_cost;
+        }
+        cost
+    }
+
+    // Reconstruct the route by following the parent links from the end back to the
+    // start. The route is returned in that order, from end to start.
     //
-    //     #[cfg(debug_assertions)]
-    //     {
-    //         layout.diag_route_step_vec = route_step_vec.clone();
-    //         layout.diag_start_via = ValidVia::from_via(start.via);
-    //         layout.diag_end_via = ValidVia::from_via(end.via);
-    //     }
+    // A wire jump is stored as a single link between two vias on the strip layer, so
+    // it's expanded to the wire layer steps between them here.
     //
-    //     route_step_vec
-    // }
-
+    // If the links are broken, which would indicate a bug in find_costs(), an empty
+    // route is returned, and the connection is treated as failed.
     fn backtrace_lowest_cost_route(
         &mut self,
         board: Board,
         layout: &mut Layout,
-        router: &mut Router,
         start_end_via: StartEndVia,
     ) -> RouteStepVec {
-        let mut route_cost = 0;
-        let start = LayerVia::from_via(start_end_via.start, false);
-        let end = LayerVia::from_via(start_end_via.end, false);
+        let start = SearchNode::new(LayerVia::from_via(start_end_via.start, false), 0);
+        let end = self.end_node;
         let mut route_step_vec = Vec::new();
-        let mut cur_node = end.clone();
-
-        route_step_vec.push(cur_node.clone());
-
-        let mut check_stuck_cnt = 0;
-
-        while cur_node.via != start.via || cur_node.is_wire_layer != start.is_wire_layer {
-            check_stuck_cnt += 1;
-            assert!(check_stuck_cnt < board.w * board.h);
-
-            let mut next_node = cur_node.clone();
-
-            // TODO: Surely, all these should be if-else? And don't call me Shirley.
-            if cur_node.is_wire_layer {
-                if cur_node.via.x > 0 {
-                    let n_left = self.step_left(cur_node);
-                    if self.get_cost(board, n_left) < self.get_cost(board, next_node) {
-                        next_node = n_left;
+        let mut cur_search_node = end;
+        let mut cur_node = end.layer_via;
+        let mut cost_breakdown = CostBreakdown::new();
+
+        route_step_vec.push(cur_node);
+
+        while cur_search_node != start {
+            let next_search_node = match self.get_parent(board, cur_search_node) {
+                Some(parent_node) if route_step_vec.len() <= self.cost_vec.len() => parent_node,
+                _ => return RouteStepVec::new(),
+            };
+            let next_node = next_search_node.layer_via;
+            let is_wire_jump = !cur_node.is_wire_layer && !next_node.is_wire_layer && cur_node.via.x != next_node.via.x;
+            if is_wire_jump {
+                // Through to the wire layer, along the wire, and the final step
+                // through to the strip layer is stored below.
+                let y = cur_node.via.y;
+                let x1 = cur_node.via.x;
+                let x2 = next_node.via.x;
+                route_step_vec.push(LayerVia::from_via(cur_node.via, true));
+                if x1 > x2 {
+                    for x in (x2 + 1..x1).rev() {
+                        route_step_vec.push(LayerVia::from_via(Via::new(x, y), true));
                     }
-                }
-                if cur_node.via.x < board.w - 1 {
-                    let n_right = self.step_right(cur_node);
-                    if self.get_cost(board, n_right) < self.get_cost(board, next_node) {
-                        next_node = n_right;
+                } else {
+                    for x in x1 + 1..x2 {
+                        route_step_vec.push(LayerVia::from_via(Via::new(x, y), true));
                     }
                 }
-                let n_strip = self.step_to_strip(cur_node);
-                if self.get_cost(board, n_strip) < self.get_cost(board, next_node) {
-                    next_node = n_strip;
+                route_step_vec.push(LayerVia::from_via(next_node.via, true));
+                cost_breakdown.n_reused += 1;
+            } else if cur_node.is_wire_layer != next_node.is_wire_layer {
+                cost_breakdown.n_vias += 1;
+                // Going backwards, so this is where the wire starts.
+                if cur_node.is_wire_layer {
+                    cost_breakdown.n_wires += 1;
                 }
+            } else if cur_node.is_wire_layer {
+                cost_breakdown.wire_len += 1;
             } else {
-                if cur_node.via.y > 0 {
-                    let n_up = self.step_up(cur_node);
-                    if self.get_cost(board, n_up) < self.get_cost(board, next_node) {
-                        next_node = n_up;
-                    }
-                }
-                if cur_node.via.y < board.h - 1 {
-                    let n_down = self.step_down(cur_node);
-                    if self.get_cost(board, n_down) < self.get_cost(board, next_node) {
-                        next_node = n_down;
-                    }
-                }
-                let n_wire = self.step_to_wire(cur_node);
-                if self.get_cost(board, n_wire) < self.get_cost(board, next_node) {
-                    next_node = n_wire;
-                }
-
-                let wire_to_via = router.wire_to_via_ref(board, cur_node.via);
-                if wire_to_via.is_valid {
-                    let mut n_wire_jump = LayerVia::from_via(wire_to_via.via, false);
-                    if self.get_cost(board, n_wire_jump) < self.get_cost(board, next_node) {
-                        // When we jump, we have to record the steps.
-                        // Through to wire layer.
-                        route_step_vec.push(LayerVia::from_via(cur_node.via, true));
-                        let x1 = cur_node.via.x;
-                        let x2 = n_wire_jump.via.x;
-                        if x1 > x2 {
-                            for x in (x2..x1).rev() {
-                                route_step_vec.push(LayerVia::from_via(Via::new(x, cur_node.via.y), true));
-                            }
-                        } else {
-                            for x in (x1..x2) {
-                                route_step_vec.push(LayerVia::from_via(Via::new(x, cur_node.via.y), true));
-                            }
-                        }
-                        // let step = if x1 > x2 { -1 } else { 1 };
-                        // for x in (x1..x2).step_by(step) {
-                        //     route_step_vec.push(LayerVia::from_via(Via::new(x, cur_node.via.y), true));
-                        // }
-                        if x1 != x2 {
-                            route_step_vec.push(LayerVia::from_via(Via::new(x2, cur_node.via.y), true));
-                        }
-                        // Final step through to strip layer is stored outside the
-                        // conditional.
-                        next_node = n_wire_jump;
-                    }
-                }
+                cost_breakdown.strip_len += 1;
             }
-            route_cost += self.get_cost(board, cur_node) - self.get_cost(board, next_node);
-            // println!("route_cost={}", route_cost);
             cur_node = next_node;
-            route_step_vec.push(cur_node.clone());
+            cur_search_node = next_search_node;
+            route_step_vec.push(cur_node);
         }
 
-        layout.cost += route_cost;
-        // route_step_vec.reverse();
+        cost_breakdown.cost = self.get_cost(board, end);
+        // Steps in cost regions and pair detours cost more than the items add up to.
+        debug_assert!(
+            self.has_cost_factors
+                || cost_breakdown.cost
+                    == (cost_breakdown.wire_len + cost_breakdown.n_reused) * self.step_costs.wire_cost
+                        + cost_breakdown.strip_len * self.step_costs.strip_cost
+                        + cost_breakdown.n_vias * self.step_costs.via_cost
+        );
+        layout.cost += cost_breakdown.cost;
+        layout.cost_breakdown.add(&cost_breakdown);
 
         #[cfg(debug_assertions)]
         {
             layout.diag_route_step_vec = route_step_vec.clone();
-            layout.diag_start_via = ValidVia::from_via(start.via);
-            layout.diag_end_via = ValidVia::from_via(end.via);
+            layout.diag_start_via = ValidVia::from_via(start.layer_via.via);
+            layout.diag_end_via = ValidVia::from_via(end.layer_via.via);
         }
 
         route_step_vec
     }
 
-    fn get_cost(&self, board: Board, layer_via: LayerVia) -> usize {
-        let i = board.idx(layer_via.via);
-        if layer_via.is_wire_layer {
-            // println!("wire_cost: {:?} {:03x}", layer_via, self.via_cost_vec[i].wire_cost);
-            self.via_cost_vec[i].wire_cost
+    fn get_parent(&self, board: Board, node: SearchNode) -> Option<SearchNode> {
+        let i = self.node_idx(board, node);
+        if self.generation_vec[i] != self.generation || self.parent_vec[i] == usize::MAX {
+            return None;
+        }
+        Some(self.node_from_idx(board, self.parent_vec[i]))
+    }
+
+    fn get_layer_changes(&self, board: Board, node: SearchNode) -> usize {
+        let i = self.node_idx(board, node);
+        if self.generation_vec[i] == self.generation {
+            self.layer_changes_vec[i]
         } else {
-            // println!("strip_cost: {:?} {:03x}", layer_via, self.via_cost_vec[i].strip_cost);
-            self.via_cost_vec[i].strip_cost
+            usize::MAX
         }
     }
 
-    fn set_cost(&mut self, board: Board, layer_via: LayerVia, cost: usize) {
-        let i = board.idx(layer_via.via);
-        if layer_via.is_wire_layer {
-            self.via_cost_vec[i].wire_cost = cost;
+    // Record the step that reached the node with the lowest cost so far.
+    fn set_parent(&mut self, board: Board, node: SearchNode, parent_node: SearchNode, layer_changes: usize) {
+        let i = self.node_idx(board, node);
+        let parent_idx = self.node_idx(board, parent_node);
+        self.claim_node(i);
+        self.parent_vec[i] = parent_idx;
+        self.layer_changes_vec[i] = layer_changes;
+    }
+
+    fn get_cost(&self, board: Board, node: SearchNode) -> usize {
+        let i = self.node_idx(board, node);
+        if self.generation_vec[i] == self.generation {
+            self.cost_vec[i]
         } else {
-            self.via_cost_vec[i].strip_cost = cost;
+            usize::MAX
         }
     }
 
+    fn set_cost(&mut self, board: Board, node: SearchNode, cost: usize) {
+        let i = self.node_idx(board, node);
+        self.claim_node(i);
+        self.cost_vec[i] = cost;
+    }
+
     fn dump_n(&self, v: usize) {
         if v == usize::MAX {
             print!("--- ");
@@ -460,9 +648,7 @@ impl UniformCostSearch {
         for y in 0..board.h {
             print!("{:03x} ", y);
             for x in 0..board.w {
-                let i = board.idx(Via::new(x, y));
-                let v = self.via_cost_vec[i].wire_cost;
-                // assert_eq!(v, self.get_cost(board, LayerVia::from_via(Via::new(x, y), true)));
+                let v = self.get_cost(board, SearchNode::new(LayerVia::from_via(Via::new(x, y), true), 0));
                 self.dump_n(v);
             }
             println!();
@@ -477,9 +663,7 @@ impl UniformCostSearch {
         for y in 0..board.h {
             print!("{:03x} ", y);
             for x in 0..board.w {
-                let i = board.idx(Via::new(x, y));
-                let v = self.via_cost_vec[i].strip_cost;
-                // assert_eq!(v, self.get_cost(board, LayerVia::from_via(Via::new(x, y), false)));
+                let v = self.get_cost(board, SearchNode::new(LayerVia::from_via(Via::new(x, y), false), 0));
                 self.dump_n(v);
             }
             println!();
@@ -513,3 +697,226 @@ impl UniformCostSearch {
         LayerVia::from_via(v.via, false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::{Component, Connection, ConnectionPoint};
+    use crate::via::OffsetVia;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    // Small board with random single pin components, a few 3 via wide two pin
+    // components that block the wire layer, and random connections between the pins.
+    fn random_layout(rng: &mut StdRng) -> Layout {
+        let mut layout = Layout::new();
+        layout.board = Board::new(rng.gen_range(3..12), rng.gen_range(3..12));
+        let circuit = &mut layout.circuit;
+        circuit.package_to_pos_map.insert("pin".to_string(), vec![OffsetVia::new(0, 0)]);
+        circuit
+            .package_to_pos_map
+            .insert("res".to_string(), vec![OffsetVia::new(0, 0), OffsetVia::new(2, 0)]);
+
+        let mut free_via_vec = Vec::new();
+        for y in 0..layout.board.h {
+            for x in 0..layout.board.w {
+                free_via_vec.push(Via::new(x, y));
+            }
+        }
+        free_via_vec.shuffle(rng);
+
+        let mut pin_vec = Vec::new();
+        for i in 0..rng.gen_range(2..(free_via_vec.len() / 2).max(3)) {
+            let via = free_via_vec.pop().unwrap();
+            let end_via = Via::new(via.x + 2, via.y);
+            let name = format!("c{}", i);
+            if rng.gen_bool(0.2) && end_via.x < layout.board.w && free_via_vec.contains(&end_via) {
+                free_via_vec.retain(|v| *v != end_via);
+                circuit
+                    .component_name_to_component_map
+                    .insert(name.clone(), Component::new("res".to_string(), via));
+                pin_vec.push(ConnectionPoint::new(name.clone(), 0));
+                pin_vec.push(ConnectionPoint::new(name, 1));
+            } else {
+                circuit
+                    .component_name_to_component_map
+                    .insert(name.clone(), Component::new("pin".to_string(), via));
+                pin_vec.push(ConnectionPoint::new(name, 0));
+            }
+        }
+
+        for _ in 0..rng.gen_range(1..6) {
+            let a = pin_vec.choose(rng).unwrap().clone();
+            let b = pin_vec.choose(rng).unwrap().clone();
+            if a.component_name != b.component_name || a.pin_idx != b.pin_idx {
+                circuit.connection_vec.push(Connection::new(a, b));
+            }
+        }
+        layout
+    }
+
+    // Reference search: Bellman-Ford over the same graph that find_costs() searches,
+    // with the availability of each step decided by the same Router. With a maximum
+    // strip length, each via has a node for each number of strip steps.
+    fn brute_force_cost(
+        board: Board,
+        layout: &mut Layout,
+        nets: &mut Nets,
+        router: &mut Router,
+        start_end_via: StartEndVia,
+        max_strip_len: Option<usize>,
+    ) -> Option<usize> {
+        let settings = layout.settings.clone();
+        let n_strip_lens = max_strip_len.map_or(1, |max_strip_len| max_strip_len + 1);
+        let mut cost_vec = vec![usize::MAX; board.size() * 2 * n_strip_lens];
+        let idx = |v: LayerVia, strip_len: usize| (board.idx(v.via) * 2 + v.is_wire_layer as usize) * n_strip_lens + strip_len;
+        cost_vec[idx(LayerVia::from_via(start_end_via.start, false), 0)] = 0;
+        let mut is_changed = true;
+        while is_changed {
+            is_changed = false;
+            for y in 0..board.h {
+                for x in 0..board.w {
+                    for is_wire_layer in [false, true] {
+                        for strip_len in 0..n_strip_lens {
+                            let node = LayerVia::from_via(Via::new(x, y), is_wire_layer);
+                            let cost = cost_vec[idx(node, strip_len)];
+                            if cost == usize::MAX || node.is_target(start_end_via.end) {
+                                continue;
+                            }
+                            let next_strip_len = strip_len + max_strip_len.is_some() as usize;
+                            let mut step_vec = Vec::new();
+                            if is_wire_layer {
+                                if x > 0 {
+                                    step_vec.push((LayerVia::from_via(Via::new(x - 1, y), true), settings.wire_cost, true, strip_len));
+                                }
+                                step_vec.push((LayerVia::from_via(Via::new(x + 1, y), true), settings.wire_cost, true, strip_len));
+                                step_vec.push((LayerVia::from_via(Via::new(x, y), false), settings.via_cost, true, strip_len));
+                            } else {
+                                if y > 0 {
+                                    step_vec.push((LayerVia::from_via(Via::new(x, y - 1), false), settings.strip_cost, true, next_strip_len));
+                                }
+                                step_vec.push((LayerVia::from_via(Via::new(x, y + 1), false), settings.strip_cost, true, next_strip_len));
+                                step_vec.push((LayerVia::from_via(Via::new(x, y), true), settings.via_cost, true, strip_len));
+                                let wire_to_via = router.wire_to_via_ref(board, node.via).clone();
+                                if wire_to_via.is_valid {
+                                    step_vec.push((LayerVia::from_via(wire_to_via.via, false), settings.wire_cost, false, strip_len));
+                                }
+                            }
+                            for (next_node, step_cost, is_checked, next_strip_len) in step_vec {
+                                if next_strip_len >= n_strip_lens {
+                                    continue;
+                                }
+                                if is_checked && !router.is_available(board, layout, nets, next_node, start_end_via.start) {
+                                    continue;
+                                }
+                                if cost + step_cost < cost_vec[idx(next_node, next_strip_len)] {
+                                    cost_vec[idx(next_node, next_strip_len)] = cost + step_cost;
+                                    is_changed = true;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let end = LayerVia::from_via(start_end_via.end, false);
+        (0..n_strip_lens)
+            .map(|strip_len| cost_vec[idx(end, strip_len)])
+            .min()
+            .filter(|&cost| cost != usize::MAX)
+    }
+
+    fn search_cost(
+        board: Board,
+        layout: &mut Layout,
+        nets: &mut Nets,
+        router: &mut Router,
+        start_end_via: StartEndVia,
+        search_algorithm: SearchAlgorithm,
+        net_attributes: &NetAttributes,
+    ) -> Option<usize> {
+        layout.settings.search_algorithm = search_algorithm;
+        let cost_before = layout.cost;
+        let mut ucs = UniformCostSearch::new(board);
+        let route_step_vec = ucs.find_lowest_cost_route(board, layout, nets, router, start_end_via, net_attributes);
+        let cost = layout.cost - cost_before;
+        if route_step_vec.is_empty() {
+            return None;
+        }
+        assert_eq!(route_step_vec.first().unwrap().via, start_end_via.end);
+        assert_eq!(route_step_vec.last().unwrap().via, start_end_via.start);
+        Some(cost)
+    }
+
+    // Route a random number of the connections to get wires, wire jumps and nets on
+    // the board, then check that both searches find the lowest cost route for the next
+    // connection, with and without a maximum strip length.
+    #[test]
+    fn search_finds_lowest_cost_on_random_boards() {
+        for seed in 0..1000 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut layout = random_layout(&mut rng);
+            layout.settings.wire_cost = rng.gen_range(1..20);
+            layout.settings.strip_cost = rng.gen_range(1..20);
+            layout.settings.via_cost = rng.gen_range(1..20);
+            let board = layout.board;
+            let connection_via_vec = layout.circuit.gen_connection_via_vec();
+            if connection_via_vec.is_empty() {
+                continue;
+            }
+            let n_routed = rng.gen_range(0..connection_via_vec.len());
+
+            let mut router = Router::new(board);
+            let mut nets = Nets::new(board);
+            let ordering = (0..connection_via_vec.len()).collect();
+            let mut limit_routes = Arc::new(AtomicUsize::new(n_routed));
+            router.route(board, &mut layout, &mut nets, ordering, &mut limit_routes);
+
+            let start_end_via = connection_via_vec[n_routed];
+            let max_strip_len = rng.gen_bool(0.5).then(|| rng.gen_range(0..board.h));
+            let net_attributes = NetAttributes {
+                max_strip_len,
+                ..NetAttributes::new()
+            };
+            let expected = brute_force_cost(board, &mut layout, &mut nets, &mut router, start_end_via, max_strip_len);
+            for search_algorithm in [SearchAlgorithm::UniformCost, SearchAlgorithm::AStar] {
+                let actual =
+                    search_cost(board, &mut layout, &mut nets, &mut router, start_end_via, search_algorithm, &net_attributes);
+                assert_eq!(
+                    actual, expected,
+                    "seed={} search_algorithm={:?} max_strip_len={:?}",
+                    seed, search_algorithm, max_strip_len
+                );
+            }
+        }
+    }
+
+    // The router swaps in a search for an empty board while the real one runs, so the
+    // first search on a board of another size must still use the settings.
+    #[test]
+    fn search_uses_the_settings_after_the_board_size_changes() {
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut layout = random_layout(&mut rng);
+            layout.settings.wire_cost = rng.gen_range(20..40);
+            layout.settings.strip_cost = rng.gen_range(20..40);
+            layout.settings.via_cost = rng.gen_range(20..40);
+            let board = layout.board;
+            let Some(&start_end_via) = layout.circuit.gen_connection_via_vec().first() else {
+                continue;
+            };
+            let mut router = Router::new(board);
+            let mut nets = Nets::new(board);
+            let expected = brute_force_cost(board, &mut layout, &mut nets, &mut router, start_end_via, None);
+            let cost_before = layout.cost;
+            let mut ucs = UniformCostSearch::new(Board::new(0, 0));
+            let route_step_vec =
+                ucs.find_lowest_cost_route(board, &mut layout, &mut nets, &mut router, start_end_via, &NetAttributes::new());
+            let actual = (!route_step_vec.is_empty()).then(|| layout.cost - cost_before);
+            assert_eq!(actual, expected, "seed={}", seed);
+        }
+    }
+}
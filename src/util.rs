@@ -21,3 +21,29 @@ impl Drop for Timer {
         println!("Time elapsed: {:?}", duration);
     }
 }
+
+// 64-bit FNV-1a. Unlike DefaultHasher, the algorithm is fixed, but the bytes that
+// the standard Hash impls feed it are not guaranteed to stay the same between Rust
+// versions or platforms. A stored hash may therefore stop matching after an upgrade.
+pub struct Fnv1aHasher {
+    hash: u64,
+}
+
+impl Fnv1aHasher {
+    pub fn new() -> Self {
+        Self { hash: 0xcbf29ce484222325 }
+    }
+}
+
+impl std::hash::Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.hash ^= b as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+}
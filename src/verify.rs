@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::layout::Layout;
+use crate::via::Via;
+
+// Check that a finished layout actually implements the circuit.
+//
+// The router works with its own idea of which vias are connected (the nets), and
+// the strip cuts are calculated from that in a separate step. This check ignores
+// all of that and rebuilds the electrical connectivity from what would actually
+// end up on the board:
+//
+// - Each strip connects all the vias in its column, except where it has been cut.
+// A cut at a via separates the via from the one above it.
+// - Each wire connects the vias at its two ends.
+//
+// The connectivity is then compared with the nets implied by the connections in
+// the circuit. Any component pin that is not a "Don't Care" pin is checked, also
+// the ones that are not used by any connections, since those must not end up
+// connected to anything either.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyIssue {
+    // The two pins of a connection are not electrically connected.
+    Open { start_pin: String, end_pin: String },
+    // Pins from different nets are electrically connected.
+    Short { pin_vec: Vec<String> },
+    // A pin that is not used by any connection shares a strip section with other
+    // pins.
+    SharedStrip { pin: String, other_pin_vec: Vec<String> },
+    // A connection uses a pin that is not checked, because it's a "Don't Care" pin or
+    // the component doesn't have it. The parser only checks the pins of a connection
+    // against the "Don't Care" lines that come before it.
+    UncheckedPin { pin: String },
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyIssue::Open { start_pin, end_pin } => write!(f, "Open: {} - {}", start_pin, end_pin),
+            VerifyIssue::Short { pin_vec } => write!(f, "Short: {}", pin_vec.join(" ")),
+            VerifyIssue::SharedStrip { pin, other_pin_vec } => {
+                write!(f, "Unused pin on shared strip: {} (with {})", pin, other_pin_vec.join(" "))
+            }
+            VerifyIssue::UncheckedPin { pin } => write!(f, "Connection to a Don't Care or missing pin: {}", pin),
+        }
+    }
+}
+
+pub struct VerifyReport {
+    pub issue_vec: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.issue_vec.is_empty()
+    }
+
+    pub fn n_opens(&self) -> usize {
+        self.count(|issue| matches!(issue, VerifyIssue::Open { .. }))
+    }
+
+    pub fn n_shorts(&self) -> usize {
+        self.count(|issue| matches!(issue, VerifyIssue::Short { .. }))
+    }
+
+    pub fn n_shared_strips(&self) -> usize {
+        self.count(|issue| matches!(issue, VerifyIssue::SharedStrip { .. }))
+    }
+
+    fn count(&self, f: impl Fn(&VerifyIssue) -> bool) -> usize {
+        self.issue_vec.iter().filter(|issue| f(issue)).count()
+    }
+}
+
+pub fn verify_layout(layout: &Layout) -> VerifyReport {
+    let board = layout.board;
+    let circuit = &layout.circuit;
+    let mut issue_vec = Vec::new();
+
+    // Electrical connectivity of the vias
+    let mut copper = UnionFind::new(board.size());
+    let cut_set: HashSet<Via> = layout.strip_cut_vec.iter().copied().collect();
+    for x in 0..board.w {
+        for y in 1..board.h {
+            let via = Via::new(x, y);
+            if !cut_set.contains(&via) {
+                copper.union(board.idx(Via::new(x, y - 1)), board.idx(via));
+            }
+        }
+    }
+    for route_section_vec in &layout.route_vec {
+        for section in route_section_vec {
+            if section.start.is_wire_layer {
+                copper.union(board.idx(section.start.via), board.idx(section.end.via));
+            }
+        }
+    }
+
+    // The active pins, sorted by name so that the report is stable.
+    let mut pin_via_map = BTreeMap::new();
+    for (component_name, component) in &circuit.component_name_to_component_map {
+        for (pin_idx, via) in circuit.calc_component_pins(component_name).into_iter().enumerate() {
+            if !component.dont_care_pin_idx_set.contains(&pin_idx) {
+                pin_via_map.insert(pin_name(component_name, pin_idx), via);
+            }
+        }
+    }
+    let pin_name_vec: Vec<&String> = pin_via_map.keys().collect();
+    let pin_idx_map: HashMap<&String, usize> = pin_name_vec.iter().enumerate().map(|(i, &n)| (n, i)).collect();
+
+    // Intended nets, from the connections
+    let mut intended = UnionFind::new(pin_name_vec.len());
+    let mut is_used_pin_vec = vec![false; pin_name_vec.len()];
+    for connection in &circuit.connection_vec {
+        let start_pin = pin_name(&connection.start.component_name, connection.start.pin_idx);
+        let end_pin = pin_name(&connection.end.component_name, connection.end.pin_idx);
+        let (Some(&start_idx), Some(&end_idx)) = (pin_idx_map.get(&start_pin), pin_idx_map.get(&end_pin)) else {
+            for pin in [start_pin, end_pin] {
+                if !pin_idx_map.contains_key(&pin) {
+                    issue_vec.push(VerifyIssue::UncheckedPin { pin });
+                }
+            }
+            continue;
+        };
+        intended.union(start_idx, end_idx);
+        is_used_pin_vec[start_idx] = true;
+        is_used_pin_vec[end_idx] = true;
+        if copper.find(board.idx(pin_via_map[pin_name_vec[start_idx]]))
+            != copper.find(board.idx(pin_via_map[pin_name_vec[end_idx]]))
+        {
+            issue_vec.push(VerifyIssue::Open { start_pin, end_pin });
+        }
+    }
+
+    // Group the pins by the copper they're on, and check that each group holds a
+    // single net.
+    let mut copper_group_map: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (pin_idx, pin) in pin_name_vec.iter().enumerate() {
+        let root = copper.find(board.idx(pin_via_map[*pin]));
+        copper_group_map.entry(root).or_default().push(pin_idx);
+    }
+    for pin_idx_vec in copper_group_map.values() {
+        if pin_idx_vec.len() < 2 {
+            continue;
+        }
+        let names = |idx_vec: &mut dyn Iterator<Item = &usize>| idx_vec.map(|&i| pin_name_vec[i].clone()).collect();
+        for &pin_idx in pin_idx_vec.iter().filter(|&&i| !is_used_pin_vec[i]) {
+            issue_vec.push(VerifyIssue::SharedStrip {
+                pin: pin_name_vec[pin_idx].clone(),
+                other_pin_vec: names(&mut pin_idx_vec.iter().filter(|&&i| i != pin_idx)),
+            });
+        }
+        let used_pin_idx_vec: Vec<usize> = pin_idx_vec.iter().copied().filter(|&i| is_used_pin_vec[i]).collect();
+        let net_set: HashSet<usize> = used_pin_idx_vec.iter().map(|&i| intended.find(i)).collect();
+        if net_set.len() > 1 {
+            issue_vec.push(VerifyIssue::Short {
+                pin_vec: names(&mut used_pin_idx_vec.iter()),
+            });
+        }
+    }
+
+    VerifyReport { issue_vec }
+}
+
+// Pin numbers are 1 based in the .circuit file.
+fn pin_name(component_name: &str, pin_idx: usize) -> String {
+    format!("{}.{}", component_name, pin_idx + 1)
+}
+
+struct UnionFind {
+    parent_vec: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parent_vec: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, i: usize) -> usize {
+        let mut root = i;
+        while self.parent_vec[root] != root {
+            root = self.parent_vec[root];
+        }
+        let mut j = i;
+        while self.parent_vec[j] != root {
+            let next = self.parent_vec[j];
+            self.parent_vec[j] = root;
+            j = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let root_a = self.find(a);
+        let root_b = self.find(b);
+        self.parent_vec[root_b] = root_a;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit_parser::CircuitFileParser;
+    use crate::nets::Nets;
+    use crate::router::Router;
+    use std::path::Path;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    fn routed_example_layout() -> Layout {
+        let mut layout = Layout::new();
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("circuits/example.circuit");
+        CircuitFileParser::new(&mut layout).parse(path.as_os_str());
+        let board = layout.board;
+        let mut router = Router::new(board);
+        let mut nets = Nets::new(board);
+        let ordering = (0..layout.circuit.connection_vec.len()).collect();
+        let mut limit_routes = Arc::new(AtomicUsize::new(usize::MAX));
+        router.route(board, &mut layout, &mut nets, ordering, &mut limit_routes);
+        layout
+    }
+
+    #[test]
+    fn routed_layout_has_opens_only_for_failed_routes() {
+        let layout = routed_example_layout();
+        let report = verify_layout(&layout);
+        assert_eq!(report.n_opens(), layout.n_failed_routes);
+        assert_eq!(report.n_shorts(), 0);
+        assert_eq!(report.n_shared_strips(), 0);
+    }
+
+    #[test]
+    fn missing_strip_cuts_are_reported_as_shorts() {
+        let mut layout = routed_example_layout();
+        layout.strip_cut_vec.clear();
+        let report = verify_layout(&layout);
+        assert!(report.n_shorts() > 0);
+        assert!(!report.is_ok());
+    }
+
+    // The "Don't Care" line for b.2 comes after the connection that uses it, so the
+    // parser accepts the connection.
+    #[test]
+    fn connection_to_dont_care_pin_is_reported() {
+        let path = std::env::temp_dir().join(format!("striprouter_verify_{}.circuit", std::process::id()));
+        std::fs::write(&path, "board 10,10\npad2 0,0 1,0\na pad2 1,1\nb pad2 5,1\na.1 b.2\nb 2\n").unwrap();
+        let mut layout = Layout::new();
+        CircuitFileParser::new(&mut layout).parse(path.as_os_str());
+        std::fs::remove_file(&path).unwrap();
+        assert!(layout.circuit.parser_error_vec.is_empty());
+        let report = verify_layout(&layout);
+        assert_eq!(
+            report.issue_vec,
+            vec![VerifyIssue::UncheckedPin {
+                pin: "b.2".to_string()
+            }]
+        );
+    }
+}
@@ -216,7 +216,7 @@ impl LayerCostVia {
 // StartEndVia
 //
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct StartEndVia {
     pub start: Via,
     pub end: Via,